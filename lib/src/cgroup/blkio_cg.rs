@@ -13,6 +13,7 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::cgroup::normalized::IoMetrics;
 use crate::cgroup::utils;
 use crate::common;
 use crate::common::CGroupType;
@@ -76,7 +77,7 @@ impl BlkIOCGroup {
         }
     }
 
-    pub fn update(&mut self) {
+    pub fn update(&mut self) -> common::Result<()> {
         match self {
             BlkIOCGroup::V1(v1) => v1.update(),
             BlkIOCGroup::V2(v2) => v2.update(),
@@ -96,6 +97,43 @@ impl BlkIOCGroup {
             BlkIOCGroup::V2(v2) => v2.reset(),
         }
     }
+
+    pub fn normalize(&self) -> IoMetrics {
+        match self {
+            BlkIOCGroup::V1(v1) => v1.normalize(),
+            BlkIOCGroup::V2(v2) => v2.normalize(),
+        }
+    }
+}
+
+/// io throughput between the last two updates, with the same meaning on cgroup
+/// v1 and v2
+#[derive(Clone, Debug, Copy, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct IoThroughput {
+    pub read_bytes_per_sec: f64,
+    pub write_bytes_per_sec: f64,
+}
+
+impl IoThroughput {
+    /// none when no time elapsed or a counter went backwards, e.g. the cgroup was
+    /// recreated
+    pub(crate) fn between(
+        last_time: u64,
+        last: &IoMetrics,
+        now_time: u64,
+        now: &IoMetrics,
+    ) -> Option<IoThroughput> {
+        let secs = now_time.checked_sub(last_time)?;
+        let read_bytes = now.read_bytes.checked_sub(last.read_bytes)?;
+        let write_bytes = now.write_bytes.checked_sub(last.write_bytes)?;
+        if last_time == 0 || secs == 0 {
+            return None;
+        }
+        Some(IoThroughput {
+            read_bytes_per_sec: read_bytes as f64 / secs as f64,
+            write_bytes_per_sec: write_bytes as f64 / secs as f64,
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
@@ -110,6 +148,7 @@ pub struct BlkIOCGroupV2 {
     pub(crate) bpf_fs_data: WrapperFSData,
     pub(crate) old_bpf_fs_data: WrapperFSData,
     bpf_io_latency: WrapperIoLatpcts,
+    throughput: Option<IoThroughput>,
     update_time: u64,
 }
 
@@ -136,6 +175,21 @@ impl BlkIOCGroupV2 {
         self.full_path.as_path()
     }
 
+    pub fn normalize(&self) -> IoMetrics {
+        let mut metrics = IoMetrics {
+            throughput: self.throughput,
+            pressure: Some(self.io_pressure),
+            ..Default::default()
+        };
+        for stat in self.io_stat.values() {
+            metrics.read_bytes += stat.rbytes;
+            metrics.write_bytes += stat.wbytes;
+            metrics.read_ops += stat.rios;
+            metrics.write_ops += stat.wios;
+        }
+        metrics
+    }
+
     fn update_io_stat(&mut self) -> common::Result<bool> {
         let mut file = PathBuf::from(&self.full_path());
         file.push("io.stat");
@@ -241,8 +295,10 @@ impl BlkIOCGroupV2 {
         Ok(true)
     }
 
-    fn update(&mut self) {
-        if let Err(e) = self.update_io_stat() {
+    fn update(&mut self) -> common::Result<()> {
+        let (last_time, last) = (self.update_time, self.normalize());
+        let result = self.update_io_stat();
+        if let Err(e) = &result {
             warn!(
                 "[blkiocg] update io stat error: {}, path= {}",
                 e,
@@ -282,6 +338,9 @@ impl BlkIOCGroupV2 {
             );
         }
         self.update_time = get_secs_since_epoch();
+        self.throughput =
+            IoThroughput::between(last_time, &last, self.update_time, &self.normalize());
+        result.map(|_| ())
     }
 
     fn update_ebpf(&mut self) {
@@ -368,6 +427,7 @@ pub struct BlkIOCGroupV1 {
     pub(crate) bps_total: u64,
     pub(crate) bpf_fs_data: WrapperFSData,
     pub(crate) old_bpf_fs_data: WrapperFSData,
+    throughput: Option<IoThroughput>,
     update_time: u64,
 }
 
@@ -395,9 +455,28 @@ impl BlkIOCGroupV1 {
         self.user_path.as_path()
     }
 
+    pub fn normalize(&self) -> IoMetrics {
+        let sum = |details: &HashMap<String, HashMap<BlkOperationType, u64>>, t| {
+            details
+                .values()
+                .filter_map(|ops: &HashMap<BlkOperationType, u64>| ops.get(&t))
+                .sum::<u64>()
+        };
+        IoMetrics {
+            read_bytes: sum(&self.bps_details, BlkOperationType::Read),
+            write_bytes: sum(&self.bps_details, BlkOperationType::Write),
+            read_ops: sum(&self.iops_details, BlkOperationType::Read),
+            write_ops: sum(&self.iops_details, BlkOperationType::Write),
+            throughput: self.throughput,
+            pressure: None,
+        }
+    }
+
     #[cfg(not(tarpaulin_include))]
-    pub fn update(&mut self) {
-        if let Err(e) = self.update_bps() {
+    pub fn update(&mut self) -> common::Result<()> {
+        let (last_time, last) = (self.update_time, self.normalize());
+        let result = self.update_bps();
+        if let Err(e) = &result {
             warn!(
                 "[blkio_cg] update bps error: {}, path= {}",
                 e,
@@ -412,6 +491,9 @@ impl BlkIOCGroupV1 {
             );
         }
         self.update_time = get_secs_since_epoch();
+        self.throughput =
+            IoThroughput::between(last_time, &last, self.update_time, &self.normalize());
+        result.map(|_| ())
     }
 
     pub fn reset(&mut self) {
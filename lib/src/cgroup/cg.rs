@@ -14,18 +14,21 @@ limitations under the License.
 */
 
 use crate::cgroup::{
-    new_blkio_cgroup, new_cpu_cgroup, new_cpuset_cgroup, new_memory_cgroup, new_net_cgroup,
-    new_perf_event_cgroup, BlkIOCGroup, CpuCGroup, CpuSetCGroup, MemoryCGroup, NetCGroup,
-    PerfEventCGroup,
+    new_blkio_cgroup, new_cpu_cgroup, new_cpuset_cgroup, new_hugetlb_cgroup, new_memory_cgroup,
+    new_misc_cgroup, new_net_cgroup, new_perf_event_cgroup, new_pids_cgroup, new_rdma_cgroup,
+    BlkIOCGroup, CGroupMetrics, CpuCGroup, CpuSetCGroup, HugeTLBCGroup, MemoryCGroup, MiscCGroup,
+    NetCGroup, PerfEventCGroup, PidsCGroup, RdmaCGroup,
 };
 use crate::common;
 use crate::common::{CGroupType, MODULE_LIST};
 use crate::settings;
+use crate::system::{get_secs_since_epoch, DueSubSystems};
 use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use utoipa::ToSchema;
 
 const SUB_SYSTEM_MEM_STR: &str = "memory";
@@ -34,6 +37,10 @@ const SUB_SYSTEM_CPU_STR: &str = "cpuacct";
 const SUB_SYSTEM_BLK_IOSTR: &str = "blkio";
 const SUB_SYSTEM_NET_STR: &str = "net_cls";
 const SUB_SYSTEM_PERF_EVENT_STR: &str = "perf_event";
+const SUB_SYSTEM_PIDS_STR: &str = "pids";
+const SUB_SYSTEM_HUGETLB_STR: &str = "hugetlb";
+const SUB_SYSTEM_RDMA_STR: &str = "rdma";
+const SUB_SYSTEM_MISC_STR: &str = "misc";
 
 #[derive(Eq, PartialEq, Hash, Serialize, Deserialize, Clone, Debug, ToSchema)]
 pub enum SubSystemType {
@@ -43,6 +50,10 @@ pub enum SubSystemType {
     Blkio,
     NetCls,
     PerfEvent,
+    Pids,
+    Hugetlb,
+    Rdma,
+    Misc,
 }
 
 impl SubSystemType {
@@ -54,6 +65,10 @@ impl SubSystemType {
             SubSystemType::Blkio => settings::SubSystemType::Storage,
             SubSystemType::NetCls => settings::SubSystemType::Net,
             SubSystemType::PerfEvent => settings::SubSystemType::Unknow,
+            SubSystemType::Pids => settings::SubSystemType::Pids,
+            SubSystemType::Hugetlb => settings::SubSystemType::Hugetlb,
+            SubSystemType::Rdma => settings::SubSystemType::Rdma,
+            SubSystemType::Misc => settings::SubSystemType::Misc,
         }
     }
 }
@@ -67,6 +82,31 @@ impl fmt::Display for SubSystemType {
             SubSystemType::Blkio => write!(f, "{}", SUB_SYSTEM_BLK_IOSTR),
             SubSystemType::NetCls => write!(f, "{}", SUB_SYSTEM_NET_STR),
             SubSystemType::PerfEvent => write!(f, "{}", SUB_SYSTEM_PERF_EVENT_STR),
+            SubSystemType::Pids => write!(f, "{}", SUB_SYSTEM_PIDS_STR),
+            SubSystemType::Hugetlb => write!(f, "{}", SUB_SYSTEM_HUGETLB_STR),
+            SubSystemType::Rdma => write!(f, "{}", SUB_SYSTEM_RDMA_STR),
+            SubSystemType::Misc => write!(f, "{}", SUB_SYSTEM_MISC_STR),
+        }
+    }
+}
+
+impl FromStr for SubSystemType {
+    type Err = String;
+
+    /// the controller name, e.g. `memory` or `net_cls`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            SUB_SYSTEM_MEM_STR => Ok(SubSystemType::Memory),
+            SUB_SYSTEM_CPU_SET_STR => Ok(SubSystemType::Cpuset),
+            SUB_SYSTEM_CPU_STR => Ok(SubSystemType::Cpuacct),
+            SUB_SYSTEM_BLK_IOSTR => Ok(SubSystemType::Blkio),
+            SUB_SYSTEM_NET_STR => Ok(SubSystemType::NetCls),
+            SUB_SYSTEM_PERF_EVENT_STR => Ok(SubSystemType::PerfEvent),
+            SUB_SYSTEM_PIDS_STR => Ok(SubSystemType::Pids),
+            SUB_SYSTEM_HUGETLB_STR => Ok(SubSystemType::Hugetlb),
+            SUB_SYSTEM_RDMA_STR => Ok(SubSystemType::Rdma),
+            SUB_SYSTEM_MISC_STR => Ok(SubSystemType::Misc),
+            _ => Err(format!("unknown sub system {}", s)),
         }
     }
 }
@@ -83,11 +123,24 @@ pub struct CGroup {
     pub sub_system_groups: HashMap<SubSystemType, SubSystem>,
     // cgroup v1 or v2
     cgroup_type: CGroupType,
+    // last update time
+    update_time: u64,
+    /// failures of the last update, the sub systems which failed keep their previous values
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
 }
 
 impl CGroup {
+    /// a cgroup of the host's cgroup version
     pub fn new(mount_point: &str, user_path: PathBuf) -> CGroup {
-        let cgroup_type: CGroupType = MODULE_LIST.cgroup_type.actual_status();
+        CGroup::with_type(
+            mount_point,
+            user_path,
+            MODULE_LIST.cgroup_type.actual_status(),
+        )
+    }
+
+    pub fn with_type(mount_point: &str, user_path: PathBuf, cgroup_type: CGroupType) -> CGroup {
         let sub_system_groups: HashMap<SubSystemType, SubSystem> = vec![
             SubSystem::Memory(new_memory_cgroup(mount_point, &user_path, cgroup_type)),
             SubSystem::Cpu(new_cpu_cgroup(mount_point, &user_path, cgroup_type)),
@@ -95,6 +148,10 @@ impl CGroup {
             SubSystem::BlkIO(new_blkio_cgroup(mount_point, &user_path, cgroup_type)),
             SubSystem::Net(new_net_cgroup(mount_point, &user_path, cgroup_type)),
             SubSystem::PerfEvent(new_perf_event_cgroup(mount_point, &user_path, cgroup_type)),
+            SubSystem::Pids(new_pids_cgroup(mount_point, &user_path, cgroup_type)),
+            SubSystem::HugeTLB(new_hugetlb_cgroup(mount_point, &user_path, cgroup_type)),
+            SubSystem::Rdma(new_rdma_cgroup(mount_point, &user_path, cgroup_type)),
+            SubSystem::Misc(new_misc_cgroup(mount_point, &user_path, cgroup_type)),
         ]
         .into_iter()
         .map(|sub_system| (sub_system.to_type(), sub_system))
@@ -105,6 +162,8 @@ impl CGroup {
             user_path,
             sub_system_groups,
             cgroup_type,
+            update_time: 0,
+            last_error: None,
         }
     }
 
@@ -115,6 +174,46 @@ impl CGroup {
     pub fn mount_point(&self) -> &Path {
         self.mount_point.as_path()
     }
+
+    pub fn update_time(&self) -> u64 {
+        self.update_time
+    }
+
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    /// keep the NUMA stats of the given nodes only
+    pub fn retain_numa_nodes(&mut self, nodes: &[usize]) {
+        if let Some(SubSystem::Memory(memory)) =
+            self.sub_system_groups.get_mut(&SubSystemType::Memory)
+        {
+            memory.retain_numa_nodes(nodes);
+        }
+    }
+
+    /// the same metrics whichever cgroup version backs this cgroup
+    pub fn normalize(&self) -> CGroupMetrics {
+        let mut metrics = CGroupMetrics {
+            user_path: self.user_path.clone(),
+            cgroup_type: self.cgroup_type,
+            cpu: None,
+            memory: None,
+            io: None,
+            update_time: self.update_time,
+            last_error: self.last_error.clone(),
+        };
+        for sub_system in self.sub_system_groups.values() {
+            match sub_system {
+                SubSystem::Cpu(cpu) => metrics.cpu = Some(cpu.normalize()),
+                SubSystem::Memory(memory) => metrics.memory = Some(memory.normalize()),
+                SubSystem::BlkIO(io) => metrics.io = Some(io.normalize()),
+                _ => {}
+            }
+        }
+        metrics
+    }
+
     pub fn is_valid(&self) -> bool {
         !self.sub_system_groups.is_empty()
     }
@@ -124,9 +223,16 @@ impl CGroup {
             .retain(|_, subsystem| subsystem.sub_system_path_exists());
     }
 
-    pub fn update(&mut self, ds_settings: &dyn settings::DataSource) -> common::Result<bool> {
+    /// refresh the sub systems which are enabled and due; a sub system fails when
+    /// its main stat file cannot be read, the other files only log a warning
+    pub fn update(
+        &mut self,
+        ds_settings: &dyn settings::DataSource,
+        due: &DueSubSystems,
+    ) -> common::Result<bool> {
         // delete invalid subsystem info
         self.clear_invalid_subsystem_item();
+        let mut failed = vec![];
         if self.is_valid() && ds_settings.is_enable() {
             // update valid subsystem item
             for (_sub_system_type, sub_system) in self.sub_system_groups.iter_mut() {
@@ -138,16 +244,31 @@ impl CGroup {
                         );
                         continue;
                     }
+                    if !due.contains(_sub_system_type.to_settings()) {
+                        continue;
+                    }
+                }
+                if let Err(e) = sub_system.update() {
+                    failed.push(format!("{}: {}", _sub_system_type, e));
                 }
-                sub_system.update();
             }
+            self.update_time = get_secs_since_epoch();
         } else {
             warn!("cgroup is invalid");
         }
-        Ok(true)
+        self.last_error = (!failed.is_empty()).then(|| failed.join("; "));
+        match &self.last_error {
+            Some(e) => Err(e.clone().into()),
+            None => Ok(true),
+        }
     }
 
-    pub fn update_ebpf(&mut self, ds_settings: &dyn settings::DataSource) -> common::Result<bool> {
+    /// refresh the sub systems which are enabled and due
+    pub fn update_ebpf(
+        &mut self,
+        ds_settings: &dyn settings::DataSource,
+        due: &DueSubSystems,
+    ) -> common::Result<bool> {
         // delete invalid subsystem info
         self.clear_invalid_subsystem_item();
         if self.is_valid() && ds_settings.is_enable() {
@@ -161,6 +282,9 @@ impl CGroup {
                         );
                         continue;
                     }
+                    if !due.contains(_sub_system_type.to_settings()) {
+                        continue;
+                    }
                 }
                 sub_system.update_ebpf();
             }
@@ -204,6 +328,10 @@ pub enum SubSystem {
     BlkIO(BlkIOCGroup),
     Net(NetCGroup),
     PerfEvent(PerfEventCGroup),
+    Pids(PidsCGroup),
+    HugeTLB(HugeTLBCGroup),
+    Rdma(RdmaCGroup),
+    Misc(MiscCGroup),
 }
 
 impl SubSystem {
@@ -215,6 +343,10 @@ impl SubSystem {
             SubSystem::BlkIO(_) => SubSystemType::Blkio,
             SubSystem::Net(_) => SubSystemType::NetCls,
             SubSystem::PerfEvent(_) => SubSystemType::PerfEvent,
+            SubSystem::Pids(_) => SubSystemType::Pids,
+            SubSystem::HugeTLB(_) => SubSystemType::Hugetlb,
+            SubSystem::Rdma(_) => SubSystemType::Rdma,
+            SubSystem::Misc(_) => SubSystemType::Misc,
         }
     }
     pub const fn is_memory_cgroup(&self) -> bool {
@@ -238,6 +370,18 @@ impl SubSystem {
     pub const fn is_perf_event_cgroup(&self) -> bool {
         matches!(self, SubSystem::PerfEvent(_))
     }
+    pub const fn is_pids_cgroup(&self) -> bool {
+        matches!(self, SubSystem::Pids(_))
+    }
+    pub const fn is_hugetlb_cgroup(&self) -> bool {
+        matches!(self, SubSystem::HugeTLB(_))
+    }
+    pub const fn is_rdma_cgroup(&self) -> bool {
+        matches!(self, SubSystem::Rdma(_))
+    }
+    pub const fn is_misc_cgroup(&self) -> bool {
+        matches!(self, SubSystem::Misc(_))
+    }
     pub fn get_full_path(&self) -> &Path {
         match self {
             SubSystem::Memory(x) => x.full_path(),
@@ -246,11 +390,15 @@ impl SubSystem {
             SubSystem::BlkIO(x) => x.full_path(),
             SubSystem::Net(x) => x.full_path(),
             SubSystem::PerfEvent(x) => x.full_path(),
+            SubSystem::Pids(x) => x.full_path(),
+            SubSystem::HugeTLB(x) => x.full_path(),
+            SubSystem::Rdma(x) => x.full_path(),
+            SubSystem::Misc(x) => x.full_path(),
         }
     }
 
     #[cfg(not(tarpaulin_include))]
-    pub fn update(&mut self) {
+    pub fn update(&mut self) -> common::Result<()> {
         match self {
             SubSystem::Memory(ref mut x) => x.update(),
             SubSystem::Cpu(ref mut x) => x.update(),
@@ -258,6 +406,10 @@ impl SubSystem {
             SubSystem::BlkIO(ref mut x) => x.update(),
             SubSystem::Net(ref mut x) => x.update(),
             SubSystem::PerfEvent(ref mut x) => x.update(),
+            SubSystem::Pids(ref mut x) => x.update(),
+            SubSystem::HugeTLB(ref mut x) => x.update(),
+            SubSystem::Rdma(ref mut x) => x.update(),
+            SubSystem::Misc(ref mut x) => x.update(),
         }
     }
 
@@ -269,6 +421,10 @@ impl SubSystem {
             SubSystem::BlkIO(ref mut x) => x.update_ebpf(),
             SubSystem::Net(ref mut x) => x.update_ebpf(),
             SubSystem::PerfEvent(ref mut x) => x.update_ebpf(),
+            SubSystem::Pids(ref mut x) => x.update_ebpf(),
+            SubSystem::HugeTLB(ref mut x) => x.update_ebpf(),
+            SubSystem::Rdma(ref mut x) => x.update_ebpf(),
+            SubSystem::Misc(ref mut x) => x.update_ebpf(),
         }
     }
 
@@ -281,11 +437,23 @@ impl SubSystem {
             SubSystem::BlkIO(ref mut x) => x.reset(),
             SubSystem::Net(ref mut x) => x.reset(),
             SubSystem::PerfEvent(ref mut x) => x.reset(),
+            SubSystem::Pids(ref mut x) => x.reset(),
+            SubSystem::HugeTLB(ref mut x) => x.reset(),
+            SubSystem::Rdma(ref mut x) => x.reset(),
+            SubSystem::Misc(ref mut x) => x.reset(),
         }
     }
 
+    /// on cgroup v2 every controller shares one directory, so the newer
+    /// controllers look for their own interface files instead
     pub fn sub_system_path_exists(&self) -> bool {
-        self.get_full_path().exists()
+        match self {
+            SubSystem::Pids(x) => x.exists(),
+            SubSystem::HugeTLB(x) => x.exists(),
+            SubSystem::Rdma(x) => x.exists(),
+            SubSystem::Misc(x) => x.exists(),
+            _ => self.get_full_path().exists(),
+        }
     }
 
     #[cfg(not(tarpaulin_include))]
@@ -13,6 +13,7 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::cgroup::normalized::CpuMetrics;
 use crate::cgroup::utils;
 use crate::common;
 use crate::common::CGroupType;
@@ -22,7 +23,7 @@ use crate::ffi::{
     BPF_MODULE_CGROUP_PMU, BPF_MODULE_CGROUP_SCHED,
 };
 use crate::psi::PressureStallInfo;
-use crate::system::{get_secs_since_epoch, LoadAvg};
+use crate::system::{get_naosecs_since_epoch, get_secs_since_epoch, LoadAvg};
 use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Number, Value};
@@ -48,7 +49,7 @@ impl CpuSetCGroup {
         }
     }
 
-    pub fn update(&mut self) {
+    pub fn update(&mut self) -> common::Result<()> {
         match self {
             CpuSetCGroup::V1(v1) => v1.update(),
             CpuSetCGroup::V2(v2) => v2.update(),
@@ -137,14 +138,16 @@ impl CpuSetCGroupV2 {
         }
     }
 
-    pub fn update(&mut self) {
-        if let Err(e) = self.update_cpus() {
+    pub fn update(&mut self) -> common::Result<()> {
+        let result = self.update_cpus();
+        if let Err(e) = &result {
             warn!("[cpusetcg] update cpus error: {}", e);
         }
         if let Err(e) = self.update_mems() {
             warn!("[cpusetcg] update mems error: {}", e);
         }
-        self.update_time = get_secs_since_epoch()
+        self.update_time = get_secs_since_epoch();
+        result.map(|_| ())
     }
 
     pub fn reset(&mut self) {
@@ -200,8 +203,9 @@ impl CpuSetCGroupV1 {
         }
     }
 
-    pub fn update(&mut self) {
-        if let Err(e) = self.update_cpus() {
+    pub fn update(&mut self) -> common::Result<()> {
+        let result = self.update_cpus();
+        if let Err(e) = &result {
             warn!(
                 "[cpusetcg] update cpus error: {}, path= {:?}",
                 e, self.full_path
@@ -213,7 +217,8 @@ impl CpuSetCGroupV1 {
                 e, self.full_path
             );
         }
-        self.update_time = get_secs_since_epoch()
+        self.update_time = get_secs_since_epoch();
+        result.map(|_| ())
     }
     pub fn reset(&mut self) {
         *self = Self {
@@ -267,6 +272,77 @@ impl Sub for CpuCGroupBasicInfo {
     }
 }
 
+/// cumulative cfs counters of one update, in nanoseconds
+#[derive(Clone, Debug, Copy, Default, Eq, PartialEq)]
+pub(crate) struct CpuThrottleSample {
+    usage_ns: u64,
+    nr_periods: u64,
+    nr_throttled: u64,
+    throttled_ns: u64,
+    /// nanoseconds since epoch, updates may be less than a second apart
+    sample_time_ns: u64,
+}
+
+/// cfs bandwidth control between the last two updates, with the same meaning on
+/// cgroup v1 and v2
+#[derive(Clone, Debug, Copy, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct CpuThrottling {
+    /// share of the elapsed periods in which the cgroup was throttled
+    throttled_periods_ratio: Option<f64>,
+    /// nanoseconds throttled per second
+    throttled_ns_per_sec: Option<f64>,
+    /// usage per elapsed period relative to the quota, none when unlimited
+    quota_utilization: Option<f64>,
+}
+
+impl CpuThrottling {
+    /// none when no time elapsed or a counter went backwards, e.g. the cgroup was
+    /// recreated
+    pub(crate) fn between(
+        last: &CpuThrottleSample,
+        now: &CpuThrottleSample,
+        quota_ns: Option<u64>,
+    ) -> Option<CpuThrottling> {
+        let elapsed_ns = now.sample_time_ns.checked_sub(last.sample_time_ns)?;
+        let usage_ns = now.usage_ns.checked_sub(last.usage_ns)?;
+        let periods = now.nr_periods.checked_sub(last.nr_periods)?;
+        let throttled = now.nr_throttled.checked_sub(last.nr_throttled)?;
+        let throttled_ns = now.throttled_ns.checked_sub(last.throttled_ns)?;
+        if elapsed_ns == 0 {
+            return None;
+        }
+
+        let throttled_periods_ratio = if periods > 0 {
+            Some(throttled as f64 / periods as f64)
+        } else {
+            None
+        };
+        let quota_utilization = match quota_ns {
+            Some(quota_ns) if quota_ns > 0 && periods > 0 => {
+                Some(usage_ns as f64 / (periods as f64 * quota_ns as f64))
+            }
+            _ => None,
+        };
+        Some(CpuThrottling {
+            throttled_periods_ratio,
+            throttled_ns_per_sec: Some(throttled_ns as f64 * 1e9 / elapsed_ns as f64),
+            quota_utilization,
+        })
+    }
+
+    pub fn throttled_periods_ratio(&self) -> Option<f64> {
+        self.throttled_periods_ratio
+    }
+
+    pub fn throttled_ns_per_sec(&self) -> Option<f64> {
+        self.throttled_ns_per_sec
+    }
+
+    pub fn quota_utilization(&self) -> Option<f64> {
+        self.quota_utilization
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub enum CpuCGroup {
     V1(CpuCGroupV1),
@@ -281,7 +357,7 @@ impl CpuCGroup {
         }
     }
 
-    pub fn update(&mut self) {
+    pub fn update(&mut self) -> common::Result<()> {
         match self {
             CpuCGroup::V1(v1) => v1.update(),
             CpuCGroup::V2(v2) => v2.update(),
@@ -301,6 +377,13 @@ impl CpuCGroup {
             CpuCGroup::V2(v2) => v2.reset(),
         }
     }
+
+    pub fn normalize(&self) -> CpuMetrics {
+        match self {
+            CpuCGroup::V1(v1) => v1.normalize(),
+            CpuCGroup::V2(v2) => v2.normalize(),
+        }
+    }
 }
 
 pub fn new_cpu_cgroup(mount_point: &str, user_path: &Path, cgroup_type: CGroupType) -> CpuCGroup {
@@ -340,6 +423,9 @@ pub struct CpuCGroupV2 {
     cpu_usage_ratio: f32,
     cpu_user_usage_ratio: f32,
     cpu_sys_usage_ratio: f32,
+    throttling: Option<CpuThrottling>,
+    #[serde(skip)]
+    throttle_sample: Option<CpuThrottleSample>,
     update_time: u64,
     cycles: u64,
     instructions: u64,
@@ -357,6 +443,9 @@ pub struct CpuStatsV2 {
     nr_periods: u64,
     nr_throttled: u64,
     throttled_usec: u64,
+    /// with cpu.max.burst, kernel 5.14+
+    nr_bursts: Option<u64>,
+    burst_usec: Option<u64>,
     update_time: u64, // unit: s
 }
 
@@ -461,6 +550,50 @@ impl CpuCGroupV2 {
         Ok(true)
     }
 
+    fn quota_ns(&self) -> Option<u64> {
+        match self.max {
+            Some(max) if max != u64::MAX => Some(max * 1000),
+            _ => None,
+        }
+    }
+
+    fn update_cpu_throttling(&mut self) {
+        let sample = CpuThrottleSample {
+            usage_ns: self.cpu_stats.usage_usec * 1000,
+            nr_periods: self.cpu_stats.nr_periods,
+            nr_throttled: self.cpu_stats.nr_throttled,
+            throttled_ns: self.cpu_stats.throttled_usec * 1000,
+            sample_time_ns: get_naosecs_since_epoch() as u64,
+        };
+        if let Some(last) = &self.throttle_sample {
+            self.throttling = CpuThrottling::between(last, &sample, self.quota_ns());
+        }
+        self.throttle_sample = Some(sample);
+    }
+
+    pub fn normalize(&self) -> CpuMetrics {
+        let limit_cores = match (self.max, self.max_period) {
+            (Some(max), Some(period)) if max != u64::MAX && period > 0 => {
+                Some(max as f64 / period as f64)
+            }
+            _ => None,
+        };
+        CpuMetrics {
+            usage_ns: self.cpu_stats.usage_usec * 1000,
+            user_ns: self.cpu_stats.user_usec * 1000,
+            sys_ns: self.cpu_stats.system_usec * 1000,
+            usage_ratio: self.cpu_usage_ratio,
+            limit_cores,
+            nr_periods: Some(self.cpu_stats.nr_periods),
+            nr_throttled: Some(self.cpu_stats.nr_throttled),
+            throttled_ns: Some(self.cpu_stats.throttled_usec * 1000),
+            nr_bursts: self.cpu_stats.nr_bursts,
+            burst_ns: self.cpu_stats.burst_usec.map(|usec| usec * 1000),
+            throttling: self.throttling,
+            pressure: Some(self.cpu_pressure),
+        }
+    }
+
     fn update_cpu_pressure(&mut self) -> common::Result<bool> {
         let mut file = PathBuf::from(&self.full_path());
         file.push("cpu.pressure");
@@ -485,8 +618,9 @@ impl CpuCGroupV2 {
         Ok(true)
     }
 
-    pub fn update(&mut self) {
-        if let Err(e) = self.update_cpu_stat() {
+    pub fn update(&mut self) -> common::Result<()> {
+        let result = self.update_cpu_stat();
+        if let Err(e) = &result {
             warn!(
                 "[cpucg] update cpu stat error: {}, path= {}",
                 e,
@@ -507,6 +641,10 @@ impl CpuCGroupV2 {
                 self.full_path.display()
             );
         }
+        // stale counters of a failed read would show no throttling at all
+        if result.is_ok() {
+            self.update_cpu_throttling();
+        }
         if let Err(e) = self.update_cpu_pressure() {
             warn!(
                 "[cpucg] update cpu pressure error: {}, path= {}",
@@ -514,6 +652,7 @@ impl CpuCGroupV2 {
                 self.full_path.display()
             );
         }
+        result.map(|_| ())
     }
 
     pub fn update_ebpf(&mut self) {
@@ -584,6 +723,12 @@ pub struct CpuCGroupV1 {
     cpu_nr_throttled: Option<u64>,
     cpu_nr_periods: Option<u64>,
     cpu_throttled_time: Option<u64>,
+    /// with cpu.cfs_burst_us, kernel 5.14+
+    cpu_nr_bursts: Option<u64>,
+    cpu_burst_time: Option<u64>,
+    throttling: Option<CpuThrottling>,
+    #[serde(skip)]
+    throttle_sample: Option<CpuThrottleSample>,
 
     load: Option<LoadAvg>,
     update_time: u64,
@@ -601,6 +746,28 @@ impl CpuCGroupV1 {
         self.full_path.as_path()
     }
 
+    pub fn normalize(&self) -> CpuMetrics {
+        let limit_cores = if self.cfs_quota_us > 0 && self.cfs_period_us > 0 {
+            Some(self.cfs_quota_us as f64 / self.cfs_period_us as f64)
+        } else {
+            None
+        };
+        CpuMetrics {
+            usage_ns: self.new_cpu_basic_info.cpu_usage,
+            user_ns: self.new_cpu_basic_info.cpu_user_time,
+            sys_ns: self.new_cpu_basic_info.cpu_sys_time,
+            usage_ratio: self.cpu_usage_ratio,
+            limit_cores,
+            nr_periods: self.cpu_nr_periods,
+            nr_throttled: self.cpu_nr_throttled,
+            throttled_ns: self.cpu_throttled_time,
+            nr_bursts: self.cpu_nr_bursts,
+            burst_ns: self.cpu_burst_time,
+            throttling: self.throttling,
+            pressure: None,
+        }
+    }
+
     pub fn update_cpu_stat(&mut self) -> common::Result<bool> {
         let mut file = PathBuf::from(&self.full_path);
         file.push("cpu.stat");
@@ -609,9 +776,40 @@ impl CpuCGroupV1 {
         self.cpu_nr_throttled = data.get(&"nr_throttled").cloned();
         self.cpu_throttled_time = data.get(&"throttled_time").cloned();
         self.cpu_nr_periods = data.get(&"nr_periods").cloned();
+        self.cpu_nr_bursts = data.get(&"nr_bursts").cloned();
+        self.cpu_burst_time = data.get(&"burst_time").cloned();
         Ok(true)
     }
 
+    fn update_cpu_throttling(&mut self) {
+        let (nr_periods, nr_throttled, throttled_ns) = match (
+            self.cpu_nr_periods,
+            self.cpu_nr_throttled,
+            self.cpu_throttled_time,
+        ) {
+            (Some(nr_periods), Some(nr_throttled), Some(throttled_ns)) => {
+                (nr_periods, nr_throttled, throttled_ns)
+            }
+            _ => return,
+        };
+        let sample = CpuThrottleSample {
+            usage_ns: self.new_cpu_basic_info.cpu_usage,
+            nr_periods,
+            nr_throttled,
+            throttled_ns,
+            sample_time_ns: get_naosecs_since_epoch() as u64,
+        };
+        // a negative quota means unlimited
+        let quota_ns = u64::try_from(self.cfs_quota_us)
+            .ok()
+            .filter(|quota| *quota > 0)
+            .map(|quota| quota * 1000);
+        if let Some(last) = &self.throttle_sample {
+            self.throttling = CpuThrottling::between(last, &sample, quota_ns);
+        }
+        self.throttle_sample = Some(sample);
+    }
+
     fn get_cpu_usage(&self) -> u64 {
         let mut path = PathBuf::from(&self.full_path);
         path.push("cpuacct.usage");
@@ -733,7 +931,7 @@ impl CpuCGroupV1 {
         Ok(true)
     }
 
-    pub fn update(&mut self) {
+    pub fn update(&mut self) -> common::Result<()> {
         if let Err(e) = self.update_period_us() {
             warn!(
                 "[cpucg] update cpu period us error: {}, path= {}",
@@ -755,13 +953,18 @@ impl CpuCGroupV1 {
                 self.full_path.display()
             );
         }
-        if let Err(e) = self.update_cpu_stat() {
+        let result = self.update_cpu_stat();
+        if let Err(e) = &result {
             warn!(
                 "[cpucg] update cpu stat error: {}, path= {}",
                 e,
                 self.full_path.display()
             );
         }
+        // stale counters of a failed read would show no throttling at all
+        if result.is_ok() {
+            self.update_cpu_throttling();
+        }
         if let Err(e) = self.update_cpu_shares() {
             warn!(
                 "[cpucg] update cpu shares error: {}, path= {}",
@@ -776,7 +979,8 @@ impl CpuCGroupV1 {
                 self.full_path.display()
             );
         }
-        self.update_time = get_secs_since_epoch()
+        self.update_time = get_secs_since_epoch();
+        result.map(|_| ())
     }
 
     pub fn update_ebpf(&mut self) {
@@ -827,7 +1031,7 @@ mod tests_cpu_cg {
         let user_path: PathBuf = PathBuf::from("pod_user_path");
 
         let mut cg = new_cpuset_cgroup(&mount_point, &user_path, CGroupType::V1 {});
-        cg.update();
+        cg.update().unwrap();
 
         let correct_full_path = PathBuf::from(format!("{}/cpuset/pod_user_path", mount_point));
 
@@ -871,7 +1075,7 @@ mod tests_cpu_cg {
         let user_path: PathBuf = PathBuf::from("tests/sample/cgroupv2");
 
         let mut cg = new_cpuset_cgroup(&mount_point, &user_path, CGroupType::V2 {});
-        cg.update();
+        cg.update().unwrap();
 
         let correct_full_path = PathBuf::from(format!("{}/tests/sample/cgroupv2", mount_point));
 
@@ -914,7 +1118,7 @@ mod tests_cpu_cg {
         let user_path: PathBuf = PathBuf::from("pod_user_path");
 
         let mut cg = new_cpu_cgroup(&mount_point, &user_path, CGroupType::V1 {});
-        cg.update();
+        cg.update().unwrap();
 
         let correct_full_path = PathBuf::from(format!("{}/cpuacct/pod_user_path", mount_point));
 
@@ -934,8 +1138,10 @@ mod tests_cpu_cg {
             assert_eq!(cpu_cg.cpu_nr_periods, Some(422162 as u64));
             assert_eq!(cpu_cg.cpu_nr_throttled, Some(2 as u64));
             assert_eq!(cpu_cg.cpu_throttled_time, Some(10 as u64));
+            assert_eq!(cpu_cg.cpu_nr_bursts, Some(1 as u64));
+            assert_eq!(cpu_cg.cpu_burst_time, Some(2000 as u64));
 
-            cpu_cg.update();
+            cpu_cg.update().unwrap();
             assert_eq!(
                 cpu_cg.new_cpu_basic_info.cpu_user_time,
                 cpu_cg.old_cpu_basic_info.cpu_user_time
@@ -960,6 +1166,46 @@ mod tests_cpu_cg {
         }
     }
 
+    #[test]
+    fn test_cpu_throttling() {
+        let last = CpuThrottleSample {
+            usage_ns: 1_000_000_000,
+            nr_periods: 100,
+            nr_throttled: 10,
+            throttled_ns: 500_000_000,
+            sample_time_ns: 100_000_000_000,
+        };
+        let now = CpuThrottleSample {
+            usage_ns: 9_000_000_000,
+            nr_periods: 200,
+            nr_throttled: 60,
+            throttled_ns: 2_500_000_000,
+            sample_time_ns: 110_000_000_000,
+        };
+
+        // 100 periods of 100ms with a quota of 1 core
+        let throttling = CpuThrottling::between(&last, &now, Some(100_000_000)).unwrap();
+        assert_eq!(throttling.throttled_periods_ratio(), Some(0.5));
+        assert_eq!(throttling.throttled_ns_per_sec(), Some(200_000_000.0));
+        assert_eq!(throttling.quota_utilization(), Some(0.8));
+
+        let throttling = CpuThrottling::between(&last, &now, None).unwrap();
+        assert_eq!(throttling.quota_utilization(), None);
+
+        // updates less than a second apart
+        let soon = CpuThrottleSample {
+            throttled_ns: 600_000_000,
+            sample_time_ns: 100_250_000_000,
+            ..last
+        };
+        let throttling = CpuThrottling::between(&last, &soon, None).unwrap();
+        assert_eq!(throttling.throttled_ns_per_sec(), Some(400_000_000.0));
+
+        // counters reset when the cgroup is recreated
+        assert_eq!(CpuThrottling::between(&now, &last, None), None);
+        assert_eq!(CpuThrottling::between(&last, &last, None), None);
+    }
+
     #[test]
     fn test_cpu_cg_v2() {
         let mount_point: String = env::current_dir()
@@ -970,7 +1216,7 @@ mod tests_cpu_cg {
         let user_path: PathBuf = PathBuf::from("tests/sample/cgroupv2");
 
         let mut cg = new_cpu_cgroup(&mount_point, &user_path, CGroupType::V2 {});
-        cg.update();
+        cg.update().unwrap();
 
         let correct_full_path = PathBuf::from(format!("{}/tests/sample/cgroupv2", mount_point));
 
@@ -982,6 +1228,10 @@ mod tests_cpu_cg {
             // cpu.stat
             assert_eq!(cpu_cg.cpu_stats.usage_usec, 6983392776);
             assert_eq!(cpu_cg.cpu_stats.system_usec, 2777685920);
+            assert_eq!(cpu_cg.cpu_stats.nr_bursts, Some(3));
+            assert_eq!(cpu_cg.cpu_stats.burst_usec, Some(1500));
+            // a single sample gives no interval
+            assert_eq!(cpu_cg.throttling, None);
 
             // cpu.cpu_pressure
             assert_eq!(cpu_cg.cpu_pressure.get_some().unwrap().get_avg10(), 1.23);
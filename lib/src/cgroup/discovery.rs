@@ -0,0 +1,224 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::cgroup::CGroupUserPath;
+use crate::common::CGroupType;
+use log::warn;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// match one path component against a pattern, `*` matches any sequence
+/// and `?` any single character
+pub fn match_component(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let name = name.chars().collect::<Vec<char>>();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            p = star_p + 1;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// user path with a leading `/` and without `.`, empty or trailing components, the
+/// form discovery reports, so that `kubepods/pod1/` and `/kubepods/pod1` are one cgroup
+pub fn normalize_user_path(user_path: &Path) -> CGroupUserPath {
+    let mut normalized = PathBuf::from("/");
+    normalized.extend(user_path.components().filter_map(|c| match c {
+        Component::Normal(name) => Some(name),
+        _ => None,
+    }));
+    normalized
+}
+
+/// whether a user path matches any of the discovery patterns
+pub fn match_user_path(patterns: &[String], user_path: &Path) -> bool {
+    let names = user_path
+        .iter()
+        .filter(|c| *c != "/")
+        .map(|c| c.to_string_lossy())
+        .collect::<Vec<_>>();
+    patterns.iter().any(|pattern| {
+        let components = pattern
+            .split('/')
+            .filter(|c| !c.is_empty())
+            .collect::<Vec<&str>>();
+        !components.is_empty()
+            && components.len() == names.len()
+            && components
+                .iter()
+                .zip(names.iter())
+                .all(|(p, n)| match_component(p, n))
+    })
+}
+
+fn discover_pattern(dir: &Path, user_path: &Path, components: &[&str], result: &mut Vec<PathBuf>) {
+    let (component, rest) = match components.split_first() {
+        Some(x) => x,
+        None => {
+            result.push(user_path.to_path_buf());
+            return;
+        }
+    };
+
+    if !component.contains(['*', '?']) {
+        let next = dir.join(component);
+        if next.is_dir() {
+            discover_pattern(&next, &user_path.join(component), rest, result);
+        }
+        return;
+    }
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("[discovery] read dir {} error: {}", dir.display(), e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        if !entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+            continue;
+        }
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if match_component(component, &name) {
+            discover_pattern(&entry.path(), &user_path.join(&*name), rest, result);
+        }
+    }
+}
+
+/// walk the cgroup hierarchy under mount_point and return the user paths
+/// matching any of the patterns, e.g. `kubepods/*/pod*`; on v1 only the memory
+/// hierarchy is walked, a cgroup missing from it is not discovered even when
+/// other controllers have it
+pub fn discover_cgroups(
+    mount_point: &Path,
+    cgroup_type: CGroupType,
+    patterns: &[String],
+) -> Vec<CGroupUserPath> {
+    // v1 controllers share the same hierarchy, walk the memory one
+    let hierarchy = match cgroup_type {
+        CGroupType::V1 => mount_point.join("memory"),
+        CGroupType::V2 => mount_point.to_path_buf(),
+    };
+
+    let mut result = vec![];
+    for pattern in patterns {
+        let components = pattern
+            .split('/')
+            .filter(|c| !c.is_empty())
+            .collect::<Vec<&str>>();
+        if components.is_empty() {
+            continue;
+        }
+        discover_pattern(&hierarchy, Path::new("/"), &components, &mut result);
+    }
+    result.sort();
+    result.dedup();
+    result
+}
+
+#[cfg(test)]
+mod tests_discovery {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_match_component() {
+        assert!(match_component("pod*", "pod1234"));
+        assert!(match_component("pod*", "pod"));
+        assert!(match_component("*.service", "sshd.service"));
+        assert!(!match_component("*.service", "sshd.socket"));
+        assert!(match_component("b?rstable", "burstable"));
+        assert!(match_component("*a*e", "burstable"));
+        assert!(!match_component("pod*", "besteffort"));
+        assert!(match_component("kubepods", "kubepods"));
+    }
+
+    #[test]
+    fn test_normalize_user_path() {
+        let expected = PathBuf::from("/kubepods/burstable/pod1");
+        for user_path in [
+            "/kubepods/burstable/pod1",
+            "kubepods/burstable/pod1",
+            "kubepods/burstable/pod1/",
+            "//kubepods/./burstable//pod1",
+        ] {
+            assert_eq!(normalize_user_path(Path::new(user_path)), expected);
+        }
+        assert_eq!(normalize_user_path(Path::new("")), PathBuf::from("/"));
+    }
+
+    #[test]
+    fn test_match_user_path() {
+        let patterns = vec![String::from("kubepods/*/pod*")];
+        assert!(match_user_path(
+            &patterns,
+            Path::new("/kubepods/burstable/pod1")
+        ));
+        assert!(match_user_path(
+            &patterns,
+            Path::new("kubepods/besteffort/pod2")
+        ));
+        assert!(!match_user_path(
+            &patterns,
+            Path::new("/kubepods/burstable")
+        ));
+        assert!(!match_user_path(
+            &patterns,
+            Path::new("/kubepods/burstable/pod1/c1")
+        ));
+        assert!(!match_user_path(&[], Path::new("/kubepods/burstable/pod1")));
+    }
+
+    #[test]
+    fn test_discover_cgroups() {
+        let current_path: PathBuf = env::current_dir().unwrap();
+        let mount_point = PathBuf::from(format!(
+            "{}/tests/sample/cgroup_tree",
+            current_path.to_string_lossy()
+        ));
+        let patterns = vec![
+            String::from("kubepods/*/pod*"),
+            String::from("/system.slice/*.service"),
+            String::from("kubepods/burstable/pod*"),
+        ];
+        let result = discover_cgroups(&mount_point, CGroupType::V2, &patterns);
+        assert_eq!(
+            result,
+            vec![
+                PathBuf::from("/kubepods/besteffort/pod2"),
+                PathBuf::from("/kubepods/burstable/pod1"),
+                PathBuf::from("/system.slice/sshd.service"),
+            ]
+        );
+
+        let result = discover_cgroups(&mount_point, CGroupType::V1, &patterns);
+        assert_eq!(result, vec![PathBuf::from("/kubepods/burstable/pod1")]);
+    }
+}
@@ -0,0 +1,211 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::cgroup::utils;
+use crate::common;
+use crate::common::CGroupType;
+use crate::system::get_secs_since_epoch;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq, ToSchema)]
+pub struct HugeTLBStat {
+    /// bytes of huge pages charged
+    usage: Option<u64>,
+    /// u64::MAX when unlimited
+    limit: Option<u64>,
+    /// peak usage, v1 only
+    max_usage: Option<u64>,
+    /// allocations refused for hitting the limit, failcnt on v1
+    max_events: Option<u64>,
+}
+
+impl HugeTLBStat {
+    pub fn usage(&self) -> Option<u64> {
+        self.usage
+    }
+
+    pub fn limit(&self) -> Option<u64> {
+        self.limit
+    }
+
+    pub fn max_usage(&self) -> Option<u64> {
+        self.max_usage
+    }
+
+    pub fn max_events(&self) -> Option<u64> {
+        self.max_events
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HugeTLBCGroup {
+    full_path: PathBuf,
+    user_path: PathBuf,
+    cgroup_type: CGroupType,
+    /// keyed by huge page size, e.g. 2MB or 1GB
+    stats: HashMap<String, HugeTLBStat>,
+    update_time: u64,
+}
+
+pub fn new_hugetlb_cgroup(
+    mount_point: &str,
+    user_path: &Path,
+    cgroup_type: CGroupType,
+) -> HugeTLBCGroup {
+    let full_path: PathBuf = match cgroup_type {
+        CGroupType::V1 => PathBuf::from(format!(
+            "{}/hugetlb/{}",
+            mount_point,
+            user_path.to_string_lossy()
+        )),
+        CGroupType::V2 => PathBuf::from(format!("{}/{}", mount_point, user_path.to_string_lossy())),
+    };
+
+    HugeTLBCGroup {
+        full_path,
+        user_path: user_path.to_path_buf(),
+        cgroup_type,
+        stats: HashMap::new(),
+        update_time: 0,
+    }
+}
+
+impl HugeTLBCGroup {
+    pub fn full_path(&self) -> &Path {
+        self.full_path.as_path()
+    }
+
+    pub fn user_path(&self) -> &Path {
+        self.user_path.as_path()
+    }
+
+    pub fn stats(&self) -> &HashMap<String, HugeTLBStat> {
+        &self.stats
+    }
+
+    /// huge page sizes with interface files, e.g. hugetlb.2MB.current
+    fn page_sizes(&self) -> BTreeSet<String> {
+        let entries = match fs::read_dir(&self.full_path) {
+            Ok(entries) => entries,
+            Err(_) => return BTreeSet::new(),
+        };
+        entries
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                let mut parts = name.split('.');
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some("hugetlb"), Some(size), Some(_)) => Some(size.to_string()),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// on v2 the interface files only exist when the controller is enabled
+    pub fn exists(&self) -> bool {
+        !self.page_sizes().is_empty()
+    }
+
+    fn update_stats(&mut self) -> common::Result<bool> {
+        let file =
+            |size: &str, name: &str| self.full_path.join(format!("hugetlb.{}.{}", size, name));
+        let mut stats = HashMap::new();
+        for size in self.page_sizes() {
+            let stat = match self.cgroup_type {
+                CGroupType::V1 => HugeTLBStat {
+                    usage: utils::get_cgroup_value(file(&size, "usage_in_bytes")),
+                    limit: utils::get_cgroup_value(file(&size, "limit_in_bytes")),
+                    max_usage: utils::get_cgroup_value(file(&size, "max_usage_in_bytes")),
+                    max_events: utils::get_cgroup_value(file(&size, "failcnt")),
+                },
+                CGroupType::V2 => {
+                    let events = fs::read_to_string(file(&size, "events"))?;
+                    HugeTLBStat {
+                        usage: utils::get_cgroup_value(file(&size, "current")),
+                        limit: utils::get_cgroup_value(file(&size, "max")),
+                        max_usage: None,
+                        max_events: utils::parse_cgroup_limit_file(&events).get("max").cloned(),
+                    }
+                }
+            };
+            stats.insert(size, stat);
+        }
+        self.stats = stats;
+        Ok(true)
+    }
+
+    pub fn update(&mut self) -> common::Result<()> {
+        let result = self.update_stats();
+        if let Err(e) = &result {
+            warn!(
+                "[hugetlb_cg] update hugetlb stats error: {}, path= {}",
+                e,
+                self.full_path.display()
+            );
+        }
+        self.update_time = get_secs_since_epoch();
+        result.map(|_| ())
+    }
+
+    pub fn update_ebpf(&mut self) {}
+
+    pub fn reset(&mut self) {
+        self.stats.clear();
+        self.update_time = get_secs_since_epoch();
+    }
+}
+
+#[cfg(test)]
+mod tests_hugetlb_cg {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_hugetlb_cg() {
+        let mount_point = format!("{}/tests/sample", env::current_dir().unwrap().display());
+        let mut cg = new_hugetlb_cgroup(&mount_point, Path::new("pod_user_path"), CGroupType::V1);
+        assert!(cg.exists());
+        cg.update().unwrap();
+        assert_eq!(cg.stats().len(), 2);
+        let stat = cg.stats().get("2MB").unwrap();
+        assert_eq!(stat.usage(), Some(1073741824));
+        assert_eq!(stat.limit(), Some(2147483648));
+        assert_eq!(stat.max_usage(), Some(1073741824));
+        assert_eq!(stat.max_events(), Some(3));
+        assert_eq!(cg.stats().get("1GB").unwrap().usage(), Some(0));
+    }
+
+    #[test]
+    fn test_hugetlb_cg_v2() {
+        let mount_point = env::current_dir().unwrap().display().to_string();
+        let mut cg = new_hugetlb_cgroup(
+            &mount_point,
+            Path::new("tests/sample/cgroupv2"),
+            CGroupType::V2,
+        );
+        assert!(cg.exists());
+        cg.update().unwrap();
+        let stat = cg.stats().get("2MB").unwrap();
+        assert_eq!(stat.usage(), Some(4194304));
+        assert_eq!(stat.limit(), Some(u64::MAX));
+        assert_eq!(stat.max_usage(), None);
+        assert_eq!(stat.max_events(), Some(1));
+    }
+}
@@ -13,6 +13,7 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::cgroup::normalized::{MemoryMetrics, MEMORY_UNLIMITED_V1};
 use crate::cgroup::utils;
 use crate::common;
 use crate::common::CGroupType;
@@ -54,6 +55,7 @@ pub fn new_memory_cgroup(
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[allow(clippy::large_enum_variant)]
 pub enum MemoryCGroup {
     /// cgroup v1 info from cgroupfs
     V1(MemoryCGroupV1),
@@ -69,7 +71,7 @@ impl MemoryCGroup {
         }
     }
 
-    pub fn update(&mut self) {
+    pub fn update(&mut self) -> common::Result<()> {
         match self {
             MemoryCGroup::V1(v1) => v1.update(),
             MemoryCGroup::V2(v2) => v2.update(),
@@ -100,6 +102,31 @@ impl MemoryCGroup {
             MemoryCGroup::V2(v2) => v2.reset(),
         }
     }
+
+    /// keep the NUMA stats of the given nodes only
+    pub fn retain_numa_nodes(&mut self, nodes: &[usize]) {
+        // stats are keyed by node name, e.g. N0
+        let keep = |name: &str| {
+            name.strip_prefix('N')
+                .and_then(|id| id.parse::<usize>().ok())
+                .is_some_and(|id| nodes.contains(&id))
+        };
+        match self {
+            MemoryCGroup::V1(v1) => {
+                if let Some(numa_stat) = &mut v1.numa_stat {
+                    numa_stat.retain(|stat| keep(&stat.numa_name));
+                }
+            }
+            MemoryCGroup::V2(v2) => v2.mem_numa_stats.retain(|name, _| keep(name)),
+        }
+    }
+
+    pub fn normalize(&self) -> MemoryMetrics {
+        match self {
+            MemoryCGroup::V1(v1) => v1.normalize(),
+            MemoryCGroup::V2(v2) => v2.normalize(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
@@ -109,8 +136,17 @@ pub struct MemoryCGroupV2 {
     mem_stats: MemStatsV2,
     mem_numa_stats: HashMap<String, MemNumaStatsV2>,
     mem_pressure: PressureStallInfo,
-    mem_local_events: MemEventLocalV2,
+    mem_local_events: MemEventsV2,
+    /// memory.events, counted over the whole subtree
+    mem_events: MemEventsV2,
+    swap_events: MemSwapEventsV2,
     memory_usage_in_bytes: Option<u64>,
+    /// highest memory.current recorded since creation
+    peak: Option<u64>,
+    swap_current: Option<u64>,
+    swap_peak: Option<u64>,
+    /// compressed size of the pages held in zswap
+    zswap_current: Option<u64>,
     max: Option<u64>,
     high: Option<u64>,
     low: Option<u64>,
@@ -121,13 +157,28 @@ pub struct MemoryCGroupV2 {
     update_time: u64,
 }
 
+/// memory.events or memory.events.local, keys the kernel does not have yet are 0
 #[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
-pub struct MemEventLocalV2 {
+#[serde(default)]
+pub struct MemEventsV2 {
     low: u64,
     high: u64,
     max: u64,
     oom: u64,
     oom_kill: u64,
+    oom_group_kill: u64,
+}
+
+/// former name of `MemEventsV2`, from when only memory.events.local was read
+pub type MemEventLocalV2 = MemEventsV2;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
+#[serde(default)]
+pub struct MemSwapEventsV2 {
+    high: u64,
+    max: u64,
+    /// swap allocations which failed
+    fail: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
@@ -186,6 +237,14 @@ pub struct MemStatsV2 {
     pglazyfreed: u64,
     thp_fault_alloc: u64,
     thp_collapse_alloc: u64,
+    percpu: u64,
+    sec_pagetables: u64,
+    zswap: u64,
+    zswapped: u64,
+    zswpin: u64,
+    zswpout: u64,
+    workingset_restore_anon: u64,
+    workingset_restore_file: u64,
 }
 
 impl MemoryCGroupV2 {
@@ -196,6 +255,22 @@ impl MemoryCGroupV2 {
         self.user_path.as_path()
     }
 
+    pub fn normalize(&self) -> MemoryMetrics {
+        MemoryMetrics {
+            usage_bytes: self.memory_usage_in_bytes,
+            limit_bytes: self.max.filter(|max| *max != u64::MAX),
+            working_set_bytes: self
+                .memory_usage_in_bytes
+                .map(|usage| usage.saturating_sub(self.mem_stats.inactive_file)),
+            anon_bytes: Some(self.mem_stats.anon),
+            file_bytes: Some(self.mem_stats.file),
+            pgfault: Some(self.mem_stats.pgfault),
+            pgmajfault: Some(self.mem_stats.pgmajfault),
+            oom_count: self.oom_cnt,
+            pressure: Some(self.mem_pressure),
+        }
+    }
+
     fn update_oom_cnt(&mut self) -> common::Result<bool> {
         let path = PathBuf::from(&self.user_path());
         let mem_data = wrapper_get_cgroup_mem_data(path);
@@ -209,15 +284,16 @@ impl MemoryCGroupV2 {
         let contents = fs::read_to_string(&file)?;
         let data = utils::parse_cgroup_file(contents.as_str());
 
+        // keys missing on older kernels keep their default, unknown ones are ignored
         let stat = MemStatsV2::default();
-        let r = serde_json::to_string(&stat).unwrap();
-        let mut stat_map: Map<String, Value> = serde_json::from_str(&r).unwrap();
+        let r = serde_json::to_string(&stat)?;
+        let mut stat_map: Map<String, Value> = serde_json::from_str(&r)?;
         for (key, value) in data {
             stat_map.insert(String::from(key), Value::Number(Number::from(value)));
         }
 
-        let s = serde_json::to_string(&stat_map).unwrap();
-        let mem_stats_v2: MemStatsV2 = serde_json::from_str(&s).unwrap();
+        let s = serde_json::to_string(&stat_map)?;
+        let mem_stats_v2: MemStatsV2 = serde_json::from_str(&s)?;
         self.mem_stats = mem_stats_v2;
         Ok(true)
     }
@@ -264,6 +340,10 @@ impl MemoryCGroupV2 {
             "memory.swap.max",
             "memory.watermark_scale_factor",
             "memory.current",
+            "memory.peak",
+            "memory.swap.current",
+            "memory.swap.peak",
+            "memory.zswap.current",
         ];
         let data: HashMap<&str, Option<u64>> = v
             .iter()
@@ -295,22 +375,55 @@ impl MemoryCGroupV2 {
         if let Some(value) = data.get(&"memory.current") {
             self.memory_usage_in_bytes = *value;
         }
+        if let Some(value) = data.get(&"memory.peak") {
+            self.peak = *value;
+        }
+        if let Some(value) = data.get(&"memory.swap.current") {
+            self.swap_current = *value;
+        }
+        if let Some(value) = data.get(&"memory.swap.peak") {
+            self.swap_peak = *value;
+        }
+        if let Some(value) = data.get(&"memory.zswap.current") {
+            self.zswap_current = *value;
+        }
         Ok(true)
     }
 
+    fn read_mem_events(file: &Path) -> common::Result<MemEventsV2> {
+        let contents = fs::read_to_string(file)?;
+        let data = utils::parse_cgroup_file(contents.as_str());
+        let s = serde_json::to_string(&data)?;
+        Ok(serde_json::from_str(&s)?)
+    }
+
     fn update_memory_local_event(&mut self) -> common::Result<bool> {
         let mut file = PathBuf::from(&self.full_path());
         file.push("memory.events.local");
+        self.mem_local_events = MemoryCGroupV2::read_mem_events(&file)?;
+        Ok(true)
+    }
+
+    fn update_memory_event(&mut self) -> common::Result<bool> {
+        let mut file = PathBuf::from(&self.full_path());
+        file.push("memory.events");
+        self.mem_events = MemoryCGroupV2::read_mem_events(&file)?;
+        Ok(true)
+    }
+
+    fn update_memory_swap_event(&mut self) -> common::Result<bool> {
+        let mut file = PathBuf::from(&self.full_path());
+        file.push("memory.swap.events");
         let contents = fs::read_to_string(&file)?;
         let data = utils::parse_cgroup_file(contents.as_str());
-        let s = serde_json::to_string(&data).unwrap();
-        let mem_local_events_v2: MemEventLocalV2 = serde_json::from_str(&s).unwrap();
-        self.mem_local_events = mem_local_events_v2;
+        let s = serde_json::to_string(&data)?;
+        self.swap_events = serde_json::from_str(&s)?;
         Ok(true)
     }
 
-    fn update(&mut self) {
-        if let Err(e) = self.update_memory_stat() {
+    fn update(&mut self) -> common::Result<()> {
+        let result = self.update_memory_stat();
+        if let Err(e) = &result {
             warn!(
                 "[memcgv2] update memory stat error: {}, path= {}",
                 e,
@@ -342,6 +455,25 @@ impl MemoryCGroupV2 {
             );
         }
 
+        if let Err(e) = self.update_memory_event() {
+            warn!(
+                "[memcgv2] update memory event error: {}, path= {}",
+                e,
+                self.full_path.display()
+            );
+        }
+
+        // absent when swap accounting is disabled
+        if self.swap_max.is_some() {
+            if let Err(e) = self.update_memory_swap_event() {
+                warn!(
+                    "[memcgv2] update memory swap event error: {}, path= {}",
+                    e,
+                    self.full_path.display()
+                );
+            }
+        }
+
         if let Err(e) = self.update_memory_pressure() {
             warn!(
                 "[memcgv2] update memory pressure error: {}, path= {}",
@@ -349,6 +481,7 @@ impl MemoryCGroupV2 {
                 self.full_path.display()
             );
         }
+        result.map(|_| ())
     }
 
     fn update_ebpf(&mut self) {
@@ -425,6 +558,7 @@ pub struct MemoryCGroupV1 {
     total_pgfault: Option<u64>,
     total_pgmajfault: Option<u64>,
     total_allocstall: Option<u64>,
+    total_inactive_file: Option<u64>,
     watermark_scale_factor: Option<usize>,
     oom_cnt: Option<u64>,
     numa_stat: Option<Vec<MemoryCGroupNumaStat>>,
@@ -432,6 +566,24 @@ pub struct MemoryCGroupV1 {
 }
 
 impl MemoryCGroupV1 {
+    pub fn normalize(&self) -> MemoryMetrics {
+        MemoryMetrics {
+            usage_bytes: self.memory_usage_in_bytes,
+            limit_bytes: self
+                .memory_limit_in_bytes
+                .filter(|limit| *limit < MEMORY_UNLIMITED_V1),
+            working_set_bytes: self
+                .memory_usage_in_bytes
+                .map(|usage| usage.saturating_sub(self.total_inactive_file.unwrap_or_default())),
+            anon_bytes: self.total_rss,
+            file_bytes: self.total_cache,
+            pgfault: self.total_pgfault,
+            pgmajfault: self.total_pgmajfault,
+            oom_count: self.oom_cnt,
+            pressure: None,
+        }
+    }
+
     pub fn update_numa_stat(&mut self) -> common::Result<bool> {
         let mut file = PathBuf::from(&self.full_path);
         file.push("memory.numa_stat");
@@ -469,6 +621,7 @@ impl MemoryCGroupV1 {
         self.total_pgfault = data.get(&"total_pgfault").cloned();
         self.total_pgmajfault = data.get(&"total_pgmajfault").cloned();
         self.total_allocstall = data.get(&"total_allocstall").cloned();
+        self.total_inactive_file = data.get(&"total_inactive_file").cloned();
         Ok(true)
     }
 
@@ -540,7 +693,7 @@ impl MemoryCGroupV1 {
         Ok(true)
     }
 
-    fn update(&mut self) {
+    fn update(&mut self) -> common::Result<()> {
         if let Err(e) = self.update_memory_limit() {
             warn!(
                 "[memcg] update memory limit error: {}, path= {}",
@@ -548,7 +701,8 @@ impl MemoryCGroupV1 {
                 self.full_path.display()
             );
         }
-        if let Err(e) = self.update_memory_stat() {
+        let result = self.update_memory_stat();
+        if let Err(e) = &result {
             warn!(
                 "[memcg] update memory stat error: {}, path= {}",
                 e,
@@ -590,6 +744,7 @@ impl MemoryCGroupV1 {
                 self.full_path.display()
             );
         }
+        result.map(|_| ())
     }
 
     fn update_ebpf(&mut self) {
@@ -628,7 +783,7 @@ mod tests_memory_cg {
         let user_path: PathBuf = PathBuf::from("pod_user_path");
 
         let mut cg = new_memory_cgroup(&mount_point, &user_path, CGroupType::V1 {});
-        cg.update();
+        cg.update().unwrap();
 
         let correct_full_path = PathBuf::from(format!("{}/memory/pod_user_path", mount_point));
 
@@ -675,6 +830,26 @@ mod tests_memory_cg {
         }
     }
 
+    #[test]
+    fn test_retain_numa_nodes() {
+        let mount_point: String = String::from(format!(
+            "{}/tests/sample",
+            env::current_dir().unwrap().to_string_lossy()
+        ));
+        let user_path: PathBuf = PathBuf::from("pod_user_path");
+
+        let mut cg = new_memory_cgroup(&mount_point, &user_path, CGroupType::V1 {});
+        cg.update().unwrap();
+        cg.retain_numa_nodes(&[1]);
+
+        if let MemoryCGroup::V1(memory_cg) = cg {
+            let numa_stat = memory_cg.numa_stat.unwrap();
+            assert_eq!(numa_stat.len(), 1);
+            assert_eq!(numa_stat[0].numa_name, "N1");
+            assert_eq!(numa_stat[0].total, Some(589301));
+        }
+    }
+
     #[test]
     fn test_memory_cg_v2() {
         let mount_point: String = env::current_dir()
@@ -685,7 +860,7 @@ mod tests_memory_cg {
         let user_path: PathBuf = PathBuf::from("tests/sample/cgroupv2");
 
         let mut cg = new_memory_cgroup(&mount_point, &user_path, CGroupType::V2 {});
-        cg.update();
+        cg.update().unwrap();
 
         let correct_full_path = PathBuf::from(format!("{}/tests/sample/cgroupv2", mount_point));
 
@@ -718,6 +893,23 @@ mod tests_memory_cg {
             assert_eq!(memory_cg.mem_local_events.low, 1);
             assert_eq!(memory_cg.mem_local_events.oom_kill, 666);
 
+            //memory.events
+            assert_eq!(memory_cg.mem_events.max, 12);
+            assert_eq!(memory_cg.mem_events.oom_kill, 668);
+            assert_eq!(memory_cg.mem_events.oom_group_kill, 1);
+
+            // swap, zswap and peak
+            assert_eq!(memory_cg.peak, Some(20971520));
+            assert_eq!(memory_cg.swap_current, Some(1048576));
+            assert_eq!(memory_cg.swap_peak, Some(2097152));
+            assert_eq!(memory_cg.zswap_current, Some(262144));
+            assert_eq!(memory_cg.swap_events.max, 2);
+            assert_eq!(memory_cg.swap_events.fail, 1);
+            assert_eq!(memory_cg.mem_stats.zswapped, 786432);
+            assert_eq!(memory_cg.mem_stats.sec_pagetables, 4096);
+            assert_eq!(memory_cg.mem_stats.percpu, 97408);
+            assert_eq!(memory_cg.mem_stats.workingset_restore_file, 3);
+
             // test for memory subsystem
             {
                 let memory_subsystem = SubSystem::Memory(MemoryCGroup::V2(memory_cg));
@@ -731,4 +923,41 @@ mod tests_memory_cg {
             }
         }
     }
+
+    #[test]
+    fn test_read_mem_events() {
+        let sample = env::current_dir().unwrap().join("tests/sample");
+
+        // `hgh` is not a key of the struct and is ignored, `high` is left at 0
+        let events =
+            MemoryCGroupV2::read_mem_events(&sample.join("cgroupv2/memory.events.local")).unwrap();
+        assert_eq!(events.low, 1);
+        assert_eq!(events.high, 0);
+        assert_eq!(events.oom_kill, 666);
+        assert_eq!(events.oom_group_kill, 0);
+
+        let events =
+            MemoryCGroupV2::read_mem_events(&sample.join("memory_events/memory.events.local"))
+                .unwrap();
+        assert_eq!(events.low, 3);
+        assert_eq!(events.high, 5);
+        assert_eq!(events.max, 7);
+        assert_eq!(events.oom, 2);
+        assert_eq!(events.oom_kill, 1);
+        assert_eq!(events.oom_group_kill, 1);
+
+        // the non-numeric `high` line is skipped, the others are still read
+        let events =
+            MemoryCGroupV2::read_mem_events(&sample.join("memory_events/memory.events")).unwrap();
+        assert_eq!(events.low, 3);
+        assert_eq!(events.high, 0);
+        assert_eq!(events.max, 7);
+        assert_eq!(events.oom_group_kill, 1);
+
+        // a missing file is an error
+        assert!(
+            MemoryCGroupV2::read_mem_events(&sample.join("memory_events/memory.swap.events"))
+                .is_err()
+        );
+    }
 }
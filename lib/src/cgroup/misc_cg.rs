@@ -0,0 +1,178 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::cgroup::utils;
+use crate::common;
+use crate::common::CGroupType;
+use crate::system::get_secs_since_epoch;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq, ToSchema)]
+pub struct MiscResourceStat {
+    current: u64,
+    /// u64::MAX when unlimited
+    max: Option<u64>,
+    /// charges refused for hitting the limit
+    max_events: Option<u64>,
+}
+
+impl MiscResourceStat {
+    pub fn current(&self) -> u64 {
+        self.current
+    }
+
+    pub fn max(&self) -> Option<u64> {
+        self.max
+    }
+
+    pub fn max_events(&self) -> Option<u64> {
+        self.max_events
+    }
+}
+
+/// scalar resources of the misc controller, e.g. sev and sev_es ASIDs
+#[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
+pub struct MiscCGroup {
+    full_path: PathBuf,
+    user_path: PathBuf,
+    /// keyed by resource name
+    resources: HashMap<String, MiscResourceStat>,
+    update_time: u64,
+}
+
+pub fn new_misc_cgroup(mount_point: &str, user_path: &Path, cgroup_type: CGroupType) -> MiscCGroup {
+    let full_path: PathBuf = match cgroup_type {
+        CGroupType::V1 => PathBuf::from(format!(
+            "{}/misc/{}",
+            mount_point,
+            user_path.to_string_lossy()
+        )),
+        CGroupType::V2 => PathBuf::from(format!("{}/{}", mount_point, user_path.to_string_lossy())),
+    };
+
+    MiscCGroup {
+        full_path,
+        user_path: user_path.to_path_buf(),
+        ..Default::default()
+    }
+}
+
+impl MiscCGroup {
+    pub fn full_path(&self) -> &Path {
+        self.full_path.as_path()
+    }
+
+    pub fn user_path(&self) -> &Path {
+        self.user_path.as_path()
+    }
+
+    pub fn resources(&self) -> &HashMap<String, MiscResourceStat> {
+        &self.resources
+    }
+
+    /// on v2 the interface files only exist when the controller is enabled
+    pub fn exists(&self) -> bool {
+        self.full_path.join("misc.current").exists()
+    }
+
+    fn update_resources(&mut self) -> common::Result<bool> {
+        let current = fs::read_to_string(self.full_path.join("misc.current"))?;
+        // misc.max and misc.events are missing in the root cgroup
+        let max = fs::read_to_string(self.full_path.join("misc.max")).unwrap_or_default();
+        let events = fs::read_to_string(self.full_path.join("misc.events")).unwrap_or_default();
+        let max = utils::parse_cgroup_limit_file(&max);
+        let events = utils::parse_cgroup_limit_file(&events);
+
+        self.resources = utils::parse_cgroup_limit_file(&current)
+            .into_iter()
+            .map(|(name, current)| {
+                let stat = MiscResourceStat {
+                    current,
+                    max: max.get(&name).cloned(),
+                    // e.g. `sev.max 0`
+                    max_events: events.get(&format!("{}.max", name)).cloned(),
+                };
+                (name, stat)
+            })
+            .collect();
+        Ok(true)
+    }
+
+    pub fn update(&mut self) -> common::Result<()> {
+        let result = self.update_resources();
+        if let Err(e) = &result {
+            warn!(
+                "[misc_cg] update misc resources error: {}, path= {}",
+                e,
+                self.full_path.display()
+            );
+        }
+        self.update_time = get_secs_since_epoch();
+        result.map(|_| ())
+    }
+
+    pub fn update_ebpf(&mut self) {}
+
+    pub fn reset(&mut self) {
+        *self = Self {
+            full_path: self.full_path.clone(),
+            user_path: self.user_path.clone(),
+            ..Default::default()
+        };
+        self.update_time = get_secs_since_epoch();
+    }
+}
+
+#[cfg(test)]
+mod tests_misc_cg {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_misc_cg() {
+        let mount_point = format!("{}/tests/sample", env::current_dir().unwrap().display());
+        let mut cg = new_misc_cgroup(&mount_point, Path::new("pod_user_path"), CGroupType::V1);
+        assert!(cg.exists());
+        cg.update().unwrap();
+        let sev = cg.resources().get("sev").unwrap();
+        assert_eq!(sev.current(), 3);
+        assert_eq!(sev.max(), Some(u64::MAX));
+        assert_eq!(sev.max_events(), Some(0));
+        let sev_es = cg.resources().get("sev_es").unwrap();
+        assert_eq!(sev_es.current(), 1);
+        assert_eq!(sev_es.max(), Some(1));
+        assert_eq!(sev_es.max_events(), Some(2));
+    }
+
+    #[test]
+    fn test_misc_cg_v2() {
+        let mount_point = env::current_dir().unwrap().display().to_string();
+        let mut cg = new_misc_cgroup(
+            &mount_point,
+            Path::new("tests/sample/cgroupv2"),
+            CGroupType::V2,
+        );
+        assert!(cg.exists());
+        cg.update().unwrap();
+        assert_eq!(cg.resources().len(), 2);
+        assert_eq!(cg.resources().get("sev").unwrap().current(), 0);
+        assert_eq!(cg.resources().get("sev").unwrap().max(), Some(16));
+    }
+}
@@ -16,15 +16,31 @@ limitations under the License.
 mod blkio_cg;
 mod cg;
 mod cpu_cg;
+mod discovery;
+mod hugetlb_cg;
 mod memory_cg;
+mod misc_cg;
 mod net_cg;
+mod normalized;
 mod perf_event_cg;
+mod pids_cg;
+mod rdma_cg;
+mod rollup;
 mod utils;
+mod watcher;
 
 pub use blkio_cg::*;
 pub use cg::*;
 pub use cpu_cg::*;
+pub use discovery::*;
+pub use hugetlb_cg::*;
 pub use memory_cg::*;
+pub use misc_cg::*;
 pub use net_cg::*;
+pub use normalized::*;
 pub use perf_event_cg::*;
+pub use pids_cg::*;
+pub use rdma_cg::*;
+pub use rollup::*;
 pub use utils::*;
+pub use watcher::*;
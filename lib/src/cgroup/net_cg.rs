@@ -69,7 +69,9 @@ impl NetCGroup {
     }
 
     #[cfg(not(tarpaulin_include))]
-    pub fn update(&mut self) {}
+    pub fn update(&mut self) -> common::Result<()> {
+        Ok(())
+    }
 
     pub fn update_ebpf(&mut self) {
         if !is_bpf_moudule_valid(BPF_MODULE_CGROUP_NET) {
@@ -0,0 +1,195 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::cgroup::{CpuThrottling, IoThroughput};
+use crate::common::CGroupType;
+use crate::psi::PressureStallInfo;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use utoipa::ToSchema;
+
+/// memory.limit_in_bytes of an unlimited v1 cgroup, PAGE_COUNTER_MAX rounded to pages
+pub(crate) const MEMORY_UNLIMITED_V1: u64 = 0x7FFF_FFFF_FFFF_F000;
+
+/// cpu metrics with the same meaning on cgroup v1 and v2
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct CpuMetrics {
+    /// cumulative usage in nanoseconds
+    pub usage_ns: u64,
+    pub user_ns: u64,
+    pub sys_ns: u64,
+    /// cores used between the last two updates
+    pub usage_ratio: f32,
+    /// cores allowed by the cfs quota, none when unlimited
+    pub limit_cores: Option<f64>,
+    pub nr_periods: Option<u64>,
+    pub nr_throttled: Option<u64>,
+    /// cumulative throttled time in nanoseconds
+    pub throttled_ns: Option<u64>,
+    /// bursts beyond the quota and their cumulative time in nanoseconds, where
+    /// the kernel supports cfs burst
+    pub nr_bursts: Option<u64>,
+    pub burst_ns: Option<u64>,
+    /// throttling between the last two updates
+    pub throttling: Option<CpuThrottling>,
+    /// cpu.pressure, v2 only
+    pub pressure: Option<PressureStallInfo>,
+}
+
+/// memory metrics with the same meaning on cgroup v1 and v2
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct MemoryMetrics {
+    pub usage_bytes: Option<u64>,
+    /// none when unlimited
+    pub limit_bytes: Option<u64>,
+    /// usage without the inactive file pages, as the kubelet counts it for evictions
+    pub working_set_bytes: Option<u64>,
+    /// anonymous memory, `total_rss` on v1 and `anon` on v2
+    pub anon_bytes: Option<u64>,
+    /// page cache, `total_cache` on v1 and `file` on v2
+    pub file_bytes: Option<u64>,
+    pub pgfault: Option<u64>,
+    pub pgmajfault: Option<u64>,
+    /// counted by the ebpf oom module
+    pub oom_count: Option<u64>,
+    /// memory.pressure, v2 only
+    pub pressure: Option<PressureStallInfo>,
+}
+
+/// io metrics summed over every device, with the same meaning on cgroup v1 and v2
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct IoMetrics {
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub read_ops: u64,
+    pub write_ops: u64,
+    /// throughput between the last two updates
+    pub throughput: Option<IoThroughput>,
+    /// io.pressure, v2 only
+    pub pressure: Option<PressureStallInfo>,
+}
+
+/// version agnostic view of a cgroup, a section is missing when its controller is
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct CGroupMetrics {
+    pub user_path: PathBuf,
+    pub cgroup_type: CGroupType,
+    pub cpu: Option<CpuMetrics>,
+    pub memory: Option<MemoryMetrics>,
+    pub io: Option<IoMetrics>,
+    /// seconds since epoch
+    pub update_time: u64,
+    /// failures of the last update, the sections which failed keep their previous values
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+#[cfg(test)]
+mod tests_normalized {
+    use crate::cgroup::{
+        new_blkio_cgroup, new_cpu_cgroup, new_memory_cgroup, IoMetrics, IoThroughput,
+    };
+    use crate::common::CGroupType;
+    use std::env;
+    use std::path::PathBuf;
+
+    fn sample() -> (String, String) {
+        let current_path = env::current_dir().unwrap().to_string_lossy().to_string();
+        (format!("{}/tests/sample", current_path), current_path)
+    }
+
+    #[test]
+    fn test_normalize_v1() {
+        let (mount_point, _) = sample();
+        let user_path = PathBuf::from("pod_user_path");
+
+        let mut cpu = new_cpu_cgroup(&mount_point, &user_path, CGroupType::V1);
+        cpu.update().unwrap();
+        let cpu = cpu.normalize();
+        assert_eq!(cpu.usage_ns, 145405357644162);
+        assert_eq!(cpu.limit_cores, Some(20.0));
+        assert_eq!(cpu.nr_periods, Some(422162));
+        assert_eq!(cpu.nr_throttled, Some(2));
+        assert_eq!(cpu.throttled_ns, Some(10));
+        assert_eq!(cpu.nr_bursts, Some(1));
+        assert_eq!(cpu.burst_ns, Some(2000));
+        assert!(cpu.pressure.is_none());
+
+        let mut memory = new_memory_cgroup(&mount_point, &user_path, CGroupType::V1);
+        memory.update().unwrap();
+        let memory = memory.normalize();
+        assert_eq!(memory.usage_bytes, Some(243873845248));
+        assert_eq!(memory.limit_bytes, Some(34359738368));
+        assert_eq!(memory.working_set_bytes, Some(243873845248 - 8609902592));
+        assert_eq!(memory.anon_bytes, Some(4919517184));
+        assert_eq!(memory.file_bytes, Some(8611385344));
+
+        let mut io = new_blkio_cgroup(&mount_point, &user_path, CGroupType::V1);
+        io.update().unwrap();
+        let io = io.normalize();
+        assert_eq!(io.read_bytes, 5226496);
+        assert_eq!(io.write_bytes, 36864);
+        assert_eq!(io.read_ops, 129);
+        assert_eq!(io.write_ops, 4);
+    }
+
+    #[test]
+    fn test_normalize_v2() {
+        let (_, mount_point) = sample();
+        let user_path = PathBuf::from("tests/sample/cgroupv2");
+
+        let mut cpu = new_cpu_cgroup(&mount_point, &user_path, CGroupType::V2);
+        cpu.update().unwrap();
+        let cpu = cpu.normalize();
+        assert_eq!(cpu.usage_ns, 6983392776000);
+        assert_eq!(cpu.sys_ns, 2777685920000);
+        assert_eq!(cpu.limit_cores, None);
+        assert_eq!(cpu.nr_throttled, Some(0));
+        assert_eq!(cpu.nr_bursts, Some(3));
+        assert_eq!(cpu.burst_ns, Some(1500000));
+        assert_eq!(cpu.pressure.unwrap().get_some().unwrap().get_avg10(), 1.23);
+
+        let mut io = new_blkio_cgroup(&mount_point, &user_path, CGroupType::V2);
+        io.update().unwrap();
+        let io = io.normalize();
+        assert_eq!(io.read_bytes, 1003520);
+        assert_eq!(io.write_bytes, 28135841792);
+        assert_eq!(io.read_ops, 133);
+        assert_eq!(io.write_ops, 4781811);
+        assert!(io.pressure.is_some());
+    }
+
+    #[test]
+    fn test_io_throughput() {
+        let last = IoMetrics {
+            read_bytes: 1000,
+            write_bytes: 5000,
+            ..Default::default()
+        };
+        let now = IoMetrics {
+            read_bytes: 3000,
+            write_bytes: 5000,
+            ..Default::default()
+        };
+        let throughput = IoThroughput::between(100, &last, 110, &now).unwrap();
+        assert_eq!(throughput.read_bytes_per_sec, 200.0);
+        assert_eq!(throughput.write_bytes_per_sec, 0.0);
+
+        // the first update, no time elapsed, or the cgroup was recreated
+        assert_eq!(IoThroughput::between(0, &last, 110, &now), None);
+        assert_eq!(IoThroughput::between(110, &last, 110, &now), None);
+        assert_eq!(IoThroughput::between(100, &now, 110, &last), None);
+    }
+}
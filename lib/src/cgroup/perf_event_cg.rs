@@ -14,7 +14,7 @@ limitations under the License.
 */
 
 use crate::common;
-use crate::common::{CGroupType, MOUNT_POINT};
+use crate::common::CGroupType;
 use crate::ffi::pmu::ffi::byteperf_cgroup_buffer_malachite;
 use crate::system::get_secs_since_epoch;
 use log::{debug, info};
@@ -105,7 +105,9 @@ impl PerfEventCGroup {
     }
 
     #[cfg(not(tarpaulin_include))]
-    pub fn update(&mut self) {}
+    pub fn update(&mut self) -> common::Result<()> {
+        Ok(())
+    }
 
     pub fn update_ebpf(&mut self) {}
 
@@ -125,7 +127,7 @@ impl From<&byteperf_cgroup_buffer_malachite> for PerfEventCGroup {
         debug!("[byteperf] From function: cgroup_path = {:?}", cgroup_path);
         let mut cg_str = String::from(cgroup_path.to_str().unwrap());
         let full_path = String::from(cgroup_path.to_str().unwrap());
-        if !cg_str.contains(MOUNT_POINT) {
+        if !cg_str.contains(&*common::cgroup_mount_point().to_string_lossy()) {
             info!("[PMU] pmu data cgroup name error: {:?}", cgroup_path);
         }
         if !cg_str.contains("perf_event") {
@@ -0,0 +1,153 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::cgroup::utils;
+use crate::common;
+use crate::common::CGroupType;
+use crate::system::get_secs_since_epoch;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
+pub struct PidsCGroup {
+    full_path: PathBuf,
+    user_path: PathBuf,
+    /// tasks in the cgroup and its descendants
+    current: Option<u64>,
+    /// task limit, u64::MAX when unlimited
+    max: Option<u64>,
+    /// forks refused for hitting the limit
+    max_events: Option<u64>,
+    update_time: u64,
+}
+
+pub fn new_pids_cgroup(mount_point: &str, user_path: &Path, cgroup_type: CGroupType) -> PidsCGroup {
+    let full_path: PathBuf = match cgroup_type {
+        CGroupType::V1 => PathBuf::from(format!(
+            "{}/pids/{}",
+            mount_point,
+            user_path.to_string_lossy()
+        )),
+        CGroupType::V2 => PathBuf::from(format!("{}/{}", mount_point, user_path.to_string_lossy())),
+    };
+
+    PidsCGroup {
+        full_path,
+        user_path: user_path.to_path_buf(),
+        ..Default::default()
+    }
+}
+
+impl PidsCGroup {
+    pub fn full_path(&self) -> &Path {
+        self.full_path.as_path()
+    }
+
+    pub fn user_path(&self) -> &Path {
+        self.user_path.as_path()
+    }
+
+    pub fn current(&self) -> Option<u64> {
+        self.current
+    }
+
+    pub fn max(&self) -> Option<u64> {
+        self.max
+    }
+
+    pub fn max_events(&self) -> Option<u64> {
+        self.max_events
+    }
+
+    /// on v2 the interface files only exist when the controller is enabled
+    pub fn exists(&self) -> bool {
+        self.full_path.join("pids.current").exists()
+    }
+
+    fn update_pids_events(&mut self) -> common::Result<bool> {
+        let contents = fs::read_to_string(self.full_path.join("pids.events"))?;
+        self.max_events = utils::parse_cgroup_limit_file(&contents)
+            .get("max")
+            .cloned();
+        Ok(true)
+    }
+
+    pub fn update(&mut self) -> common::Result<()> {
+        self.current = utils::get_cgroup_value(self.full_path.join("pids.current"));
+        self.max = utils::get_cgroup_value(self.full_path.join("pids.max"));
+        if let Err(e) = self.update_pids_events() {
+            warn!(
+                "[pids_cg] update pids events error: {}, path= {}",
+                e,
+                self.full_path.display()
+            );
+        }
+        self.update_time = get_secs_since_epoch();
+        match self.current {
+            Some(_) => Ok(()),
+            None => Err(format!(
+                "read pids.current failed, path= {}",
+                self.full_path.display()
+            )
+            .into()),
+        }
+    }
+
+    pub fn update_ebpf(&mut self) {}
+
+    pub fn reset(&mut self) {
+        *self = Self {
+            full_path: self.full_path.clone(),
+            user_path: self.user_path.clone(),
+            ..Default::default()
+        };
+        self.update_time = get_secs_since_epoch();
+    }
+}
+
+#[cfg(test)]
+mod tests_pids_cg {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_pids_cg() {
+        let mount_point = format!("{}/tests/sample", env::current_dir().unwrap().display());
+        let mut cg = new_pids_cgroup(&mount_point, Path::new("pod_user_path"), CGroupType::V1);
+        assert!(cg.exists());
+        cg.update().unwrap();
+        assert_eq!(cg.current(), Some(42));
+        assert_eq!(cg.max(), Some(4096));
+        assert_eq!(cg.max_events(), Some(7));
+    }
+
+    #[test]
+    fn test_pids_cg_v2() {
+        let mount_point = env::current_dir().unwrap().display().to_string();
+        let mut cg = new_pids_cgroup(
+            &mount_point,
+            Path::new("tests/sample/cgroupv2"),
+            CGroupType::V2,
+        );
+        assert!(cg.exists());
+        cg.update().unwrap();
+        assert_eq!(cg.current(), Some(30));
+        assert_eq!(cg.max(), Some(u64::MAX));
+        assert_eq!(cg.max_events(), Some(0));
+    }
+}
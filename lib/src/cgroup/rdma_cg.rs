@@ -0,0 +1,183 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::cgroup::utils;
+use crate::common;
+use crate::common::CGroupType;
+use crate::system::get_secs_since_epoch;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq, ToSchema)]
+pub struct RdmaDeviceStat {
+    hca_handle: u64,
+    hca_object: u64,
+    /// u64::MAX when unlimited
+    hca_handle_max: Option<u64>,
+    /// u64::MAX when unlimited
+    hca_object_max: Option<u64>,
+}
+
+impl RdmaDeviceStat {
+    pub fn hca_handle(&self) -> u64 {
+        self.hca_handle
+    }
+
+    pub fn hca_object(&self) -> u64 {
+        self.hca_object
+    }
+
+    pub fn hca_handle_max(&self) -> Option<u64> {
+        self.hca_handle_max
+    }
+
+    pub fn hca_object_max(&self) -> Option<u64> {
+        self.hca_object_max
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
+pub struct RdmaCGroup {
+    full_path: PathBuf,
+    user_path: PathBuf,
+    /// keyed by rdma device, e.g. mlx5_0
+    devices: HashMap<String, RdmaDeviceStat>,
+    update_time: u64,
+}
+
+pub fn new_rdma_cgroup(mount_point: &str, user_path: &Path, cgroup_type: CGroupType) -> RdmaCGroup {
+    let full_path: PathBuf = match cgroup_type {
+        CGroupType::V1 => PathBuf::from(format!(
+            "{}/rdma/{}",
+            mount_point,
+            user_path.to_string_lossy()
+        )),
+        CGroupType::V2 => PathBuf::from(format!("{}/{}", mount_point, user_path.to_string_lossy())),
+    };
+
+    RdmaCGroup {
+        full_path,
+        user_path: user_path.to_path_buf(),
+        ..Default::default()
+    }
+}
+
+impl RdmaCGroup {
+    pub fn full_path(&self) -> &Path {
+        self.full_path.as_path()
+    }
+
+    pub fn user_path(&self) -> &Path {
+        self.user_path.as_path()
+    }
+
+    pub fn devices(&self) -> &HashMap<String, RdmaDeviceStat> {
+        &self.devices
+    }
+
+    /// on v2 the interface files only exist when the controller is enabled
+    pub fn exists(&self) -> bool {
+        self.full_path.join("rdma.current").exists()
+    }
+
+    fn update_devices(&mut self) -> common::Result<bool> {
+        // mlx5_0 hca_handle=2 hca_object=2000
+        let current = fs::read_to_string(self.full_path.join("rdma.current"))?;
+        // rdma.max is missing in the root cgroup
+        let max = fs::read_to_string(self.full_path.join("rdma.max")).unwrap_or_default();
+        let max = utils::parse_cgroup_nested_u64(&max);
+
+        self.devices = utils::parse_cgroup_nested_u64(&current)
+            .into_iter()
+            .filter(|(device, _)| !device.is_empty())
+            .map(|(device, current)| {
+                let max = max.get(&device);
+                let stat = RdmaDeviceStat {
+                    hca_handle: current.get("hca_handle").cloned().unwrap_or_default(),
+                    hca_object: current.get("hca_object").cloned().unwrap_or_default(),
+                    hca_handle_max: max.and_then(|m| m.get("hca_handle").cloned()),
+                    hca_object_max: max.and_then(|m| m.get("hca_object").cloned()),
+                };
+                (device, stat)
+            })
+            .collect();
+        Ok(true)
+    }
+
+    pub fn update(&mut self) -> common::Result<()> {
+        let result = self.update_devices();
+        if let Err(e) = &result {
+            warn!(
+                "[rdma_cg] update rdma devices error: {}, path= {}",
+                e,
+                self.full_path.display()
+            );
+        }
+        self.update_time = get_secs_since_epoch();
+        result.map(|_| ())
+    }
+
+    pub fn update_ebpf(&mut self) {}
+
+    pub fn reset(&mut self) {
+        *self = Self {
+            full_path: self.full_path.clone(),
+            user_path: self.user_path.clone(),
+            ..Default::default()
+        };
+        self.update_time = get_secs_since_epoch();
+    }
+}
+
+#[cfg(test)]
+mod tests_rdma_cg {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_rdma_cg() {
+        let mount_point = format!("{}/tests/sample", env::current_dir().unwrap().display());
+        let mut cg = new_rdma_cgroup(&mount_point, Path::new("pod_user_path"), CGroupType::V1);
+        assert!(cg.exists());
+        cg.update().unwrap();
+        let mlx5 = cg.devices().get("mlx5_0").unwrap();
+        assert_eq!(mlx5.hca_handle(), 2);
+        assert_eq!(mlx5.hca_object(), 2000);
+        assert_eq!(mlx5.hca_handle_max(), Some(16));
+        assert_eq!(mlx5.hca_object_max(), Some(u64::MAX));
+    }
+
+    #[test]
+    fn test_rdma_cg_v2() {
+        let mount_point = env::current_dir().unwrap().display().to_string();
+        let mut cg = new_rdma_cgroup(
+            &mount_point,
+            Path::new("tests/sample/cgroupv2"),
+            CGroupType::V2,
+        );
+        assert!(cg.exists());
+        cg.update().unwrap();
+        assert_eq!(cg.devices().len(), 1);
+        assert_eq!(cg.devices().get("mlx5_1").unwrap().hca_handle(), 1);
+        assert_eq!(
+            cg.devices().get("mlx5_1").unwrap().hca_handle_max(),
+            Some(u64::MAX)
+        );
+    }
+}
@@ -0,0 +1,318 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::cgroup::CGroupMetrics;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::ops::Add;
+use std::path::{Component, Path, PathBuf};
+use utoipa::ToSchema;
+
+/// usage of a cgroup, or summed over cgroups; only current usage and rates between
+/// the last two updates, a cumulative counter keeps what exited children used and
+/// would show up as unaccounted forever. A field is none when its section is missing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RollupUsage {
+    /// cores used between the last two updates
+    pub cpu_usage_ratio: Option<f64>,
+    pub memory_usage_bytes: Option<u64>,
+    pub working_set_bytes: Option<u64>,
+    pub io_read_bytes_per_sec: Option<f64>,
+    pub io_write_bytes_per_sec: Option<f64>,
+}
+
+/// none only when neither side has a value
+fn add<T: Add<Output = T>>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a + b),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+impl RollupUsage {
+    fn add(&mut self, other: &RollupUsage) {
+        self.cpu_usage_ratio = add(self.cpu_usage_ratio, other.cpu_usage_ratio);
+        self.memory_usage_bytes = add(self.memory_usage_bytes, other.memory_usage_bytes);
+        self.working_set_bytes = add(self.working_set_bytes, other.working_set_bytes);
+        self.io_read_bytes_per_sec = add(self.io_read_bytes_per_sec, other.io_read_bytes_per_sec);
+        self.io_write_bytes_per_sec =
+            add(self.io_write_bytes_per_sec, other.io_write_bytes_per_sec);
+    }
+
+    /// clears the fields `other` has no value of
+    fn retain_reported(&mut self, other: &RollupUsage) {
+        fn retain<T>(a: &mut Option<T>, b: Option<T>) {
+            if b.is_none() {
+                *a = None;
+            }
+        }
+        retain(&mut self.cpu_usage_ratio, other.cpu_usage_ratio);
+        retain(&mut self.memory_usage_bytes, other.memory_usage_bytes);
+        retain(&mut self.working_set_bytes, other.working_set_bytes);
+        retain(&mut self.io_read_bytes_per_sec, other.io_read_bytes_per_sec);
+        retain(
+            &mut self.io_write_bytes_per_sec,
+            other.io_write_bytes_per_sec,
+        );
+    }
+
+    /// clamped at zero, children sampled later than their parent may exceed it;
+    /// none unless both sides have a value
+    fn saturating_sub(&self, other: &RollupUsage) -> RollupUsage {
+        let sub_f64 = |a: Option<f64>, b: Option<f64>| Some((a? - b?).max(0.0));
+        let sub_u64 = |a: Option<u64>, b: Option<u64>| Some(a?.saturating_sub(b?));
+        RollupUsage {
+            cpu_usage_ratio: sub_f64(self.cpu_usage_ratio, other.cpu_usage_ratio),
+            memory_usage_bytes: sub_u64(self.memory_usage_bytes, other.memory_usage_bytes),
+            working_set_bytes: sub_u64(self.working_set_bytes, other.working_set_bytes),
+            io_read_bytes_per_sec: sub_f64(self.io_read_bytes_per_sec, other.io_read_bytes_per_sec),
+            io_write_bytes_per_sec: sub_f64(
+                self.io_write_bytes_per_sec,
+                other.io_write_bytes_per_sec,
+            ),
+        }
+    }
+}
+
+impl From<&CGroupMetrics> for RollupUsage {
+    fn from(metrics: &CGroupMetrics) -> Self {
+        let memory = metrics.memory.as_ref();
+        let throughput = metrics.io.as_ref().and_then(|io| io.throughput);
+        RollupUsage {
+            cpu_usage_ratio: metrics.cpu.as_ref().map(|cpu| cpu.usage_ratio as f64),
+            memory_usage_bytes: memory.and_then(|memory| memory.usage_bytes),
+            working_set_bytes: memory.and_then(|memory| memory.working_set_bytes),
+            io_read_bytes_per_sec: throughput.map(|t| t.read_bytes_per_sec),
+            io_write_bytes_per_sec: throughput.map(|t| t.write_bytes_per_sec),
+        }
+    }
+}
+
+/// a cgroup with its tracked descendants aggregated into it
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct CGroupRollup {
+    pub user_path: PathBuf,
+    /// usage the kernel reports for the whole subtree, none when the cgroup
+    /// itself is not tracked
+    pub usage: Option<RollupUsage>,
+    /// sum over the children reporting each field, an untracked child counts as the
+    /// sum of its own children for the fields all of them report
+    pub children_usage: RollupUsage,
+    /// usage which no tracked descendant accounts for, none without usage or children;
+    /// a field is none unless every child reports it, as the usage of the silent
+    /// children would count as unaccounted
+    pub unaccounted: Option<RollupUsage>,
+    pub children: Vec<CGroupRollup>,
+}
+
+impl CGroupRollup {
+    /// the usage this subtree adds to its parent
+    fn total(&self) -> RollupUsage {
+        self.usage.unwrap_or_else(|| self.reported_children_usage())
+    }
+
+    /// `children_usage` of the fields every child reports
+    fn reported_children_usage(&self) -> RollupUsage {
+        let mut usage = self.children_usage;
+        for child in self.children.iter() {
+            usage.retain_reported(&child.total());
+        }
+        usage
+    }
+}
+
+#[derive(Default)]
+struct RollupBuilder {
+    usage: Option<RollupUsage>,
+    children: BTreeMap<OsString, RollupBuilder>,
+}
+
+impl RollupBuilder {
+    fn build(self, user_path: PathBuf) -> CGroupRollup {
+        let children: Vec<CGroupRollup> = self
+            .children
+            .into_iter()
+            .map(|(name, child)| child.build(user_path.join(name)))
+            .collect();
+        let mut children_usage = RollupUsage::default();
+        for child in children.iter() {
+            children_usage.add(&child.total());
+        }
+        let mut rollup = CGroupRollup {
+            user_path,
+            usage: self.usage,
+            children_usage,
+            unaccounted: None,
+            children,
+        };
+        if let Some(usage) = rollup.usage.filter(|_| !rollup.children.is_empty()) {
+            rollup.unaccounted = Some(usage.saturating_sub(&rollup.reported_children_usage()));
+        }
+        rollup
+    }
+}
+
+/// path components without the leading `/`, so that `/kubepods` and `kubepods` match
+fn path_names(path: &Path) -> Vec<OsString> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_os_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// the tree of the given cgroups under `root`, cgroups elsewhere are ignored and
+/// cgroups between tracked ones show up without usage of their own
+pub fn rollup_cgroups<I>(root: &Path, cgroups: I) -> CGroupRollup
+where
+    I: IntoIterator<Item = CGroupMetrics>,
+{
+    let root_names = path_names(root);
+    let mut tree = RollupBuilder::default();
+    for metrics in cgroups {
+        let names = path_names(&metrics.user_path);
+        if !names.starts_with(&root_names) {
+            continue;
+        }
+        let mut node = &mut tree;
+        for name in &names[root_names.len()..] {
+            node = node.children.entry(name.clone()).or_default();
+        }
+        node.usage = Some(RollupUsage::from(&metrics));
+    }
+    tree.build(root.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests_rollup {
+    use super::*;
+    use crate::cgroup::{CpuMetrics, IoMetrics, IoThroughput, MemoryMetrics};
+    use crate::common::CGroupType;
+
+    fn metrics(user_path: &str, cpu_usage_ratio: f32, memory_usage_bytes: u64) -> CGroupMetrics {
+        CGroupMetrics {
+            user_path: PathBuf::from(user_path),
+            cgroup_type: CGroupType::V2,
+            cpu: Some(CpuMetrics {
+                usage_ns: 1_000_000_000_000,
+                usage_ratio: cpu_usage_ratio,
+                ..Default::default()
+            }),
+            memory: Some(MemoryMetrics {
+                usage_bytes: Some(memory_usage_bytes),
+                ..Default::default()
+            }),
+            io: None,
+            update_time: 0,
+            last_error: None,
+        }
+    }
+
+    #[test]
+    fn test_rollup_cgroups() {
+        let cgroups = vec![
+            metrics("/kubepods/burstable", 4.0, 10000),
+            metrics("/kubepods/burstable/pod1", 1.5, 4000),
+            metrics("/kubepods/burstable/pod1/c1", 1.0, 3000),
+            // the parent pod2 is not tracked
+            metrics("kubepods/burstable/pod2/c1", 0.5, 1000),
+            metrics("kubepods/burstable/pod2/c2", 0.5, 2000),
+            metrics("/kubepods/besteffort/pod3", 2.0, 500),
+        ];
+        let tree = rollup_cgroups(Path::new("/kubepods/burstable"), cgroups);
+
+        assert_eq!(tree.user_path, PathBuf::from("/kubepods/burstable"));
+        assert_eq!(tree.children.len(), 2);
+        assert_eq!(tree.children_usage.cpu_usage_ratio, Some(2.5));
+        assert_eq!(tree.children_usage.memory_usage_bytes, Some(7000));
+        let unaccounted = tree.unaccounted.unwrap();
+        assert_eq!(unaccounted.cpu_usage_ratio, Some(1.5));
+        assert_eq!(unaccounted.memory_usage_bytes, Some(3000));
+
+        let pod1 = &tree.children[0];
+        assert_eq!(pod1.user_path, PathBuf::from("/kubepods/burstable/pod1"));
+        assert_eq!(pod1.unaccounted.unwrap().memory_usage_bytes, Some(1000));
+        assert_eq!(pod1.children[0].unaccounted, None);
+
+        let pod2 = &tree.children[1];
+        assert_eq!(pod2.usage, None);
+        assert_eq!(pod2.unaccounted, None);
+        assert_eq!(pod2.children_usage.memory_usage_bytes, Some(3000));
+
+        let tree = rollup_cgroups(Path::new("/system.slice"), vec![]);
+        assert!(tree.children.is_empty());
+        assert_eq!(tree.usage, None);
+    }
+
+    #[test]
+    fn test_rollup_missing_sections() {
+        let mut parent = metrics("/kubepods", 2.0, 8000);
+        parent.io = Some(IoMetrics {
+            throughput: Some(IoThroughput {
+                read_bytes_per_sec: 100.0,
+                write_bytes_per_sec: 50.0,
+            }),
+            ..Default::default()
+        });
+        // no memory controller and no io throughput yet
+        let mut child = metrics("/kubepods/pod1", 0.5, 0);
+        child.memory = None;
+        child.io = Some(IoMetrics::default());
+
+        let tree = rollup_cgroups(Path::new("/kubepods"), vec![parent, child]);
+        assert_eq!(tree.children_usage.cpu_usage_ratio, Some(0.5));
+        assert_eq!(tree.children_usage.memory_usage_bytes, None);
+        assert_eq!(tree.children_usage.io_read_bytes_per_sec, None);
+        // a gap is only reported where both sides are known
+        let unaccounted = tree.unaccounted.unwrap();
+        assert_eq!(unaccounted.cpu_usage_ratio, Some(1.5));
+        assert_eq!(unaccounted.memory_usage_bytes, None);
+        assert_eq!(unaccounted.io_read_bytes_per_sec, None);
+        assert_eq!(tree.children[0].usage.unwrap().memory_usage_bytes, None);
+    }
+
+    #[test]
+    fn test_rollup_partially_reported() {
+        let parent = metrics("/kubepods", 2.0, 8000);
+        let pod1 = metrics("/kubepods/pod1", 0.5, 3000);
+        // pod2 has no memory controller
+        let mut pod2 = metrics("/kubepods/pod2", 0.5, 0);
+        pod2.memory = None;
+        // the parent pod3 is not tracked, nor does c2 report memory
+        let pod3_c1 = metrics("/kubepods/pod3/c1", 0.25, 1000);
+        let mut pod3_c2 = metrics("/kubepods/pod3/c2", 0.25, 0);
+        pod3_c2.memory = None;
+
+        let tree = rollup_cgroups(
+            Path::new("/kubepods"),
+            vec![parent, pod1, pod2, pod3_c1, pod3_c2],
+        );
+        assert_eq!(tree.children_usage.cpu_usage_ratio, Some(1.5));
+        // pod3 counts without memory, as c2 does not report it
+        assert_eq!(tree.children_usage.memory_usage_bytes, Some(3000));
+        let pod3 = &tree.children[2];
+        assert_eq!(pod3.children_usage.memory_usage_bytes, Some(1000));
+        assert_eq!(pod3.total().memory_usage_bytes, None);
+        assert_eq!(pod3.total().cpu_usage_ratio, Some(0.5));
+
+        let unaccounted = tree.unaccounted.unwrap();
+        assert_eq!(unaccounted.cpu_usage_ratio, Some(0.5));
+        // a partial sum would report 5000 bytes unaccounted
+        assert_eq!(unaccounted.memory_usage_bytes, None);
+    }
+}
@@ -28,14 +28,15 @@ pub fn parse_cgroup_file(contents: &str) -> HashMap<&str, u64> {
     // total_cache 167586779136
     // total_rss 223102943232
     // ....
+    // lines whose value is not a number are treated like empty lines
     contents
         .split('\n')
         .into_iter()
         .map(|s| {
             let mut s = s.split_whitespace();
             if let Some(key) = s.next() {
-                if let Some(value) = s.next() {
-                    (key, value.parse::<u64>().unwrap())
+                if let Some(Ok(value)) = s.next().map(|v| v.parse::<u64>()) {
+                    (key, value)
                 } else {
                     ("", 0)
                 }
@@ -47,6 +48,25 @@ pub fn parse_cgroup_file(contents: &str) -> HashMap<&str, u64> {
         .collect()
 }
 
+pub fn parse_cgroup_limit_file(contents: &str) -> HashMap<String, u64> {
+    // parse file like:
+    // max 0
+    // res_a max
+    // `max` reads as u64::MAX, lines whose value is not a number are skipped
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut s = line.split_whitespace();
+            let key = s.next()?;
+            let value = match s.next()? {
+                "max" => u64::MAX,
+                value => value.parse::<u64>().ok()?,
+            };
+            Some((String::from(key), value))
+        })
+        .collect()
+}
+
 pub fn parse_cgroup_numa_stat_file(contents: &str) -> HashMap<&str, HashMap<&str, u64>> {
     // parse file like:
     // total=638459 N0=49505 N1=589301
@@ -192,6 +212,24 @@ mod tests_utils {
         correct_content.insert("total_rss", 223102943232);
         correct_content.insert("", 0);
         assert_eq!(output, correct_content);
+
+        let output = parse_cgroup_file(
+            "anon 4096
+unknown_key -
+",
+        );
+        assert_eq!(output.get("anon"), Some(&4096));
+        assert_eq!(output.get("unknown_key"), None);
+    }
+
+    #[test]
+    fn test_parse_cgroup_limit_file() {
+        let file_content = "res_a 3\nres_b max\nres_c -\n\n";
+        let output = parse_cgroup_limit_file(file_content);
+        let mut correct_content: HashMap<String, u64> = HashMap::new();
+        correct_content.insert(String::from("res_a"), 3);
+        correct_content.insert(String::from("res_b"), u64::MAX);
+        assert_eq!(output, correct_content);
     }
 
     #[test]
@@ -0,0 +1,177 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::cgroup::CGroupUserPath;
+use crate::common;
+use crate::common::CGroupType;
+use log::{debug, warn};
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CGroupWatchEvent {
+    Created(CGroupUserPath),
+    Removed(CGroupUserPath),
+}
+
+/// Watches a cgroup hierarchy with inotify and reports cgroup directories
+/// as they are created and removed.
+pub struct CGroupWatcher {
+    inotify: Inotify,
+    hierarchy: PathBuf,
+    watches: HashMap<WatchDescriptor, CGroupUserPath>,
+}
+
+impl CGroupWatcher {
+    pub fn new(mount_point: &Path, cgroup_type: CGroupType) -> common::Result<CGroupWatcher> {
+        // v1 controllers share the same hierarchy, watch the memory one
+        let hierarchy = match cgroup_type {
+            CGroupType::V1 => mount_point.join("memory"),
+            CGroupType::V2 => mount_point.to_path_buf(),
+        };
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC | InitFlags::IN_NONBLOCK)?;
+        let mut watcher = CGroupWatcher {
+            inotify,
+            hierarchy,
+            watches: HashMap::new(),
+        };
+        let mut existing = vec![];
+        watcher.add_watch_recursive(Path::new("/"), &mut existing);
+        Ok(watcher)
+    }
+
+    pub fn nr_watches(&self) -> usize {
+        self.watches.len()
+    }
+
+    fn add_watch_recursive(&mut self, user_path: &Path, found: &mut Vec<CGroupUserPath>) {
+        let dir = common::host_path(&self.hierarchy, user_path);
+        let flags = AddWatchFlags::IN_CREATE | AddWatchFlags::IN_DELETE | AddWatchFlags::IN_ONLYDIR;
+        match self.inotify.add_watch(&dir, flags) {
+            Ok(wd) => {
+                self.watches.insert(wd, user_path.to_path_buf());
+            }
+            Err(e) => {
+                warn!("[watcher] add watch {} error: {}", dir.display(), e);
+                return;
+            }
+        }
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                let child = user_path.join(entry.file_name());
+                found.push(child.clone());
+                self.add_watch_recursive(&child, found);
+            }
+        }
+    }
+
+    /// wait up to timeout_ms for cgroup changes, an empty list means timeout
+    pub fn wait_events(&mut self, timeout_ms: i32) -> common::Result<Vec<CGroupWatchEvent>> {
+        let mut fds = [PollFd::new(self.inotify.as_raw_fd(), PollFlags::POLLIN)];
+        match poll(&mut fds, timeout_ms) {
+            Ok(0) => return Ok(vec![]),
+            Ok(_) => {}
+            Err(Errno::EINTR) => return Ok(vec![]),
+            Err(e) => return Err(Box::new(e)),
+        }
+
+        let inotify_events = match self.inotify.read_events() {
+            Ok(events) => events,
+            Err(Errno::EAGAIN) => return Ok(vec![]),
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let mut events = vec![];
+        for event in inotify_events {
+            if event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+                warn!("[watcher] inotify queue overflow, events are lost");
+                continue;
+            }
+            if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+                self.watches.remove(&event.wd);
+                continue;
+            }
+            if !event.mask.contains(AddWatchFlags::IN_ISDIR) {
+                continue;
+            }
+            let (parent, name) = match (self.watches.get(&event.wd), event.name) {
+                (Some(parent), Some(name)) => (parent, name),
+                _ => continue,
+            };
+            let user_path = parent.join(name);
+            debug!("[watcher] {:?} {}", event.mask, user_path.display());
+
+            if event.mask.contains(AddWatchFlags::IN_CREATE) {
+                // children may be created before the watch is in place
+                let mut found = vec![];
+                self.add_watch_recursive(&user_path, &mut found);
+                events.push(CGroupWatchEvent::Created(user_path));
+                events.extend(found.into_iter().map(CGroupWatchEvent::Created));
+            } else if event.mask.contains(AddWatchFlags::IN_DELETE) {
+                events.push(CGroupWatchEvent::Removed(user_path));
+            }
+        }
+        Ok(events)
+    }
+}
+
+impl Drop for CGroupWatcher {
+    fn drop(&mut self) {
+        let _ = nix::unistd::close(self.inotify.as_raw_fd());
+    }
+}
+
+#[cfg(test)]
+mod tests_watcher {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_cgroup_watcher() {
+        let mount_point = env::temp_dir().join(format!("malachite_watcher_{}", std::process::id()));
+        fs::create_dir_all(mount_point.join("kubepods")).unwrap();
+
+        let mut watcher = CGroupWatcher::new(&mount_point, CGroupType::V2).unwrap();
+        assert_eq!(watcher.nr_watches(), 2);
+        assert!(watcher.wait_events(0).unwrap().is_empty());
+
+        fs::create_dir_all(mount_point.join("kubepods/pod1")).unwrap();
+        let events = watcher.wait_events(1000).unwrap();
+        assert_eq!(
+            events,
+            vec![CGroupWatchEvent::Created(PathBuf::from("/kubepods/pod1"))]
+        );
+        assert_eq!(watcher.nr_watches(), 3);
+
+        fs::remove_dir(mount_point.join("kubepods/pod1")).unwrap();
+        let events = watcher.wait_events(1000).unwrap();
+        assert_eq!(
+            events,
+            vec![CGroupWatchEvent::Removed(PathBuf::from("/kubepods/pod1"))]
+        );
+
+        fs::remove_dir_all(&mount_point).unwrap();
+    }
+}
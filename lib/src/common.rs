@@ -14,15 +14,59 @@ limitations under the License.
 */
 
 use nix::sys::statfs::*;
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use serde::{Deserialize, Serialize};
 use std::error;
 use std::fs::File;
+use std::path::{Path, PathBuf};
 use utoipa::ToSchema;
 
 pub static MOUNT_POINT: &str = "/sys/fs/cgroup";
 pub(crate) type Result<T> = std::result::Result<T, Box<dyn error::Error>>;
 
+static HOST_ROOT: OnceCell<PathBuf> = OnceCell::new();
+
+/// set the root that /proc, /sys and the cgroup mount point are read under,
+/// e.g. "/host" when the host filesystem is bind-mounted into a container.
+/// must be called before MODULE_LIST or any System is created.
+pub fn set_host_root<P: AsRef<Path>>(root: P) -> std::result::Result<(), PathBuf> {
+    HOST_ROOT.set(root.as_ref().to_path_buf())
+}
+
+pub fn host_root() -> &'static Path {
+    HOST_ROOT
+        .get()
+        .map(|root| root.as_path())
+        .unwrap_or_else(|| Path::new("/"))
+}
+
+/// resolve an absolute host path, such as "/proc/stat", under root
+pub fn host_path<P: AsRef<Path>>(root: &Path, path: P) -> PathBuf {
+    let path = path.as_ref();
+    root.join(path.strip_prefix("/").unwrap_or(path))
+}
+
+static CGROUP_MOUNT_POINT: OnceCell<PathBuf> = OnceCell::new();
+
+/// use another cgroup fs mount point than /sys/fs/cgroup, it is not resolved under the host root.
+/// must be called before MODULE_LIST or any System is created.
+pub fn set_cgroup_mount_point<P: AsRef<Path>>(path: P) -> std::result::Result<(), PathBuf> {
+    CGROUP_MOUNT_POINT.set(path.as_ref().to_path_buf())
+}
+
+/// cgroup fs mount point under root, unless overridden
+pub fn cgroup_mount_point_in(root: &Path) -> PathBuf {
+    CGROUP_MOUNT_POINT
+        .get()
+        .cloned()
+        .unwrap_or_else(|| host_path(root, MOUNT_POINT))
+}
+
+/// cgroup fs mount point under the configured host root, unless overridden
+pub fn cgroup_mount_point() -> PathBuf {
+    cgroup_mount_point_in(host_root())
+}
+
 #[derive(Clone, Debug, Copy, Eq, Deserialize, Serialize, PartialEq, Hash, ToSchema)]
 pub enum CGroupType {
     V1,
@@ -33,14 +77,26 @@ fn get_cgroup_type_config() -> Option<CGroupType> {
     None
 }
 
+/// version of the cgroup fs mounted under root, none when its mount point can't be read
 #[cfg(target_os = "linux")]
-fn _get_cgroup_type() -> (CGroupType, bool) {
-    let file = File::open(MOUNT_POINT).expect("[lib] stat cgroup mount point failed");
-    let fs = fstatfs(&file).expect("[lib] fstatfs cg file error");
+pub fn cgroup_type_in(root: &Path) -> Option<CGroupType> {
+    let file = File::open(cgroup_mount_point_in(root)).ok()?;
+    let fs = fstatfs(&file).ok()?;
     if fs.filesystem_type() == CGROUP2_SUPER_MAGIC {
-        return (CGroupType::V2, true);
+        return Some(CGroupType::V2);
     }
-    (CGroupType::V1, true)
+    Some(CGroupType::V1)
+}
+
+#[cfg(target_os = "macos")]
+pub fn cgroup_type_in(_root: &Path) -> Option<CGroupType> {
+    Some(CGroupType::V1)
+}
+
+#[cfg(target_os = "linux")]
+fn _get_cgroup_type() -> (CGroupType, bool) {
+    let cgroup_type = cgroup_type_in(host_root()).expect("[lib] stat cgroup mount point failed");
+    (cgroup_type, true)
 }
 
 fn get_cgroup_type(_args: Option<CGroupType>) -> (CGroupType, bool) {
@@ -101,3 +157,31 @@ impl ModuleList {
 }
 
 pub static MODULE_LIST: Lazy<ModuleList> = Lazy::new(ModuleList::new);
+
+#[cfg(test)]
+mod tests_common {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::process;
+
+    #[test]
+    fn test_cgroup_type_in() {
+        let root = env::temp_dir().join(format!("malachite-common-{}", process::id()));
+        fs::create_dir_all(root.join("sys/fs")).unwrap();
+        assert_eq!(cgroup_type_in(&root), None);
+
+        // detected under the root, not under the host root
+        fs::create_dir(root.join("sys/fs/cgroup")).unwrap();
+        assert_eq!(cgroup_type_in(&root), Some(CGroupType::V1));
+        fs::remove_dir(root.join("sys/fs/cgroup")).unwrap();
+
+        symlink(cgroup_mount_point(), root.join("sys/fs/cgroup")).unwrap();
+        assert_eq!(
+            cgroup_type_in(&root),
+            Some(MODULE_LIST.cgroup_type.actual_status())
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -13,13 +13,17 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::common;
+use crate::common::host_path;
 use crate::system::get_secs_since_epoch;
 use log::debug;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
+use std::num::ParseIntError;
 use std::ops::{Deref, DerefMut, Sub};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use utoipa::ToSchema;
 
 ///  name：指示CPU核
@@ -347,13 +351,13 @@ impl SystemProcessorInfo {
     }
 
     #[cfg(not(tarpaulin_include))]
-    pub fn refresh(&mut self) {
-        self.refresh_processor_stat(PathBuf::from("/proc/stat"));
-        self.refresh_processor_sched_wait(PathBuf::from("/proc/schedstat"));
+    pub fn refresh(&mut self, root: &Path) -> common::Result<()> {
+        self.refresh_processor_stat(host_path(root, "/proc/stat"))?;
+        self.refresh_processor_sched_wait(host_path(root, "/proc/schedstat"))
     }
 
-    pub fn refresh_processor_sched_wait(&mut self, path: PathBuf) {
-        let file_data = fs::read_to_string(&path).unwrap();
+    pub fn refresh_processor_sched_wait(&mut self, path: PathBuf) -> common::Result<()> {
+        let file_data = fs::read_to_string(&path)?;
 
         file_data.split('\n').skip(2).step_by(2).for_each(|line| {
             let mut iter = line.split_whitespace();
@@ -366,6 +370,7 @@ impl SystemProcessorInfo {
                 }
             }
         });
+        Ok(())
     }
     pub fn reset_processor_sched_wait(&mut self, path: PathBuf) {
         let file_data = fs::read_to_string(&path).unwrap();
@@ -382,8 +387,8 @@ impl SystemProcessorInfo {
             }
         });
     }
-    pub fn refresh_processor_stat(&mut self, path: PathBuf) {
-        let file_data = fs::read_to_string(&path).unwrap();
+    pub fn refresh_processor_stat(&mut self, path: PathBuf) -> common::Result<()> {
+        let file_data = fs::read_to_string(&path)?;
         file_data
             .split('\n')
             .filter(|s| s.starts_with("cpu"))
@@ -411,6 +416,7 @@ impl SystemProcessorInfo {
                     p.update_processor_info(info)
                 }
             });
+        Ok(())
     }
 
     pub fn reset_processor_stat(&mut self, path: PathBuf) {
@@ -431,9 +437,9 @@ impl SystemProcessorInfo {
             });
     }
 
-    pub fn reset(&mut self) {
-        self.reset_processor_stat(PathBuf::from("/proc/stat"));
-        self.reset_processor_sched_wait(PathBuf::from("/proc/schedstat"));
+    pub fn reset(&mut self, root: &Path) {
+        self.reset_processor_stat(host_path(root, "/proc/stat"));
+        self.reset_processor_sched_wait(host_path(root, "/proc/schedstat"));
     }
 }
 
@@ -467,6 +473,9 @@ impl NodeVec {
     pub fn meta(&self) -> &String {
         &self.meta
     }
+
+    /// largest id accepted from users, the kernel supports at most 8192 cpus
+    pub const MAX_ID: usize = 8191;
 }
 
 impl From<String> for NodeVec {
@@ -493,6 +502,43 @@ impl From<String> for NodeVec {
     }
 }
 
+/// fails on invalid input unlike `From<String>`, for lists given by users: ranges
+/// must not be reversed and ids not exceed `MAX_ID`; the ids are sorted and deduplicated
+impl FromStr for NodeVec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_id = |id: &str| {
+            let id = id
+                .parse::<usize>()
+                .map_err(|e: ParseIntError| format!("{:?}: {}", id, e))?;
+            if id > NodeVec::MAX_ID {
+                return Err(format!("{} exceeds the largest id {}", id, NodeVec::MAX_ID));
+            }
+            Ok(id)
+        };
+        let mut inner = BTreeSet::new();
+        for item in s.split(',') {
+            match item.split_once('-') {
+                Some((lower, upper)) => {
+                    let (lower, upper) = (parse_id(lower)?, parse_id(upper)?);
+                    if lower > upper {
+                        return Err(format!("range {} is reversed", item));
+                    }
+                    inner.extend(lower..=upper);
+                }
+                None => {
+                    inner.insert(parse_id(item)?);
+                }
+            }
+        }
+        Ok(NodeVec {
+            meta: s.to_string(),
+            inner: inner.into_iter().collect(),
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -509,6 +555,32 @@ mod tests {
         assert_eq!(node_vec.inner, correct);
     }
 
+    #[test]
+    fn test_node_vec_from_str() {
+        let node_vec: NodeVec = "0-2,5".parse().unwrap();
+        assert_eq!(node_vec.inner, vec![0, 1, 2, 5]);
+        let node_vec: NodeVec = "5,1-3,2".parse().unwrap();
+        assert_eq!(node_vec.inner, vec![1, 2, 3, 5]);
+        assert_eq!(node_vec.meta, "5,1-3,2");
+        assert!("0-x".parse::<NodeVec>().is_err());
+        assert!("".parse::<NodeVec>().is_err());
+
+        // reversed ranges used to select nothing
+        assert_eq!(
+            "5-2".parse::<NodeVec>().unwrap_err(),
+            "range 5-2 is reversed"
+        );
+        assert!("3-3".parse::<NodeVec>().is_ok());
+
+        // a huge range must fail before it is expanded
+        assert!(format!("0-{}", NodeVec::MAX_ID).parse::<NodeVec>().is_ok());
+        assert!(format!("0-{}", NodeVec::MAX_ID + 1)
+            .parse::<NodeVec>()
+            .is_err());
+        assert!("0-18446744073709551615".parse::<NodeVec>().is_err());
+        assert!("99999999".parse::<NodeVec>().is_err());
+    }
+
     #[test]
     fn test_processor_info() {
         let mut info = ProcessorInfo::default();
@@ -550,8 +622,12 @@ mod tests {
             current_path.to_string_lossy()
         ));
 
-        system_processor_info.refresh_processor_stat(PathBuf::from(&proc_stat_file));
-        system_processor_info.refresh_processor_sched_wait(proc_schedstat_file);
+        system_processor_info
+            .refresh_processor_stat(PathBuf::from(&proc_stat_file))
+            .unwrap();
+        system_processor_info
+            .refresh_processor_sched_wait(proc_schedstat_file)
+            .unwrap();
 
         assert_eq!(system_processor_info.inner.len(), 8 as usize);
 
@@ -610,7 +686,9 @@ mod tests {
             2.0316353
         );
 
-        system_processor_info.refresh_processor_stat(PathBuf::from(&proc_stat_file));
+        system_processor_info
+            .refresh_processor_stat(PathBuf::from(&proc_stat_file))
+            .unwrap();
 
         assert_eq!(
             system_processor_info
@@ -33,6 +33,7 @@ use std::collections::HashMap;
 use std::ffi::CString;
 use std::ops::Sub;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
 use utoipa::ToSchema;
 
@@ -142,6 +143,13 @@ pub fn is_bpf_moudule_valid(module: i32) -> bool {
     true
 }
 
+/// set while bpf programs are loaded, so that they are freed on shutdown
+static BPF_INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+pub fn is_bpf_initialized() -> bool {
+    BPF_INITIALIZED.load(Ordering::SeqCst)
+}
+
 #[cfg(not(tarpaulin_include))]
 pub fn wrapper_init_bpf(mask: ModuleMask) -> ::std::os::raw::c_int {
     let opts = init_options {
@@ -152,12 +160,16 @@ pub fn wrapper_init_bpf(mask: ModuleMask) -> ::std::os::raw::c_int {
     unsafe {
         let code = init_bpf(p_opts);
         let _x = Box::from_raw(p_opts);
+        if code == 0 {
+            BPF_INITIALIZED.store(true, Ordering::SeqCst);
+        }
         code
     }
 }
 
 #[cfg(not(tarpaulin_include))]
 pub fn wrapper_free_bpf() {
+    BPF_INITIALIZED.store(false, Ordering::SeqCst);
     unsafe { free_bpf() }
 }
 
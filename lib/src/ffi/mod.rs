@@ -22,3 +22,17 @@ pub mod pmu;
 pub use bpf::*;
 pub use common::*;
 pub use pmu::*;
+
+use log::info;
+
+/// free the bpf programs and PMU counters which are set up, e.g. on shutdown
+pub fn release() {
+    if is_bpf_initialized() {
+        wrapper_free_bpf();
+        info!("[ffi] release bpf resource");
+    }
+    if is_byteperf_initialized() {
+        wrapper_byteperf_destroy_malachite();
+        info!("[ffi] release byteperf resource");
+    }
+}
@@ -25,11 +25,13 @@ use crate::ffi::pmu::ffi::{
 };
 use crate::ffi::{ModuleMask, ModuleMaskConfig};
 use log::info;
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use std::collections::HashMap;
 use std::env;
 use std::error::Error;
 use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Once;
 use std::thread::sleep;
 use std::time::Duration;
@@ -202,19 +204,42 @@ impl Default for byteperf_count_buffer_malachite {
     }
 }
 
+/// set while byteperf holds PMU counters, so that they are released on shutdown
+static BYTEPERF_INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+pub fn is_byteperf_initialized() -> bool {
+    BYTEPERF_INITIALIZED.load(Ordering::SeqCst)
+}
+
 #[cfg(not(tarpaulin_include))]
 pub fn wrapper_byteperf_destroy_malachite() {
+    BYTEPERF_INITIALIZED.store(false, Ordering::SeqCst);
     unsafe { byteperf_destroy_malachite() }
 }
 
+static PMU_CONFIG_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+/// use another PMU config dir than `static/pmu_config` next to the binary.
+/// must be called before byteperf is set up.
+pub fn set_pmu_config_dir<P: AsRef<Path>>(path: P) -> Result<(), PathBuf> {
+    PMU_CONFIG_DIR.set(path.as_ref().to_path_buf())
+}
+
+fn pmu_config_dir() -> PathBuf {
+    PMU_CONFIG_DIR.get().cloned().unwrap_or_else(|| {
+        let mut p = env::current_exe().unwrap();
+        p.pop();
+        p.push("static/pmu_config");
+        p
+    })
+}
+
 #[cfg(not(tarpaulin_include))]
 pub fn wrapper_byteperf_setup_malachite() -> Result<(), Box<dyn Error>> {
     let mut is_failed = false;
 
     WRAPPER_BYTEPERF_INIT_GATHER_FUNC.call_once(|| {
-        let mut p = env::current_exe().unwrap();
-        p.pop();
-        p.push("static/pmu_config");
+        let p = pmu_config_dir();
         let xml_root = CString::new(p.to_str().unwrap()).unwrap().into_raw();
         let xml_module = CString::new("basic").unwrap().into_raw();
 
@@ -241,6 +266,8 @@ pub fn wrapper_byteperf_setup_malachite() -> Result<(), Box<dyn Error>> {
                 info!("byteperf_setup_malachite failed: {}", code);
                 info!("xml_root = {:?}", p.to_str().unwrap());
                 is_failed = true;
+            } else {
+                BYTEPERF_INITIALIZED.store(true, Ordering::SeqCst);
             }
             let _ = CString::from_raw(xml_root);
             let _ = CString::from_raw(xml_module);
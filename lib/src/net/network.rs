@@ -14,13 +14,15 @@ limitations under the License.
 */
 
 #![allow(dead_code)]
+use crate::common;
+use crate::common::host_path;
 use crate::net::utils::parse_net_file;
 use crate::system::get_secs_since_epoch;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use utoipa::ToSchema;
 
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
@@ -64,11 +66,12 @@ impl NetInfo {
     }
 
     #[cfg(not(tarpaulin_include))]
-    pub fn refresh(&mut self) {
-        self.refresh_snmp_data(PathBuf::from("/proc/net/snmp"));
-        self.refresh_netstat_data(PathBuf::from("/proc/net/netstat"));
-        //self.refresh_tcp_close_wait(PathBuf::from("/proc/net/tcp"));
+    pub fn refresh(&mut self, root: &Path) -> common::Result<()> {
+        self.refresh_snmp_data(host_path(root, "/proc/net/snmp"))?;
+        self.refresh_netstat_data(host_path(root, "/proc/net/netstat"))?;
+        //self.refresh_tcp_close_wait(host_path(root, "/proc/net/tcp"));
         self.update_time = get_secs_since_epoch();
+        Ok(())
     }
 
     fn refresh_tcp_close_wait(&mut self, path: PathBuf) {
@@ -88,10 +91,12 @@ impl NetInfo {
             .count();
     }
 
-    fn refresh_netstat_data(&mut self, path: PathBuf) {
-        let file_data = fs::read_to_string(&path).unwrap();
+    fn refresh_netstat_data(&mut self, path: PathBuf) -> common::Result<()> {
+        let file_data = fs::read_to_string(&path)?;
         let contents = parse_net_file(&file_data);
-        let tcp_contents = contents.get(&"TcpExt").unwrap();
+        let tcp_contents = contents
+            .get(&"TcpExt")
+            .ok_or_else(|| format!("no TcpExt in {}", path.display()))?;
         self.tcp_delay_acks = tcp_contents
             .get(&"DelayedACKs")
             .unwrap_or(&"0")
@@ -117,11 +122,14 @@ impl NetInfo {
             .unwrap_or(&"0")
             .parse::<u64>()
             .unwrap();
+        Ok(())
     }
-    fn refresh_snmp_data(&mut self, path: PathBuf) {
-        let file_data = fs::read_to_string(&path).unwrap();
+    fn refresh_snmp_data(&mut self, path: PathBuf) -> common::Result<()> {
+        let file_data = fs::read_to_string(&path)?;
         let contents = parse_net_file(&file_data);
-        let tcp_contents = contents.get(&"Tcp").unwrap();
+        let tcp_contents = contents
+            .get(&"Tcp")
+            .ok_or_else(|| format!("no Tcp in {}", path.display()))?;
         self.tcp_old_out_segs = self.tcp_out_segs;
         self.tcp_old_retrans_segs = self.tcp_retrans_segs;
         self.tcp_out_segs = tcp_contents
@@ -136,6 +144,7 @@ impl NetInfo {
             .unwrap();
         self.tcp_retran = (self.tcp_retrans_segs - self.tcp_old_retrans_segs) as f32
             / (self.tcp_out_segs - self.tcp_old_out_segs) as f32;
+        Ok(())
     }
     pub fn reset(&mut self) {
         *self = Self::new();
@@ -151,14 +160,18 @@ mod tests_net {
     fn test_net() {
         let current_path: PathBuf = env::current_dir().unwrap();
         let mut net_info = NetInfo::new();
-        net_info.refresh_netstat_data(PathBuf::from(format!(
-            "{}/tests/sample/proc_net_netstat",
-            current_path.to_string_lossy()
-        )));
-        net_info.refresh_snmp_data(PathBuf::from(format!(
-            "{}/tests/sample/proc_net_snmp",
-            current_path.to_string_lossy()
-        )));
+        net_info
+            .refresh_netstat_data(PathBuf::from(format!(
+                "{}/tests/sample/proc_net_netstat",
+                current_path.to_string_lossy()
+            )))
+            .unwrap();
+        net_info
+            .refresh_snmp_data(PathBuf::from(format!(
+                "{}/tests/sample/proc_net_snmp",
+                current_path.to_string_lossy()
+            )))
+            .unwrap();
 
         net_info.refresh_tcp_close_wait(PathBuf::from(format!(
             "{}/tests/sample/proc_net_tcp",
@@ -176,10 +189,12 @@ mod tests_net {
         assert_eq!(net_info.tcp_old_out_segs, 0);
         assert_eq!(net_info.tcp_close_wait, 2);
 
-        net_info.refresh_snmp_data(PathBuf::from(format!(
-            "{}/tests/sample/proc_net_snmp",
-            current_path.to_string_lossy()
-        )));
+        net_info
+            .refresh_snmp_data(PathBuf::from(format!(
+                "{}/tests/sample/proc_net_snmp",
+                current_path.to_string_lossy()
+            )))
+            .unwrap();
 
         assert_eq!(net_info.tcp_old_retrans_segs, 46059890);
         assert_eq!(net_info.tcp_old_out_segs, 474627530725);
@@ -13,11 +13,13 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::common;
+use crate::common::host_path;
 use crate::system::get_secs_since_epoch;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::ops::{Deref, DerefMut};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use utoipa::ToSchema;
 
 #[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
@@ -76,13 +78,14 @@ impl Traffic {
     }
 
     #[cfg(not(tarpaulin_include))]
-    pub fn refresh(&mut self) {
-        self.refresh_device_data(PathBuf::from("/proc/net/dev"));
+    pub fn refresh(&mut self, root: &Path) -> common::Result<()> {
+        self.refresh_device_data(host_path(root, "/proc/net/dev"))?;
         self.update_time = get_secs_since_epoch();
+        Ok(())
     }
 
-    fn refresh_device_data(&mut self, path: PathBuf) {
-        let file_data = fs::read_to_string(&path).unwrap();
+    fn refresh_device_data(&mut self, path: PathBuf) -> common::Result<()> {
+        let file_data = fs::read_to_string(&path)?;
         self.clear();
         file_data.split('\n').skip(2).for_each(|line| {
             if !line.is_empty() {
@@ -109,6 +112,7 @@ impl Traffic {
                 })
             }
         });
+        Ok(())
     }
 
     pub fn reset(&mut self) {
@@ -126,10 +130,12 @@ mod tests_traffic {
     fn test_traffic() {
         let current_path: PathBuf = env::current_dir().unwrap();
         let mut traffic = Traffic::default();
-        traffic.refresh_device_data(PathBuf::from(format!(
-            "{}/tests/sample/proc_net_dev",
-            current_path.to_string_lossy()
-        )));
+        traffic
+            .refresh_device_data(PathBuf::from(format!(
+                "{}/tests/sample/proc_net_dev",
+                current_path.to_string_lossy()
+            )))
+            .unwrap();
         let inner_vec: Vec<NetworkCardTraffic> = traffic.deref().clone();
         let net_card_traffic_info = inner_vec
             .into_iter()
@@ -13,10 +13,12 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::common;
+use crate::common::host_path;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use utoipa::ToSchema;
 
 #[derive(Clone, Debug, Deserialize, Serialize, Default, ToSchema)]
@@ -26,8 +28,8 @@ pub struct SystemProcessStats {
 }
 
 impl SystemProcessStats {
-    pub fn refresh_process_stats(&mut self, path: PathBuf) {
-        let file_data = fs::read_to_string(&path).unwrap();
+    pub fn refresh_process_stats(&mut self, path: PathBuf) -> common::Result<()> {
+        let file_data = fs::read_to_string(&path)?;
         let kvdata: HashMap<&str, u64> = file_data
             .split('\n')
             .filter(|s| s.starts_with("procs_"))
@@ -41,11 +43,12 @@ impl SystemProcessStats {
             .collect();
         self.procs_blocked = kvdata.get("procs_blocked").cloned();
         self.procs_running = kvdata.get("procs_running").cloned();
+        Ok(())
     }
 
     #[cfg(not(tarpaulin_include))]
-    pub fn refresh(&mut self) {
-        self.refresh_process_stats(PathBuf::from("/proc/stat"));
+    pub fn refresh(&mut self, root: &Path) -> common::Result<()> {
+        self.refresh_process_stats(host_path(root, "/proc/stat"))
     }
 
     pub fn reset(&mut self) {
@@ -62,7 +65,9 @@ mod tests_system_process_stats {
         let current_path: PathBuf = env::current_dir().unwrap();
         let proc_stat_file = format!("{}/tests/sample/proc_stat", current_path.to_string_lossy());
         let mut system_process_stats = SystemProcessStats::default();
-        system_process_stats.refresh_process_stats(PathBuf::from(proc_stat_file));
+        system_process_stats
+            .refresh_process_stats(PathBuf::from(proc_stat_file))
+            .unwrap();
         assert_eq!(system_process_stats.procs_running, Some(2));
         assert_eq!(system_process_stats.procs_blocked, Some(1));
     }
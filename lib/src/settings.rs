@@ -13,10 +13,14 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use config::{Config, ConfigError, File};
+use config::{Config, ConfigError, Environment, File, FileFormat};
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 use std::env;
-use strum::EnumIter;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use strum::{EnumIter, IntoEnumIterator};
 use utoipa::ToSchema;
 
 pub trait DataSource {
@@ -26,9 +30,14 @@ pub trait DataSource {
 
 pub trait SubSystem {
     fn is_enable(&self) -> bool;
+    /// refresh interval in seconds, 0 refreshes on every monitor tick
+    fn get_interval(&self) -> u64;
 }
 
-#[derive(Clone, Eq, Hash, PartialEq, Debug)]
+/// monitor tick in seconds when no sub system sets an interval
+pub const DEFAULT_REFRESH_TICK: u64 = 10;
+
+#[derive(Clone, Copy, Eq, Hash, PartialEq, Debug, EnumIter)]
 pub enum DataSourceType {
     ProcFS,
     SysFS,
@@ -37,12 +46,16 @@ pub enum DataSourceType {
     Ebpf,
 }
 
-#[derive(Debug, Clone, EnumIter)]
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq, EnumIter)]
 pub enum SubSystemType {
     Cpu,
     Mem,
     Net,
     Storage,
+    Pids,
+    Hugetlb,
+    Rdma,
+    Misc,
     Unknow,
 }
 
@@ -68,6 +81,10 @@ impl SubSystem for SubSystemDisabled {
     fn is_enable(&self) -> bool {
         false
     }
+
+    fn get_interval(&self) -> u64 {
+        0
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, PartialOrd, Eq, Hash, ToSchema)]
@@ -81,6 +98,10 @@ impl SubSystem for DataSourceSubSys {
     fn is_enable(&self) -> bool {
         self.enable
     }
+
+    fn get_interval(&self) -> u64 {
+        self.interval
+    }
 }
 
 impl Default for DataSourceSubSys {
@@ -188,6 +209,22 @@ pub struct DataSourceCgroupFS {
     mem: DataSourceSubSys,
     storage: DataSourceSubSys,
     net: DataSourceSubSys,
+    #[serde(default)]
+    pids: DataSourceSubSys,
+    #[serde(default)]
+    hugetlb: DataSourceSubSys,
+    #[serde(default)]
+    rdma: DataSourceSubSys,
+    #[serde(default)]
+    misc: DataSourceSubSys,
+    /// user path patterns of cgroups to track without being queried first,
+    /// e.g. `kubepods/*/pod*`
+    #[serde(default)]
+    discovery: Vec<String>,
+    /// watch the cgroup hierarchy with inotify, so that discovered cgroups are
+    /// tracked on creation and dropped on removal
+    #[serde(default)]
+    watch: bool,
 }
 
 impl Default for DataSourceCgroupFS {
@@ -204,6 +241,12 @@ impl DataSourceCgroupFS {
             mem: DataSourceSubSys::default(),
             storage: DataSourceSubSys::default(),
             net: DataSourceSubSys::default(),
+            pids: DataSourceSubSys::default(),
+            hugetlb: DataSourceSubSys::default(),
+            rdma: DataSourceSubSys::default(),
+            misc: DataSourceSubSys::default(),
+            discovery: vec![],
+            watch: false,
         }
     }
 }
@@ -219,6 +262,10 @@ impl DataSource for DataSourceCgroupFS {
             SubSystemType::Mem => Some(Box::new(self.mem.clone())),
             SubSystemType::Net => Some(Box::new(self.net.clone())),
             SubSystemType::Storage => Some(Box::new(self.storage.clone())),
+            SubSystemType::Pids => Some(Box::new(self.pids.clone())),
+            SubSystemType::Hugetlb => Some(Box::new(self.hugetlb.clone())),
+            SubSystemType::Rdma => Some(Box::new(self.rdma.clone())),
+            SubSystemType::Misc => Some(Box::new(self.misc.clone())),
             _ => None,
         }
     }
@@ -312,6 +359,40 @@ impl DataSource for DataSourceEBPF {
     }
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, PartialOrd, Eq, Hash, ToSchema)]
+#[allow(unused)]
+pub struct HistorySettings {
+    enable: bool,
+    /// seconds of samples kept per series
+    retention: u64,
+    /// upper bound of memory used by all series, in bytes
+    memory_budget: u64,
+}
+
+impl Default for HistorySettings {
+    fn default() -> Self {
+        Self {
+            enable: true,
+            retention: 600,
+            memory_budget: 64 * 1024 * 1024,
+        }
+    }
+}
+
+impl HistorySettings {
+    pub fn is_enable(&self) -> bool {
+        self.enable
+    }
+
+    pub fn retention(&self) -> u64 {
+        self.retention
+    }
+
+    pub fn memory_budget(&self) -> u64 {
+        self.memory_budget
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Eq, Hash, ToSchema)]
 #[allow(unused)]
 pub struct Settings {
@@ -321,6 +402,8 @@ pub struct Settings {
     cgroup: DataSourceCgroupFS,
     byteperf: DataSourceBytePerf,
     ebpf: DataSourceEBPF,
+    #[serde(default)]
+    history: HistorySettings,
 }
 
 impl Default for Settings {
@@ -332,20 +415,138 @@ impl Default for Settings {
             cgroup: DataSourceCgroupFS::default(),
             byteperf: DataSourceBytePerf::default(),
             ebpf: DataSourceEBPF::default(),
+            history: HistorySettings::default(),
         }
     }
 }
 
+/// prefix of the environment variables overriding settings, e.g. `MALACHITE_EBPF__ENABLE=false`
+pub const ENV_PREFIX: &str = "MALACHITE";
+
+static DEFAULT_PATH: OnceCell<PathBuf> = OnceCell::new();
+static STATE_PATH: OnceCell<PathBuf> = OnceCell::new();
+
+/// use another default settings file than `static/config/default.toml` next to the binary.
+/// must be called before the settings are loaded.
+pub fn set_default_path<P: AsRef<Path>>(path: P) -> Result<(), PathBuf> {
+    DEFAULT_PATH.set(path.as_ref().to_path_buf())
+}
+
+/// use another state file than `static/config/state.json` next to the binary, e.g. on a
+/// writable volume. must be called before the settings are loaded.
+pub fn set_state_path<P: AsRef<Path>>(path: P) -> Result<(), PathBuf> {
+    STATE_PATH.set(path.as_ref().to_path_buf())
+}
+
+fn env_source() -> Environment {
+    Environment::with_prefix(ENV_PREFIX)
+        .prefix_separator("_")
+        .separator("__")
+        .try_parsing(true)
+}
+
+fn config_path(name: &str) -> PathBuf {
+    let mut p = env::current_exe().unwrap();
+    p.pop();
+    p.push("static/config");
+    p.push(name);
+    p
+}
+
+/// the default settings file
+pub fn default_path() -> PathBuf {
+    DEFAULT_PATH
+        .get()
+        .cloned()
+        .unwrap_or_else(|| config_path("default.toml"))
+}
+
+/// settings changed at runtime, kept as the accumulated JSON merge patch so that they
+/// override only the changed keys of the default file across restarts
+pub fn state_path() -> PathBuf {
+    STATE_PATH
+        .get()
+        .cloned()
+        .unwrap_or_else(|| config_path("state.json"))
+}
+
 impl Settings {
+    /// environment overrides, then the persisted state if any, then the default file
     pub fn new() -> Result<Settings, ConfigError> {
-        let mut p = env::current_exe().unwrap();
-        p.pop();
-        p.push("static/config/default");
-        let s = Config::builder()
-            .add_source(File::with_name(&p.into_os_string().into_string().unwrap()))
-            .build()?;
+        Settings::load(&default_path(), Some(&state_path()), env_source())
+    }
+
+    /// the default file with environment overrides
+    pub fn load_default() -> Result<Settings, ConfigError> {
+        Settings::load(&default_path(), None, env_source())
+    }
 
-        s.try_deserialize()
+    /// environment overrides, then the given overrides, then the default file; the
+    /// overrides kept in memory may be ahead of the persisted state
+    pub fn with_overrides(overrides: &serde_json::Value) -> Result<Settings, ConfigError> {
+        Settings::load_overrides(&default_path(), overrides, env_source())
+    }
+
+    fn load_overrides(
+        default: &Path,
+        overrides: &serde_json::Value,
+        env: Environment,
+    ) -> Result<Settings, ConfigError> {
+        let overrides =
+            serde_json::to_string(overrides).map_err(|e| ConfigError::Foreign(Box::new(e)))?;
+        Config::builder()
+            .add_source(File::with_name(&default.to_string_lossy()))
+            .add_source(File::from_str(&overrides, FileFormat::Json))
+            .add_source(env)
+            .build()?
+            .try_deserialize()
+    }
+
+    fn load(
+        default: &Path,
+        state: Option<&Path>,
+        env: Environment,
+    ) -> Result<Settings, ConfigError> {
+        let mut builder = Config::builder().add_source(File::with_name(&default.to_string_lossy()));
+        if let Some(state) = state {
+            builder =
+                builder.add_source(File::from(state).format(FileFormat::Json).required(false));
+        }
+
+        builder.add_source(env).build()?.try_deserialize()
+    }
+
+    /// the overrides of the state file, an empty object when there is none
+    pub fn load_state(path: &Path) -> io::Result<serde_json::Value> {
+        match fs::read(path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Ok(serde_json::Value::Object(Default::default()))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// write the overrides to the state file, replacing it atomically
+    pub fn save_state(overrides: &serde_json::Value, path: &Path) -> io::Result<()> {
+        let data = serde_json::to_vec_pretty(overrides)?;
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        {
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(&data)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, path)
+    }
+
+    /// drop the state file, a missing one is not an error
+    pub fn remove_state(path: &Path) -> io::Result<()> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
     }
 
     pub fn is_enable(&self) -> bool {
@@ -362,6 +563,40 @@ impl Settings {
         }
     }
 
+    /// the shortest interval of the enabled sub systems, every other interval
+    /// is honored at this granularity
+    pub fn get_refresh_tick(&self) -> u64 {
+        let mut tick = None;
+        for ds_type in DataSourceType::iter() {
+            let ds_settings = match self.get_data_source(ds_type) {
+                Some(ds_settings) if ds_settings.is_enable() => ds_settings,
+                _ => continue,
+            };
+            for sub_sys_type in SubSystemType::iter() {
+                if let Some(sub_sys) = ds_settings.get_sub_system(sub_sys_type) {
+                    if sub_sys.is_enable() && sub_sys.get_interval() > 0 {
+                        tick = Some(tick.map_or(sub_sys.get_interval(), |t: u64| {
+                            t.min(sub_sys.get_interval())
+                        }));
+                    }
+                }
+            }
+        }
+        tick.unwrap_or(DEFAULT_REFRESH_TICK)
+    }
+
+    pub fn get_cgroup_discovery(&self) -> &Vec<String> {
+        &self.cgroup.discovery
+    }
+
+    pub fn get_history(&self) -> &HistorySettings {
+        &self.history
+    }
+
+    pub fn is_cgroup_watch_enable(&self) -> bool {
+        self.enable && self.cgroup.enable && self.cgroup.watch
+    }
+
     pub fn update(&mut self, new_settings: Settings) {
         self.enable = new_settings.enable;
         self.proc = new_settings.proc;
@@ -369,9 +604,94 @@ impl Settings {
         self.cgroup = new_settings.cgroup;
         self.byteperf = new_settings.byteperf;
         self.ebpf = new_settings.ebpf;
+        self.history = new_settings.history;
     }
 }
 
 pub fn get_disabeld_data_source() -> Box<dyn DataSource> {
     Box::new(DataSourceDisabled::default())
 }
+
+#[cfg(test)]
+mod tests_settings {
+    use super::*;
+
+    fn no_env() -> Environment {
+        env_source().source(Some(Default::default()))
+    }
+
+    #[test]
+    fn test_env_override() {
+        let default = Path::new("tests/sample/settings/default.toml");
+        let env = env_source().source(Some(
+            [
+                ("MALACHITE_EBPF__NET__ENABLE", "false"),
+                ("MALACHITE_PROC__CPU__INTERVAL", "1"),
+                ("MALACHITE_HOST_ROOT", "/host"),
+            ]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        ));
+        let settings = Settings::load(default, None, env).unwrap();
+        assert!(!settings.ebpf.net.is_enable());
+        assert!(settings.ebpf.cpu.is_enable());
+        assert_eq!(settings.proc.cpu.get_interval(), 1);
+        assert_eq!(settings.proc.mem.get_interval(), 10);
+    }
+
+    #[test]
+    fn test_state_override() {
+        let dir = env::temp_dir().join(format!("malachite_settings_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let default = dir.join("default.toml");
+        let sample = fs::read_to_string("tests/sample/settings/default.toml").unwrap();
+        fs::write(&default, &sample).unwrap();
+        let state = dir.join("state.json");
+
+        // no state file yet
+        let settings = Settings::load(&default, Some(&state), no_env()).unwrap();
+        assert_eq!(settings, Settings::load(&default, None, no_env()).unwrap());
+        assert!(settings.ebpf.net.is_enable());
+        assert_eq!(Settings::load_state(&state).unwrap(), serde_json::json!({}));
+
+        let overrides = serde_json::json!({
+            "ebpf": {"net": {"enable": false}},
+            "cgroup": {"discovery": ["kubepods/*"]},
+        });
+        Settings::save_state(&overrides, &state).unwrap();
+        assert!(!dir.join("state.json.tmp").exists());
+        assert_eq!(Settings::load_state(&state).unwrap(), overrides);
+
+        let loaded = Settings::load(&default, Some(&state), no_env()).unwrap();
+        assert!(!loaded.ebpf.net.is_enable());
+        assert!(loaded.ebpf.cpu.is_enable());
+        assert_eq!(loaded.cgroup.discovery, vec!["kubepods/*".to_string()]);
+
+        // keys which were not overridden still follow the default file
+        let edited = sample.replacen(
+            "cpu = { enable = true, interval = 10 }",
+            "cpu = { enable = true, interval = 5 }",
+            1,
+        );
+        fs::write(&default, edited).unwrap();
+        let loaded = Settings::load(&default, Some(&state), no_env()).unwrap();
+        assert_eq!(loaded.proc.cpu.get_interval(), 5);
+        assert!(!loaded.ebpf.net.is_enable());
+
+        // overrides in memory, ahead of the state file, win over both files
+        let ahead = serde_json::json!({"ebpf": {"cpu": {"enable": false}}});
+        let loaded = Settings::load_overrides(&default, &ahead, no_env()).unwrap();
+        assert!(!loaded.ebpf.cpu.is_enable());
+        assert!(loaded.ebpf.net.is_enable());
+        assert_eq!(loaded.proc.cpu.get_interval(), 5);
+
+        Settings::remove_state(&state).unwrap();
+        Settings::remove_state(&state).unwrap();
+        assert_eq!(
+            Settings::load(&default, Some(&state), no_env()).unwrap(),
+            Settings::load(&default, None, no_env()).unwrap()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::common;
+use crate::settings::{DataSourceType, Settings, SubSystemType};
+use crate::system::get_secs_since_epoch;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::BTreeMap;
+use strum::{EnumIter, IntoEnumIterator};
+use utoipa::ToSchema;
+
+/// one refresh step of the system, timed and guarded on its own
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    EnumIter,
+    ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Collector {
+    ProcCpu,
+    ProcMem,
+    ProcNet,
+    ProcDisk,
+    Psi,
+    Sys,
+    Cgroups,
+    Ebpf,
+    Pmu,
+}
+
+impl Collector {
+    pub fn all() -> impl Iterator<Item = Collector> {
+        Collector::iter()
+    }
+
+    /// data source, and sub system if any, whose settings turn the collector on and off
+    pub fn source(self) -> (DataSourceType, Option<SubSystemType>) {
+        match self {
+            Collector::ProcCpu => (DataSourceType::ProcFS, Some(SubSystemType::Cpu)),
+            Collector::ProcMem => (DataSourceType::ProcFS, Some(SubSystemType::Mem)),
+            Collector::ProcNet => (DataSourceType::ProcFS, Some(SubSystemType::Net)),
+            Collector::ProcDisk => (DataSourceType::ProcFS, Some(SubSystemType::Storage)),
+            Collector::Psi => (DataSourceType::ProcFS, None),
+            Collector::Sys => (DataSourceType::SysFS, None),
+            Collector::Cgroups => (DataSourceType::CgroupFS, None),
+            Collector::Ebpf => (DataSourceType::Ebpf, None),
+            Collector::Pmu => (DataSourceType::BytePerf, None),
+        }
+    }
+
+    pub fn is_enable(self, settings: &Settings) -> bool {
+        let (ds_type, sub_sys_type) = self.source();
+        let data_source = match settings.get_data_source(ds_type) {
+            Some(data_source) if settings.is_enable() && data_source.is_enable() => data_source,
+            _ => return false,
+        };
+        match sub_sys_type {
+            Some(t) => data_source
+                .get_sub_system(t)
+                .is_some_and(|sub_sys| sub_sys.is_enable()),
+            None => true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct CollectorStat {
+    /// seconds since epoch of the last run which did not fail
+    pub last_success: Option<u64>,
+    /// seconds since epoch of the last run
+    pub last_run: Option<u64>,
+    /// cause of the last failed run, cleared by a successful one
+    pub last_error: Option<String>,
+    pub consecutive_failures: u64,
+    /// duration of the last run in milliseconds
+    pub duration_ms: u64,
+}
+
+/// run statistics of every collector which ran at least once
+#[derive(Clone, Debug, Default)]
+pub struct CollectorStats(BTreeMap<Collector, CollectorStat>);
+
+/// message of a caught panic
+pub(crate) fn panic_cause(e: Box<dyn Any + Send>) -> String {
+    e.downcast_ref::<String>()
+        .cloned()
+        .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+        .unwrap_or_else(|| String::from("<cause unknown>"))
+}
+
+/// the failures of several collectors as one error
+pub(crate) fn join_failures(results: Vec<Result<(), String>>) -> common::Result<()> {
+    let failed: Vec<String> = results.into_iter().filter_map(Result::err).collect();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(failed.join("; ").into())
+    }
+}
+
+impl CollectorStats {
+    pub fn record(&mut self, collector: Collector, duration_ms: u64, result: Result<(), String>) {
+        let now = get_secs_since_epoch();
+        let stat = self.0.entry(collector).or_default();
+        stat.last_run = Some(now);
+        stat.duration_ms = duration_ms;
+        match result {
+            Ok(()) => {
+                stat.last_success = Some(now);
+                stat.last_error = None;
+                stat.consecutive_failures = 0;
+            }
+            Err(e) => {
+                stat.last_error = Some(e);
+                stat.consecutive_failures += 1;
+            }
+        }
+    }
+
+    pub fn get(&self, collector: Collector) -> Option<&CollectorStat> {
+        self.0.get(&collector)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Collector, &CollectorStat)> {
+        self.0.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests_collector {
+    use super::*;
+
+    #[test]
+    fn test_collector_stats() {
+        let mut stats = CollectorStats::default();
+        assert!(stats.get(Collector::Sys).is_none());
+
+        stats.record(Collector::Sys, 3, Err(String::from("no such file")));
+        stats.record(Collector::Sys, 4, Err(String::from("no such file")));
+        let stat = stats.get(Collector::Sys).unwrap();
+        assert_eq!(stat.consecutive_failures, 2);
+        assert_eq!(stat.last_error.as_deref(), Some("no such file"));
+        assert_eq!(stat.duration_ms, 4);
+        assert!(stat.last_success.is_none());
+        assert!(stat.last_run.is_some());
+
+        stats.record(Collector::Sys, 5, Ok(()));
+        let stat = stats.get(Collector::Sys).unwrap();
+        assert_eq!(stat.consecutive_failures, 0);
+        assert!(stat.last_error.is_none());
+        assert!(stat.last_success.is_some());
+        assert_eq!(stats.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_join_failures() {
+        assert!(join_failures(vec![Ok(()), Ok(())]).is_ok());
+        let e = join_failures(vec![
+            Err(String::from("ProcCpu: no such file")),
+            Ok(()),
+            Err(String::from("Psi: no such file")),
+        ])
+        .unwrap_err();
+        assert_eq!(e.to_string(), "ProcCpu: no such file; Psi: no such file");
+    }
+}
@@ -13,13 +13,15 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::common;
+use crate::common::host_path;
 use log::warn;
 use nix::sys::statfs::*;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use utoipa::ToSchema;
 
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
@@ -69,12 +71,19 @@ impl Disk {
         &self.usage
     }
     #[cfg(not(tarpaulin_include))]
-    pub fn refresh(&mut self) {
-        self.refresh_disk_stat(PathBuf::from("/proc/diskstats"));
-        self.refresh_disk_usage(PathBuf::from("/proc/self/mountinfo"));
+    pub fn refresh(&mut self, root: &Path) -> common::Result<()> {
+        self.refresh_disk_stat(host_path(root, "/proc/diskstats"))?;
+        // under a host root, read the mount table of the host init process
+        // rather than our own mount namespace
+        let mountinfo = if root == Path::new("/") {
+            "/proc/self/mountinfo"
+        } else {
+            "/proc/1/mountinfo"
+        };
+        self.refresh_disk_usage(root, host_path(root, mountinfo))
     }
-    fn refresh_disk_stat(&mut self, path: PathBuf) {
-        let file = File::open(path).unwrap();
+    fn refresh_disk_stat(&mut self, path: PathBuf) -> common::Result<()> {
+        let file = File::open(path)?;
         let reader = BufReader::new(file);
         let mut stats: Vec<DiskStat> = vec![];
         for line in reader.lines() {
@@ -98,10 +107,11 @@ impl Disk {
             }
         }
         self.stats = stats;
+        Ok(())
     }
 
-    fn refresh_disk_usage(&mut self, path: PathBuf) {
-        let file = File::open(path).unwrap();
+    fn refresh_disk_usage(&mut self, root: &Path, path: PathBuf) -> common::Result<()> {
+        let file = File::open(path)?;
         let reader = BufReader::new(file);
         let mut usage_vec: Vec<DiskUsage> = vec![];
         let mut dev_filter = HashMap::new();
@@ -153,7 +163,7 @@ impl Disk {
                         _ => (),
                     }
 
-                    let mp_file = match File::open(mount_point.clone()) {
+                    let mp_file = match File::open(host_path(root, &mount_point)) {
                         Ok(file) => file,
                         Err(e) => {
                             warn!("open mount point {} failed: {}", mount_point, e);
@@ -198,6 +208,7 @@ impl Disk {
             }
         }
         self.usage = usage_vec;
+        Ok(())
     }
 
     pub fn reset(&mut self) {
@@ -217,7 +228,7 @@ mod tests_disk {
             current_path.to_string_lossy()
         ));
         let mut disk_instance = Disk::default();
-        disk_instance.refresh_disk_stat(path);
+        disk_instance.refresh_disk_stat(path).unwrap();
 
         assert_eq!(disk_instance.stats.len(), 21);
 
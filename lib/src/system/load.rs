@@ -13,11 +13,13 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::common;
+use crate::common::host_path;
 use crate::system::load_utils::*;
 use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use utoipa::ToSchema;
 
 #[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
@@ -66,8 +68,8 @@ impl LoadAvg {
     }
 
     #[cfg(not(tarpaulin_include))]
-    pub fn refresh_system_load(&mut self) {
-        self.read_load_avg(PathBuf::from("/proc/loadavg"));
+    pub fn refresh_system_load(&mut self, root: &Path) -> common::Result<()> {
+        self.read_load_avg(host_path(root, "/proc/loadavg"))
     }
 
     #[cfg(not(tarpaulin_include))]
@@ -99,14 +101,9 @@ impl LoadAvg {
             .unwrap();
     }
 
-    fn read_load_avg(&mut self, path: PathBuf) {
+    fn read_load_avg(&mut self, path: PathBuf) -> common::Result<()> {
         let mut s = String::new();
-        if File::open(&path)
-            .and_then(|mut f| f.read_to_string(&mut s))
-            .is_err()
-        {
-            return;
-        }
+        File::open(&path)?.read_to_string(&mut s)?;
         let loads = s
             .trim()
             .split(' ')
@@ -116,6 +113,7 @@ impl LoadAvg {
         self.one = loads[0];
         self.five = loads[1];
         self.fifteen = loads[2];
+        Ok(())
     }
 
     pub fn reset(&mut self) {
@@ -132,10 +130,12 @@ mod tests_load {
     fn test_load() {
         let current_path: PathBuf = env::current_dir().unwrap();
         let mut load_instance = LoadAvg::new();
-        load_instance.read_load_avg(PathBuf::from(format!(
-            "{}/tests/sample/proc_loadavg",
-            current_path.to_string_lossy()
-        )));
+        load_instance
+            .read_load_avg(PathBuf::from(format!(
+                "{}/tests/sample/proc_loadavg",
+                current_path.to_string_lossy()
+            )))
+            .unwrap();
         assert_eq!(load_instance.one, 1.29);
         assert_eq!(load_instance.five, 1.24);
         assert_eq!(load_instance.fifteen, 1.17);
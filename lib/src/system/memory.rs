@@ -13,12 +13,14 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::common;
+use crate::common::host_path;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use utoipa::ToSchema;
 
 #[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
@@ -108,14 +110,14 @@ impl MemoryInfo {
     }
 
     #[cfg(not(tarpaulin_include))]
-    pub fn refresh(&mut self) {
-        self.refresh_mem_info(PathBuf::from("/proc/meminfo"));
-        self.refresh_vm_watermark(PathBuf::from("/proc/sys/vm/watermark_scale_factor"));
-        self.refresh_vm_stat(PathBuf::from("/proc/vmstat"));
+    pub fn refresh(&mut self, root: &Path) -> common::Result<()> {
+        self.refresh_mem_info(host_path(root, "/proc/meminfo"))?;
+        self.refresh_vm_watermark(host_path(root, "/proc/sys/vm/watermark_scale_factor"))?;
+        self.refresh_vm_stat(host_path(root, "/proc/vmstat"))
     }
 
-    pub fn refresh_mem_info(&mut self, path: PathBuf) {
-        let file_data = fs::read_to_string(&path).unwrap();
+    pub fn refresh_mem_info(&mut self, path: PathBuf) -> common::Result<()> {
+        let file_data = fs::read_to_string(&path)?;
         let mem_info: HashMap<&str, &str> = file_data
             .split('\n')
             .into_iter()
@@ -184,10 +186,11 @@ impl MemoryInfo {
         if self.mem_total != 0 {
             self.mem_util = (self.used_memory() / self.mem_total) as f64;
         }
+        Ok(())
     }
 
-    pub fn refresh_vm_stat(&mut self, path: PathBuf) {
-        let file_data = fs::read_to_string(&path).unwrap();
+    pub fn refresh_vm_stat(&mut self, path: PathBuf) -> common::Result<()> {
+        let file_data = fs::read_to_string(&path)?;
         let vm_stat_info: HashMap<&str, &str> = file_data
             .split('\n')
             .map(|line| {
@@ -201,13 +204,15 @@ impl MemoryInfo {
             .unwrap()
             .parse::<u64>()
             .unwrap();
+        Ok(())
     }
-    pub fn refresh_vm_watermark(&mut self, path: PathBuf) {
-        let file = File::open(&path).unwrap();
+    pub fn refresh_vm_watermark(&mut self, path: PathBuf) -> common::Result<()> {
+        let file = File::open(&path)?;
         let reader = BufReader::new(file);
         if let Some(Ok(data)) = reader.lines().next() {
             self.vm_watermark_scale_factor = data.parse::<u64>().unwrap();
         }
+        Ok(())
     }
 
     pub fn reset(&mut self) {
@@ -223,20 +228,26 @@ mod tests_memory {
     fn test_memory() {
         let current_path: PathBuf = env::current_dir().unwrap();
         let mut mem_info = MemoryInfo::new();
-        mem_info.refresh_mem_info(PathBuf::from(format!(
-            "{}/tests/sample/proc_mem_info",
-            current_path.to_string_lossy()
-        )));
+        mem_info
+            .refresh_mem_info(PathBuf::from(format!(
+                "{}/tests/sample/proc_mem_info",
+                current_path.to_string_lossy()
+            )))
+            .unwrap();
 
-        mem_info.refresh_vm_watermark(PathBuf::from(format!(
-            "{}/tests/sample/proc_sys_vm_watermark_scale_factor",
-            current_path.to_string_lossy()
-        )));
+        mem_info
+            .refresh_vm_watermark(PathBuf::from(format!(
+                "{}/tests/sample/proc_sys_vm_watermark_scale_factor",
+                current_path.to_string_lossy()
+            )))
+            .unwrap();
 
-        mem_info.refresh_vm_stat(PathBuf::from(format!(
-            "{}/tests/sample/proc_vm_stat",
-            current_path.to_string_lossy()
-        )));
+        mem_info
+            .refresh_vm_stat(PathBuf::from(format!(
+                "{}/tests/sample/proc_vm_stat",
+                current_path.to_string_lossy()
+            )))
+            .unwrap();
 
         assert_eq!(mem_info.mem_total, 16166776);
         assert_eq!(mem_info.mem_free, 1817388);
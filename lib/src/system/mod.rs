@@ -13,19 +13,23 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+pub use collector::*;
 pub use diskstat::*;
 pub use load::*;
 pub use memory::*;
 pub use numa_node::*;
 pub use pressure::*;
+pub use schedule::*;
 pub use sys::*;
 pub use utils::*;
 
+mod collector;
 mod diskstat;
 mod load;
 mod load_utils;
 mod memory;
 mod numa_node;
 mod pressure;
+mod schedule;
 mod sys;
 mod utils;
@@ -13,6 +13,8 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::common;
+use crate::common::host_path;
 use crate::cpu::NodeVec;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -20,7 +22,7 @@ use std::fs;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use utoipa::ToSchema;
 
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
@@ -115,10 +117,10 @@ impl NumaNode {
         self.mem_read_latency = 0.0;
         self.mem_write_latency = 0.0;
     }
-    pub fn refresh_numa_mem_info(&mut self) {
+    pub fn refresh_numa_mem_info(&mut self) -> common::Result<()> {
         let mut file_path = PathBuf::from(&self.path);
         file_path.push("meminfo");
-        let file_data = fs::read_to_string(&file_path).unwrap();
+        let file_data = fs::read_to_string(&file_path)?;
         let numa_mem_info: HashMap<&str, &str> = file_data
             .split('\n')
             .map(|line| {
@@ -148,6 +150,7 @@ impl NumaNode {
             .unwrap()
             .parse::<u64>()
             .unwrap();
+        Ok(())
     }
 
     pub fn reset_numa_mem_info(&mut self) {
@@ -211,11 +214,15 @@ pub struct SystemDeviceNode {
 
 impl Default for SystemDeviceNode {
     fn default() -> Self {
-        SystemDeviceNode::new(PathBuf::from("/sys/devices/system/node"))
+        SystemDeviceNode::with_root(Path::new("/"))
     }
 }
 
 impl SystemDeviceNode {
+    pub fn with_root(root: &Path) -> SystemDeviceNode {
+        SystemDeviceNode::new(host_path(root, "/sys/devices/system/node"))
+    }
+
     pub fn get_nodes(&self) -> &Vec<NumaNode> {
         &self.nodes
     }
@@ -260,10 +267,10 @@ impl SystemDeviceNode {
             path: system_node_path,
         }
     }
-    pub fn refresh_basic_info(&mut self) {
+    pub fn refresh_basic_info(&mut self) -> common::Result<()> {
         self.nodes
             .iter_mut()
-            .for_each(|node| node.refresh_numa_mem_info());
+            .try_for_each(|node| node.refresh_numa_mem_info())
     }
     pub fn reset_basic_info(&mut self) {
         self.nodes
@@ -298,7 +305,7 @@ mod tests_numa_node {
             current_path.to_string_lossy()
         ));
         let mut system_node_instance = SystemDeviceNode::new(path);
-        system_node_instance.refresh_basic_info();
+        system_node_instance.refresh_basic_info().unwrap();
 
         let correct_node_vec = NodeVec::from("0-3".to_string());
 
@@ -14,11 +14,12 @@ limitations under the License.
 */
 
 use crate::common;
+use crate::common::host_path;
 use crate::psi::PressureStallInfo;
 use log::warn;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct SystemPSI {
@@ -67,16 +68,21 @@ impl SystemPSI {
     }
 
     #[cfg(not(tarpaulin_include))]
-    pub fn update(&mut self) {
-        if let Err(e) = self.update_cpu_psi(PathBuf::from("/proc/pressure/cpu")) {
-            warn!("[PSI] update cpu psi error: {}", e);
-        }
-        if let Err(e) = self.update_memory_psi(PathBuf::from("/proc/pressure/memory")) {
-            warn!("[PSI] update memory psi error: {}", e);
-        }
-        if let Err(e) = self.update_io_psi(PathBuf::from("/proc/pressure/io")) {
-            warn!("[PSI] update io psi error: {}", e);
+    /// every resource is refreshed even if another failed, the first error is returned
+    pub fn update(&mut self, root: &Path) -> common::Result<()> {
+        let cpu = self.update_cpu_psi(host_path(root, "/proc/pressure/cpu"));
+        let memory = self.update_memory_psi(host_path(root, "/proc/pressure/memory"));
+        let io = self.update_io_psi(host_path(root, "/proc/pressure/io"));
+        let mut result = Ok(());
+        for (resource, r) in [("cpu", cpu), ("memory", memory), ("io", io)] {
+            if let Err(e) = r {
+                warn!("[PSI] update {} psi error: {}", resource, e);
+                if result.is_ok() {
+                    result = Err(format!("update {} psi: {}", resource, e).into());
+                }
+            }
         }
+        result
     }
 }
 
@@ -0,0 +1,176 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::settings::{DataSource, DataSourceType, SubSystemType};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use strum::IntoEnumIterator;
+
+/// sub systems of one data source to refresh in a round
+#[derive(Clone, Debug, Default)]
+pub struct DueSubSystems(Vec<SubSystemType>);
+
+impl DueSubSystems {
+    pub fn all() -> DueSubSystems {
+        DueSubSystems(SubSystemType::iter().collect())
+    }
+
+    pub fn contains(&self, t: SubSystemType) -> bool {
+        self.0.contains(&t)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// last refresh time of every sub system of every data source
+#[derive(Clone, Debug, Default)]
+pub struct RefreshSchedule {
+    last_refresh: HashMap<(DataSourceType, SubSystemType), Instant>,
+}
+
+impl RefreshSchedule {
+    /// sub systems whose interval elapsed since their last refresh, they are
+    /// considered refreshed at `now`; rounds run every `tick` and may start a
+    /// little early or late, so an interval elapsed within half a tick counts
+    /// as elapsed rather than being postponed by a whole tick
+    pub fn take_due(
+        &mut self,
+        ds_type: DataSourceType,
+        ds_settings: &dyn DataSource,
+        now: Instant,
+        tick: Duration,
+    ) -> DueSubSystems {
+        let tolerance = tick / 2;
+        let mut due = DueSubSystems::default();
+        for sub_sys_type in SubSystemType::iter() {
+            let interval = match ds_settings.get_sub_system(sub_sys_type) {
+                Some(sub_sys) => Duration::from_secs(sub_sys.get_interval()),
+                None => continue,
+            };
+            let key = (ds_type, sub_sys_type);
+            let elapsed = match self.last_refresh.get(&key) {
+                Some(last) => now.saturating_duration_since(*last) + tolerance >= interval,
+                None => true,
+            };
+            if elapsed {
+                self.last_refresh.insert(key, now);
+                due.0.push(sub_sys_type);
+            }
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests_schedule {
+    use super::*;
+    use crate::settings::DataSourceProcFS;
+
+    const TICK: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_take_due() {
+        let ds_settings: DataSourceProcFS = serde_json::from_str(
+            r#"{
+                "enable": true,
+                "cpu": {"enable": true, "interval": 1},
+                "mem": {"enable": true, "interval": 10},
+                "storage": {"enable": true, "interval": 0},
+                "net": {"enable": false, "interval": 30}
+            }"#,
+        )
+        .unwrap();
+        let mut schedule = RefreshSchedule::default();
+        let start = Instant::now();
+
+        // everything is due on the first round
+        let due = schedule.take_due(DataSourceType::ProcFS, &ds_settings, start, TICK);
+        assert!(due.contains(SubSystemType::Cpu));
+        assert!(due.contains(SubSystemType::Mem));
+        assert!(due.contains(SubSystemType::Storage));
+        assert!(due.contains(SubSystemType::Net));
+        assert!(!due.contains(SubSystemType::Unknow));
+
+        let due = schedule.take_due(
+            DataSourceType::ProcFS,
+            &ds_settings,
+            start + Duration::from_millis(400),
+            TICK,
+        );
+        assert!(!due.contains(SubSystemType::Cpu));
+        assert!(!due.contains(SubSystemType::Mem));
+        assert!(due.contains(SubSystemType::Storage));
+
+        let due = schedule.take_due(
+            DataSourceType::ProcFS,
+            &ds_settings,
+            start + Duration::from_secs(1),
+            TICK,
+        );
+        assert!(due.contains(SubSystemType::Cpu));
+        assert!(!due.contains(SubSystemType::Mem));
+
+        let due = schedule.take_due(
+            DataSourceType::ProcFS,
+            &ds_settings,
+            start + Duration::from_secs(10),
+            TICK,
+        );
+        assert!(due.contains(SubSystemType::Cpu));
+        assert!(due.contains(SubSystemType::Mem));
+        assert!(!due.contains(SubSystemType::Net));
+
+        // data sources are scheduled independently
+        let due = schedule.take_due(
+            DataSourceType::SysFS,
+            &ds_settings,
+            start + Duration::from_secs(10),
+            TICK,
+        );
+        assert!(due.contains(SubSystemType::Net));
+    }
+
+    #[test]
+    fn test_take_due_jitter() {
+        let ds_settings: DataSourceProcFS = serde_json::from_str(
+            r#"{
+                "enable": true,
+                "cpu": {"enable": true, "interval": 1},
+                "mem": {"enable": true, "interval": 3},
+                "storage": {"enable": false, "interval": 0},
+                "net": {"enable": false, "interval": 0}
+            }"#,
+        )
+        .unwrap();
+        let mut schedule = RefreshSchedule::default();
+        let start = Instant::now();
+        schedule.take_due(DataSourceType::ProcFS, &ds_settings, start, TICK);
+
+        // rounds starting a few ms early or late still refresh on every tick
+        let mut mem_rounds = vec![];
+        for (round, ms) in [997, 2_004, 2_999, 3_995, 5_003, 6_001].iter().enumerate() {
+            let now = start + Duration::from_millis(*ms);
+            let due = schedule.take_due(DataSourceType::ProcFS, &ds_settings, now, TICK);
+            assert!(due.contains(SubSystemType::Cpu), "round at {}ms", ms);
+            if due.contains(SubSystemType::Mem) {
+                mem_rounds.push(round + 1);
+            }
+        }
+        // and longer intervals keep their cadence in ticks
+        assert_eq!(mem_rounds, vec![3, 6]);
+    }
+}
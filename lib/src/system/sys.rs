@@ -15,7 +15,7 @@ limitations under the License.
 
 #![allow(dead_code)]
 
-use crate::cgroup::{CGroup, CGroupUserPath, PerfEventCGroup, SubSystemType};
+use crate::cgroup::{self, CGroup, CGroupUserPath, PerfEventCGroup, SubSystemType};
 use crate::common;
 use crate::cpu::SystemProcessorInfo;
 use crate::ffi;
@@ -27,19 +27,24 @@ use crate::ffi::{WrapperBpfProgStat, WrapperSystemEvent};
 use crate::net::{NetInfo, Traffic};
 use crate::process::SystemProcessStats;
 use crate::settings;
+use crate::system::collector::{join_failures, panic_cause, Collector, CollectorStats};
 use crate::system::diskstat::Disk;
 use crate::system::get_secs_since_epoch;
 use crate::system::load::LoadAvg;
 use crate::system::memory::MemoryInfo;
 use crate::system::numa_node::{ImcChannelInfo, SystemDeviceNode};
+use crate::system::schedule::{DueSubSystems, RefreshSchedule};
 use crate::system::SystemPSI;
 use lazy_static::*;
 use libc::{sysconf, _SC_PAGESIZE};
 use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
 use std::ffi::CStr;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
 use std::str;
+use std::time::{Duration, Instant};
 use std::{collections::HashMap, path::PathBuf};
 use strum::IntoEnumIterator;
 use utoipa::ToSchema;
@@ -87,26 +92,70 @@ pub struct System {
     system_pressure: Option<SystemPSI>,
     system_events: SystemEventData,
     bpf_stats: BPFProgStats,
+    /// root that /proc and /sys are read under
+    root: PathBuf,
+    schedule: RefreshSchedule,
+    collectors: CollectorStats,
 }
 
 impl System {
     pub fn new() -> System {
+        System::with_root(common::host_root())
+    }
+
+    pub fn with_root(root: &Path) -> System {
         System {
-            processors: SystemProcessorInfo::default(),
+            processors: SystemProcessorInfo::new(common::host_path(root, "/proc/cpuinfo")),
             process_stats: Default::default(),
             cgroup_list: Default::default(),
             memory: MemoryInfo::default(),
             load: LoadAvg::default(),
             net_traffic: Traffic::default(),
             net_info: NetInfo::default(),
-            system_devices_node: SystemDeviceNode::default(),
+            system_devices_node: SystemDeviceNode::with_root(root),
             disk_io: Disk::default(),
             system_pressure: None,
             system_events: SystemEventData::default(),
             bpf_stats: BPFProgStats::default(),
+            root: root.to_path_buf(),
+            schedule: RefreshSchedule::default(),
+            collectors: CollectorStats::default(),
         }
     }
 
+    pub fn get_root(&self) -> &Path {
+        self.root.as_path()
+    }
+
+    pub fn get_collector_stats(&self) -> &CollectorStats {
+        &self.collectors
+    }
+
+    /// run a collector, an error or a panic is recorded as its failure rather than
+    /// aborting the remaining collectors; idle runs, i.e. disabled or not due, are not
+    /// recorded and never fail
+    fn collect<F>(&mut self, collector: Collector, active: bool, f: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut System) -> common::Result<()>,
+    {
+        let start = Instant::now();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(self)))
+            .map_err(panic_cause)
+            .and_then(|r| r.map_err(|e| e.to_string()));
+        if let Err(e) = &result {
+            warn!("[collector] {:?} failed: {}", collector, e);
+        }
+        if !active {
+            return Ok(());
+        }
+        self.collectors.record(
+            collector,
+            start.elapsed().as_millis() as u64,
+            result.clone(),
+        );
+        result.map_err(|e| format!("{:?}: {}", collector, e))
+    }
+
     pub fn get_processors(&self) -> &SystemProcessorInfo {
         &self.processors
     }
@@ -131,8 +180,12 @@ impl System {
     pub fn get_self(&self) -> &System {
         self
     }
+    /// the path may or may not start with `/`, as in `insert_cgroups_item`
     pub fn get_cgroups(&self, path: PathBuf) -> Option<&CGroup> {
-        self.cgroup_list.get(&path)
+        self.cgroup_list.get(&cgroup::normalize_user_path(&path))
+    }
+    pub fn get_cgroup_list(&self) -> &HashMap<CGroupUserPath, CGroup> {
+        &self.cgroup_list
     }
     pub fn get_process_stats(&self) -> &SystemProcessStats {
         &self.process_stats
@@ -146,12 +199,20 @@ impl System {
     pub fn get_bpf_prog_stats(&self) -> &BPFProgStats {
         &self.bpf_stats
     }
+    /// keyed by the normalized user path, the one discovery and the watcher
+    /// report, whether the item was registered with a leading `/` or not
     pub fn insert_cgroups_item(&mut self, item: CGroup) -> common::Result<bool> {
         self.cgroup_list
-            .entry(PathBuf::from(item.user_path()))
+            .entry(cgroup::normalize_user_path(item.user_path()))
             .or_insert(item);
         Ok(true)
     }
+    pub fn remove_cgroups_item(&mut self, path: &Path) -> common::Result<bool> {
+        Ok(self
+            .cgroup_list
+            .remove(&cgroup::normalize_user_path(path))
+            .is_some())
+    }
     fn refresh_cgroup_pmu_data(
         &mut self,
         cgroup_num: usize,
@@ -320,39 +381,91 @@ impl System {
     }
 
     // at least run 1s
-    pub fn refresh_pmu(&mut self, ds_settings: &dyn settings::DataSource) {
+    pub fn refresh_pmu(
+        &mut self,
+        ds_settings: &dyn settings::DataSource,
+        due: &DueSubSystems,
+    ) -> common::Result<()> {
         if !ds_settings.is_enable() {
             info!("data source byteperf is disabled");
-            return;
+            return Ok(());
+        }
+
+        if !due.contains(settings::SubSystemType::Cpu)
+            && !due.contains(settings::SubSystemType::Mem)
+        {
+            return Ok(());
         }
 
         let (_, cur_mask) = ffi::wrapper_byteperf_check_module_health();
         if cur_mask == 0 {
-            return;
+            return Ok(());
         }
 
         let perf_data = ffi::wrapper_byteperf_gather_count_malachite();
         if let Some(sub_sys) = ds_settings.get_sub_system(settings::SubSystemType::Cpu) {
-            if sub_sys.is_enable() {
+            if sub_sys.is_enable() && due.contains(settings::SubSystemType::Cpu) {
                 self.refresh_cpu_cpi_data(perf_data.cpu_num as usize, perf_data.cpus);
             }
         }
 
         if let Some(sub_sys) = ds_settings.get_sub_system(settings::SubSystemType::Mem) {
-            if sub_sys.is_enable() {
+            if sub_sys.is_enable() && due.contains(settings::SubSystemType::Mem) {
                 self.refresh_numa_pmu_data(perf_data.imc_num as usize, perf_data.imcs);
             }
         }
+        Ok(())
+    }
+
+    /// track cgroups matching the discovery patterns before they are queried
+    pub fn refresh_cgroup_discovery(&mut self, patterns: &[String]) {
+        if patterns.is_empty() {
+            return;
+        }
+
+        let mount_point = common::cgroup_mount_point_in(&self.root);
+        let cgroup_type = match common::cgroup_type_in(&self.root) {
+            Some(cgroup_type) => cgroup_type,
+            None => {
+                warn!(
+                    "[discovery] stat cgroup mount point failed, path= {}",
+                    mount_point.display()
+                );
+                return;
+            }
+        };
+        for user_path in cgroup::discover_cgroups(&mount_point, cgroup_type, patterns) {
+            if self.cgroup_list.contains_key(&user_path) {
+                continue;
+            }
+            info!("[discovery] new cgroup, path= {}", user_path.display());
+            let item = CGroup::with_type(&mount_point.to_string_lossy(), user_path, cgroup_type);
+            if let Err(e) = self.insert_cgroups_item(item) {
+                warn!("[discovery] insert cgroup error: {}", e);
+            }
+        }
     }
 
-    pub fn refresh_cgroups(&mut self, ds_settings: &dyn settings::DataSource) {
+    /// fails only when the cgroup mount point can't be read; a tracked cgroup
+    /// which fails to update, e.g. while its pod is torn down, keeps the error
+    /// on itself and does not fail the others
+    pub fn refresh_cgroups(
+        &mut self,
+        ds_settings: &dyn settings::DataSource,
+        due: &DueSubSystems,
+    ) -> common::Result<()> {
         // delete invalid cgroup item
         self.cgroup_list
             .retain(|_, cgroup| !cgroup.sub_system_groups.is_empty());
 
         if !ds_settings.is_enable() {
             info!("data source cgroup is disabled");
-            return;
+            return Ok(());
+        }
+
+        let mount_point = common::cgroup_mount_point_in(&self.root);
+        if let Err(e) = fs::read_dir(&mount_point) {
+            return Err(format!("read {} error: {}", mount_point.display(), e).into());
         }
 
         // update valid cgroup info
@@ -362,26 +475,34 @@ impl System {
                 self.cgroup_list.keys()
             );
             for (cgroup_path, cgroup) in self.cgroup_list.iter_mut() {
-                match cgroup.update(ds_settings) {
-                    Ok(_) => {}
-                    Err(e) => warn!(
+                if let Err(e) = cgroup.update(ds_settings, due) {
+                    warn!(
                         "update cgroup error, cgroup.path={}, e={}",
                         cgroup_path.display(),
                         e
-                    ),
+                    );
                 }
             }
         }
+        Ok(())
     }
 
-    pub fn refresh_ebpf(&mut self, ds_settings: &dyn settings::DataSource) {
+    pub fn refresh_ebpf(
+        &mut self,
+        ds_settings: &dyn settings::DataSource,
+        due: &DueSubSystems,
+    ) -> common::Result<()> {
         // delete invalid cgroup item
         self.cgroup_list
             .retain(|_, cgroup| !cgroup.sub_system_groups.is_empty());
 
         if !ds_settings.is_enable() {
             info!("data source ebpf is disabled");
-            return;
+            return Ok(());
+        }
+
+        if due.is_empty() {
+            return Ok(());
         }
 
         // refresh system event
@@ -397,7 +518,7 @@ impl System {
                 self.cgroup_list.keys()
             );
             for (cgroup_path, cgroup) in self.cgroup_list.iter_mut() {
-                match cgroup.update_ebpf(ds_settings) {
+                match cgroup.update_ebpf(ds_settings, due) {
                     Ok(_) => {}
                     Err(e) => warn!(
                         "update ebpf error, cgroup.path={}, e={}",
@@ -407,6 +528,7 @@ impl System {
                 }
             }
         }
+        Ok(())
     }
 
     fn refresh_system_event(&mut self, ds_settings: &dyn settings::DataSource) {
@@ -423,125 +545,206 @@ impl System {
         self.bpf_stats.update_time = get_secs_since_epoch();
     }
 
-    fn refresh_psi(&mut self) {
+    /// a kernel built without PSI is skipped, not failed
+    fn refresh_psi(&mut self) -> common::Result<()> {
         if self.system_pressure.is_none() {
-            let dir = Path::new("/proc/pressure");
+            let dir = common::host_path(&self.root, "/proc/pressure");
             if !dir.exists() {
-                warn!("[PSI] /proc/pressure not exists, skip");
-                return;
+                warn!("[PSI] {} not exists, skip", dir.display());
+                return Ok(());
             }
             if !dir.is_dir() {
-                warn!("[PSI] /proc/pressure is not dir, skip");
-                return;
+                warn!("[PSI] {} is not dir, skip", dir.display());
+                return Ok(());
             }
             self.system_pressure = Some(SystemPSI::new());
         }
 
-        if let Some(ref mut x) = self.system_pressure {
-            x.update()
+        match self.system_pressure {
+            Some(ref mut x) => x.update(&self.root),
+            None => Ok(()),
         }
     }
 
-    pub fn refresh_proc_cpu(&mut self, ds_settings: &dyn settings::DataSource) {
+    pub fn refresh_proc_cpu(
+        &mut self,
+        ds_settings: &dyn settings::DataSource,
+    ) -> common::Result<()> {
         if let Some(sub_sys) = ds_settings.get_sub_system(settings::SubSystemType::Cpu) {
             if !sub_sys.is_enable() {
                 info!("proc {:?} is disabled", settings::SubSystemType::Cpu);
-                return;
+                return Ok(());
             }
 
-            self.load.refresh_system_load();
-            self.processors.refresh();
-            self.process_stats.refresh();
+            self.load.refresh_system_load(&self.root)?;
+            self.processors.refresh(&self.root)?;
+            self.process_stats.refresh(&self.root)?;
         } else {
             warn!(
                 "get subsys {:?} from data source proc failed",
                 settings::SubSystemType::Cpu
             );
         }
+        Ok(())
     }
 
-    pub fn refresh_proc_mem(&mut self, ds_settings: &dyn settings::DataSource) {
+    pub fn refresh_proc_mem(
+        &mut self,
+        ds_settings: &dyn settings::DataSource,
+    ) -> common::Result<()> {
         if let Some(sub_sys) = ds_settings.get_sub_system(settings::SubSystemType::Mem) {
             if !sub_sys.is_enable() {
                 info!("proc {:?} is disabled", settings::SubSystemType::Mem);
-                return;
+                return Ok(());
             }
 
-            self.memory.refresh();
+            self.memory.refresh(&self.root)?;
         } else {
             warn!(
                 "get subsys {:?} from data source proc failed",
                 settings::SubSystemType::Mem
             );
         }
+        Ok(())
     }
 
-    pub fn refresh_proc_net(&mut self, ds_settings: &dyn settings::DataSource) {
+    pub fn refresh_proc_net(
+        &mut self,
+        ds_settings: &dyn settings::DataSource,
+    ) -> common::Result<()> {
         if let Some(sub_sys) = ds_settings.get_sub_system(settings::SubSystemType::Net) {
             if !sub_sys.is_enable() {
                 info!("proc {:?} is disabled", settings::SubSystemType::Net);
-                return;
+                return Ok(());
             }
 
-            self.net_traffic.refresh();
-            self.net_info.refresh();
+            self.net_traffic.refresh(&self.root)?;
+            self.net_info.refresh(&self.root)?;
         } else {
             warn!(
                 "get subsys {:?} from data source proc failed",
                 settings::SubSystemType::Net
             );
         }
+        Ok(())
     }
 
-    pub fn refresh_proc_disk(&mut self, ds_settings: &dyn settings::DataSource) {
+    pub fn refresh_proc_disk(
+        &mut self,
+        ds_settings: &dyn settings::DataSource,
+    ) -> common::Result<()> {
         if let Some(sub_sys) = ds_settings.get_sub_system(settings::SubSystemType::Storage) {
             if !sub_sys.is_enable() {
                 info!("proc {:?} is disabled", settings::SubSystemType::Storage);
-                return;
+                return Ok(());
             }
 
-            self.disk_io.refresh();
+            self.disk_io.refresh(&self.root)?;
         } else {
             warn!(
                 "get subsys {:?} from data source proc failed",
                 settings::SubSystemType::Storage
             );
         }
+        Ok(())
     }
 
-    pub fn refresh_proc(&mut self, ds_settings: &dyn settings::DataSource) {
+    /// fails with the collectors of the proc data source which failed
+    pub fn refresh_proc(
+        &mut self,
+        ds_settings: &dyn settings::DataSource,
+        due: &DueSubSystems,
+    ) -> common::Result<()> {
         if !ds_settings.is_enable() {
             info!("data source proc is disabled");
-            return;
+            return Ok(());
+        }
+
+        if due.is_empty() {
+            return Ok(());
+        }
+
+        let enabled = |t| {
+            ds_settings
+                .get_sub_system(t)
+                .is_some_and(|sub_sys| sub_sys.is_enable())
+        };
+        let mut results = vec![];
+        if due.contains(settings::SubSystemType::Cpu) {
+            results.push(self.collect(
+                Collector::ProcCpu,
+                enabled(settings::SubSystemType::Cpu),
+                |system| system.refresh_proc_cpu(ds_settings),
+            ));
+        }
+        if due.contains(settings::SubSystemType::Mem) {
+            results.push(self.collect(
+                Collector::ProcMem,
+                enabled(settings::SubSystemType::Mem),
+                |system| system.refresh_proc_mem(ds_settings),
+            ));
+        }
+        if due.contains(settings::SubSystemType::Net) {
+            results.push(self.collect(
+                Collector::ProcNet,
+                enabled(settings::SubSystemType::Net),
+                |system| system.refresh_proc_net(ds_settings),
+            ));
+        }
+        if due.contains(settings::SubSystemType::Storage) {
+            results.push(self.collect(
+                Collector::ProcDisk,
+                enabled(settings::SubSystemType::Storage),
+                |system| system.refresh_proc_disk(ds_settings),
+            ));
         }
 
-        self.refresh_proc_cpu(ds_settings);
-        self.refresh_proc_mem(ds_settings);
-        self.refresh_proc_net(ds_settings);
-        self.refresh_proc_disk(ds_settings);
+        // TODO: split cpu/men/io
+        results.push(self.collect(Collector::Psi, true, |system| system.refresh_psi()));
 
-        self.refresh_psi(); // TODO: split cpu/men/io
+        join_failures(results)
     }
 
-    pub fn refresh_sys(&mut self, ds_settings: &dyn settings::DataSource) {
+    pub fn refresh_sys(
+        &mut self,
+        ds_settings: &dyn settings::DataSource,
+        due: &DueSubSystems,
+    ) -> common::Result<()> {
         if !ds_settings.is_enable() {
             info!("data source sys is disabled");
-            return;
+            return Ok(());
+        }
+
+        if due.is_empty() {
+            return Ok(());
         }
 
-        self.system_devices_node.refresh_basic_info();
+        self.system_devices_node.refresh_basic_info()?;
         self.system_devices_node
             .refresh_numa_avaiable_mem(self.memory.vm_watermark_scale_factor());
+        Ok(())
     }
 
-    pub fn refresh(&mut self, s: &settings::Settings) {
+    /// refresh every data source sub system whose interval elapsed, fails with the
+    /// collectors which failed in this round; the others are refreshed regardless
+    pub fn refresh(&mut self, s: &settings::Settings) -> common::Result<()> {
         if !s.is_enable() {
             warn!("all data source is disabled");
-            return;
+            return Ok(());
         }
 
+        let now = Instant::now();
+        let tick = Duration::from_secs(s.get_refresh_tick());
+        let mut results = vec![];
+
         if let Some(ds_settings) = s.get_data_source(settings::DataSourceType::ProcFS) {
-            self.refresh_proc(&*ds_settings);
+            let due =
+                self.schedule
+                    .take_due(settings::DataSourceType::ProcFS, &*ds_settings, now, tick);
+            results.push(
+                self.refresh_proc(&*ds_settings, &due)
+                    .map_err(|e| e.to_string()),
+            );
         } else {
             warn!(
                 "get data source {:?} from settings failed",
@@ -550,7 +753,13 @@ impl System {
         }
 
         if let Some(ds_settings) = s.get_data_source(settings::DataSourceType::SysFS) {
-            self.refresh_sys(&*ds_settings);
+            let due =
+                self.schedule
+                    .take_due(settings::DataSourceType::SysFS, &*ds_settings, now, tick);
+            let active = ds_settings.is_enable() && !due.is_empty();
+            results.push(self.collect(Collector::Sys, active, |system| {
+                system.refresh_sys(&*ds_settings, &due)
+            }));
         } else {
             warn!(
                 "get data source {:?} from settings failed",
@@ -559,7 +768,19 @@ impl System {
         }
 
         if let Some(ds_settings) = s.get_data_source(settings::DataSourceType::CgroupFS) {
-            self.refresh_cgroups(&*ds_settings);
+            let due = self.schedule.take_due(
+                settings::DataSourceType::CgroupFS,
+                &*ds_settings,
+                now,
+                tick,
+            );
+            let active = ds_settings.is_enable() && !due.is_empty();
+            results.push(self.collect(Collector::Cgroups, active, |system| {
+                if active {
+                    system.refresh_cgroup_discovery(s.get_cgroup_discovery());
+                }
+                system.refresh_cgroups(&*ds_settings, &due)
+            }));
         } else {
             warn!(
                 "get data source {:?} from settings failed",
@@ -568,7 +789,13 @@ impl System {
         }
 
         if let Some(ds_settings) = s.get_data_source(settings::DataSourceType::Ebpf) {
-            self.refresh_ebpf(&*ds_settings);
+            let due =
+                self.schedule
+                    .take_due(settings::DataSourceType::Ebpf, &*ds_settings, now, tick);
+            let active = ds_settings.is_enable() && !due.is_empty();
+            results.push(self.collect(Collector::Ebpf, active, |system| {
+                system.refresh_ebpf(&*ds_settings, &due)
+            }));
         } else {
             warn!(
                 "get data source {:?} from settings failed",
@@ -577,13 +804,26 @@ impl System {
         }
 
         if let Some(ds_settings) = s.get_data_source(settings::DataSourceType::BytePerf) {
-            self.refresh_pmu(&*ds_settings);
+            let due = self.schedule.take_due(
+                settings::DataSourceType::BytePerf,
+                &*ds_settings,
+                now,
+                tick,
+            );
+            let active = ds_settings.is_enable()
+                && (due.contains(settings::SubSystemType::Cpu)
+                    || due.contains(settings::SubSystemType::Mem));
+            results.push(self.collect(Collector::Pmu, active, |system| {
+                system.refresh_pmu(&*ds_settings, &due)
+            }));
         } else {
             warn!(
                 "get data source {:?} from settings failed",
                 settings::DataSourceType::BytePerf
             );
         }
+
+        join_failures(results)
     }
 
     fn turn_off_all(&mut self) {
@@ -917,7 +1157,7 @@ impl System {
     pub fn turn_off_proc_cpu(&mut self) {
         info!("turn_off_proc_cpu");
         self.load.reset();
-        self.processors.reset();
+        self.processors.reset(&self.root);
         self.process_stats.reset();
     }
     pub fn turn_off_proc_mem(&mut self) {
@@ -946,3 +1186,118 @@ impl Default for System {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests_sys {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_system_with_root() {
+        let current_path: PathBuf = env::current_dir().unwrap();
+        let root = PathBuf::from(format!(
+            "{}/tests/sample/rootfs",
+            current_path.to_string_lossy()
+        ));
+        let mut system = System::with_root(&root);
+        assert_eq!(system.get_root(), root.as_path());
+
+        system
+            .refresh_proc(
+                &settings::DataSourceProcFS::default(),
+                &DueSubSystems::all(),
+            )
+            .unwrap();
+        system
+            .refresh_sys(&settings::DataSourceSysFS::default(), &DueSubSystems::all())
+            .unwrap();
+
+        assert_eq!(system.get_processors().len(), 8);
+        assert_eq!(system.get_load().one, 1.29);
+        assert_eq!(system.get_load().fifteen, 1.17);
+        assert_eq!(system.get_disk_io().get_stats().len(), 21);
+        assert_eq!(system.get_disk_io().get_usage().len(), 1);
+        assert!(system.get_system_pressure().is_some());
+        assert_eq!(system.get_system_device_nodes().get_nodes().len(), 4);
+        assert_eq!(system.get_net_traffic().len(), 6);
+        assert_eq!(system.get_memory_info().total_memory(), 16166776);
+    }
+
+    #[test]
+    fn test_insert_remove_cgroups_item() {
+        let mut system = System::new();
+        let user_path = PathBuf::from("kubepods/burstable/pod1");
+        let cgroup = CGroup::new(common::MOUNT_POINT, user_path.clone());
+        assert!(system.insert_cgroups_item(cgroup).unwrap());
+        assert_eq!(system.get_cgroup_list().len(), 1);
+        assert!(system.get_cgroups(user_path.clone()).is_some());
+        // registered without the leading `/`, found with it as discovery reports it
+        let discovered = PathBuf::from("/kubepods/burstable/pod1");
+        assert!(system.get_cgroup_list().contains_key(&discovered));
+        assert!(system.get_cgroups(discovered.clone()).is_some());
+        let cgroup = CGroup::new(common::MOUNT_POINT, discovered.clone());
+        assert!(system.insert_cgroups_item(cgroup).unwrap());
+        assert_eq!(system.get_cgroup_list().len(), 1);
+
+        assert!(system.remove_cgroups_item(&discovered).unwrap());
+        assert!(!system.remove_cgroups_item(&user_path).unwrap());
+        assert!(system.get_cgroup_list().is_empty());
+    }
+
+    #[test]
+    fn test_refresh_cgroups_error() {
+        let mount_point = env::temp_dir().join(format!("malachite-sys-{}", std::process::id()));
+        // a cgroup torn down but for its directories, whichever the cgroup version
+        fs::create_dir_all(mount_point.join("pod1")).unwrap();
+        fs::create_dir_all(mount_point.join("memory/pod1")).unwrap();
+        let cgroup = CGroup::new(&mount_point.to_string_lossy(), PathBuf::from("pod1"));
+        let ds_settings = settings::DataSourceCgroupFS::default();
+
+        // the error stays on the cgroup, the collector succeeds
+        let mut system = System::new();
+        system.insert_cgroups_item(cgroup.clone()).unwrap();
+        system
+            .refresh_cgroups(&ds_settings, &DueSubSystems::all())
+            .unwrap();
+        let updated = system.get_cgroups(PathBuf::from("pod1")).unwrap();
+        assert!(updated.last_error().is_some());
+        assert_eq!(
+            updated.normalize().last_error.as_deref(),
+            updated.last_error()
+        );
+        fs::remove_dir_all(&mount_point).unwrap();
+
+        // without the cgroup fs under the root the collector fails
+        let root = env::current_dir().unwrap().join("tests/sample/rootfs");
+        let mut system = System::with_root(&root);
+        system.insert_cgroups_item(cgroup).unwrap();
+        assert!(system
+            .refresh_cgroups(&ds_settings, &DueSubSystems::all())
+            .is_err());
+    }
+
+    #[test]
+    fn test_collect() {
+        let mut system = System::new();
+        let result = system.collect(Collector::Sys, true, |_| panic!("read error"));
+        assert_eq!(result, Err(String::from("Sys: read error")));
+        let result = system.collect(Collector::Psi, true, |_| Err("no such file".into()));
+        assert_eq!(result, Err(String::from("Psi: no such file")));
+        let result = system.collect(Collector::Ebpf, false, |_| Err("no such file".into()));
+        assert!(result.is_ok());
+        assert!(system.collect(Collector::Cgroups, true, |_| Ok(())).is_ok());
+
+        let stats = system.get_collector_stats();
+        let sys = stats.get(Collector::Sys).unwrap();
+        assert_eq!(sys.last_error.as_deref(), Some("read error"));
+        assert_eq!(sys.consecutive_failures, 1);
+        let psi = stats.get(Collector::Psi).unwrap();
+        assert_eq!(psi.last_error.as_deref(), Some("no such file"));
+        assert!(stats.get(Collector::Ebpf).is_none());
+        assert!(stats
+            .get(Collector::Cgroups)
+            .unwrap()
+            .last_success
+            .is_some());
+    }
+}
@@ -0,0 +1,463 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::cli::Cli;
+use crate::common::ApiError;
+use once_cell::sync::OnceCell;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::tokio::task::spawn_blocking;
+use rocket::Catcher;
+use rocket_basicauth::BasicAuth;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+static AUTHENTICATORS: OnceCell<Vec<Box<dyn Authenticator>>> = OnceCell::new();
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Role {
+    /// query metrics, cgroups and settings
+    Read,
+    /// also change settings and tracked cgroups
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Role::Read),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("unknown role {}, read or admin expected", s)),
+        }
+    }
+}
+
+/// resolves the role of the `Authorization` header value, None if the
+/// credentials are not of its scheme or do not match
+pub trait Authenticator: Send + Sync {
+    fn authenticate(&self, authorization: &str) -> Option<Role>;
+    /// `WWW-Authenticate` challenge of its scheme
+    fn challenge(&self) -> &'static str;
+}
+
+/// password of a basic auth user as found in the credentials file
+enum Password {
+    /// `$2b$...` as written by `htpasswd -nbB`
+    Bcrypt(String),
+    Plain(String),
+}
+
+impl Password {
+    fn parse(password: &str) -> Password {
+        if ["$2a$", "$2b$", "$2x$", "$2y$"]
+            .iter()
+            .any(|prefix| password.starts_with(prefix))
+        {
+            Password::Bcrypt(password.to_string())
+        } else {
+            Password::Plain(password.to_string())
+        }
+    }
+
+    fn verify(&self, password: &str) -> bool {
+        match self {
+            Password::Bcrypt(hash) => bcrypt::verify(password, hash).unwrap_or(false),
+            Password::Plain(plain) => constant_time_eq(plain.as_bytes(), password.as_bytes()),
+        }
+    }
+}
+
+/// basic auth, `username:password:role` per line, the password preferably bcrypt hashed
+pub struct BasicAuthenticator {
+    users: HashMap<String, (Password, Role)>,
+}
+
+impl BasicAuthenticator {
+    pub fn load(path: &Path) -> Result<BasicAuthenticator, String> {
+        let mut users = HashMap::new();
+        for (credential, role) in read_entries(path)? {
+            let (username, password) = credential
+                .split_once(':')
+                .ok_or_else(|| format!("{}: missing password of {}", path.display(), credential))?;
+            let password = Password::parse(password);
+            if let Password::Plain(_) = password {
+                warn!(
+                    "[auth] {}: password of {} is stored in plaintext, bcrypt hash it",
+                    path.display(),
+                    username
+                );
+            }
+            users.insert(username.to_string(), (password, role));
+        }
+        Ok(BasicAuthenticator { users })
+    }
+}
+
+impl Authenticator for BasicAuthenticator {
+    fn authenticate(&self, authorization: &str) -> Option<Role> {
+        let auth = BasicAuth::new(authorization)?;
+        let (password, role) = self.users.get(&auth.username)?;
+        if password.verify(&auth.password) {
+            Some(*role)
+        } else {
+            None
+        }
+    }
+
+    fn challenge(&self) -> &'static str {
+        "Basic realm=\"malachite\""
+    }
+}
+
+/// static bearer tokens, `token:role` per line
+pub struct BearerAuthenticator {
+    tokens: Vec<(String, Role)>,
+}
+
+impl BearerAuthenticator {
+    pub fn load(path: &Path) -> Result<BearerAuthenticator, String> {
+        Ok(BearerAuthenticator {
+            tokens: read_entries(path)?,
+        })
+    }
+}
+
+impl Authenticator for BearerAuthenticator {
+    fn authenticate(&self, authorization: &str) -> Option<Role> {
+        let token = authorization.strip_prefix("Bearer ")?.trim();
+        self.tokens
+            .iter()
+            .find(|(t, _)| constant_time_eq(t.as_bytes(), token.as_bytes()))
+            .map(|(_, role)| *role)
+    }
+
+    fn challenge(&self) -> &'static str {
+        "Bearer realm=\"malachite\""
+    }
+}
+
+/// non-empty lines which are not `#` comments, split at the last `:`
+fn read_entries(path: &Path) -> Result<Vec<(String, Role)>, String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("read {} error: {}", path.display(), e))?;
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (key, role) = line
+                .rsplit_once(':')
+                .ok_or_else(|| format!("{}: missing role in line", path.display()))?;
+            let role = role
+                .trim()
+                .parse()
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            Ok((key.to_string(), role))
+        })
+        .collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// load the configured authenticators, without any every request is granted admin access
+pub fn init(cli: &Cli) -> Result<(), String> {
+    let mut authenticators: Vec<Box<dyn Authenticator>> = vec![];
+    if let Some(path) = &cli.auth_credentials_file {
+        authenticators.push(Box::new(BasicAuthenticator::load(path)?));
+    }
+    if let Some(path) = &cli.auth_tokens_file {
+        authenticators.push(Box::new(BearerAuthenticator::load(path)?));
+    }
+    if authenticators.is_empty() {
+        warn!("[auth] no credentials configured, API is open to anyone who can reach it");
+    }
+    AUTHENTICATORS
+        .set(authenticators)
+        .map_err(|_| String::from("authenticators already set"))
+}
+
+fn authorize(
+    authenticators: &[Box<dyn Authenticator>],
+    authorization: Option<&str>,
+    required: Role,
+) -> Outcome<Role, ()> {
+    if authenticators.is_empty() {
+        return Outcome::Success(Role::Admin);
+    }
+    let role =
+        authorization.and_then(|header| authenticators.iter().find_map(|a| a.authenticate(header)));
+    match role {
+        None => Outcome::Error((Status::Unauthorized, ())),
+        Some(role) if role < required => Outcome::Error((Status::Forbidden, ())),
+        Some(role) => Outcome::Success(role),
+    }
+}
+
+/// bcrypt takes a while on purpose, so credentials are checked off the async workers
+async fn authorize_request(req: &Request<'_>, required: Role) -> Outcome<Role, ()> {
+    let authenticators = match AUTHENTICATORS.get() {
+        Some(authenticators) if !authenticators.is_empty() => authenticators,
+        _ => return Outcome::Success(Role::Admin),
+    };
+    let authorization = req.headers().get_one("Authorization").map(str::to_string);
+    spawn_blocking(move || authorize(authenticators, authorization.as_deref(), required))
+        .await
+        .unwrap_or(Outcome::Error((Status::InternalServerError, ())))
+}
+
+fn challenges(authenticators: &[Box<dyn Authenticator>]) -> Vec<&'static str> {
+    authenticators.iter().map(|a| a.challenge()).collect()
+}
+
+#[catch(401)]
+fn unauthorized() -> ApiError {
+    ApiError::Unauthorized(challenges(AUTHENTICATORS.get().map_or(&[], Vec::as_slice)))
+}
+
+#[catch(403)]
+fn forbidden() -> ApiError {
+    ApiError::Forbidden
+}
+
+/// answer the guards' 401 and 403 with an error body like any other API error
+pub fn catchers() -> Vec<Catcher> {
+    catchers![unauthorized, forbidden]
+}
+
+/// request guard of read-only routes
+pub struct ReadAccess {
+    role: Role,
+}
+
+impl ReadAccess {
+    /// whether the client may also track new cgroups
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ReadAccess {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize_request(req, Role::Read)
+            .await
+            .map(|role| ReadAccess { role })
+    }
+}
+
+/// request guard of routes changing settings or tracked cgroups
+pub struct AdminAccess;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminAccess {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize_request(req, Role::Admin)
+            .await
+            .map(|_| AdminAccess)
+    }
+}
+
+#[cfg(test)]
+mod tests_auth {
+    use super::*;
+    use rocket::local::blocking::Client;
+    use rocket::serde::json::{serde_json, Value};
+    use std::env;
+    use std::process;
+
+    fn write_entries(name: &str, content: &str) -> std::path::PathBuf {
+        let path = env::temp_dir().join(format!("malachite-auth-{}-{}", name, process::id()));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_read_entries() {
+        let path = write_entries(
+            "entries",
+            "# comment\n\n  alice:secret:admin  \nbob:$2b$04$abc:def:read\n",
+        );
+        let entries = read_entries(&path).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                ("alice:secret".to_string(), Role::Admin),
+                ("bob:$2b$04$abc:def".to_string(), Role::Read),
+            ]
+        );
+
+        fs::write(&path, "token\n").unwrap();
+        assert!(read_entries(&path).unwrap_err().contains("missing role"));
+        fs::write(&path, "token:root\n").unwrap();
+        assert!(read_entries(&path)
+            .unwrap_err()
+            .contains("unknown role root"));
+        fs::remove_file(&path).unwrap();
+        assert!(read_entries(&path).is_err());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(!constant_time_eq(b"secret", b""));
+    }
+
+    #[test]
+    fn test_authorize() {
+        let hash = bcrypt::hash("hunter2", 4).unwrap();
+        let credentials = write_entries(
+            "credentials",
+            &format!("alice:secret:admin\nbob:{}:read\n", hash),
+        );
+        let tokens = write_entries("tokens", "t0ken:read\n");
+        let authenticators: Vec<Box<dyn Authenticator>> = vec![
+            Box::new(BasicAuthenticator::load(&credentials).unwrap()),
+            Box::new(BearerAuthenticator::load(&tokens).unwrap()),
+        ];
+        fs::remove_file(&credentials).unwrap();
+        fs::remove_file(&tokens).unwrap();
+
+        // alice:secret, plaintext
+        let alice = Some("Basic YWxpY2U6c2VjcmV0");
+        // bob:hunter2 and bob:wrong, bcrypt hashed
+        let bob = Some("Basic Ym9iOmh1bnRlcjI=");
+        let bob_wrong = Some("Basic Ym9iOndyb25n");
+
+        assert_eq!(
+            authorize(&authenticators, alice, Role::Admin),
+            Outcome::Success(Role::Admin)
+        );
+        assert_eq!(
+            authorize(&authenticators, bob, Role::Read),
+            Outcome::Success(Role::Read)
+        );
+        assert_eq!(
+            authorize(&authenticators, bob, Role::Admin),
+            Outcome::Error((Status::Forbidden, ()))
+        );
+        assert_eq!(
+            authorize(&authenticators, bob_wrong, Role::Read),
+            Outcome::Error((Status::Unauthorized, ()))
+        );
+        assert_eq!(
+            authorize(&authenticators, Some("Bearer t0ken"), Role::Read),
+            Outcome::Success(Role::Read)
+        );
+        assert_eq!(
+            authorize(&authenticators, Some("Bearer t0ke"), Role::Read),
+            Outcome::Error((Status::Unauthorized, ()))
+        );
+        assert_eq!(
+            authorize(&authenticators, None, Role::Read),
+            Outcome::Error((Status::Unauthorized, ()))
+        );
+
+        // without authenticators the API is open
+        assert_eq!(
+            authorize(&[], None, Role::Admin),
+            Outcome::Success(Role::Admin)
+        );
+    }
+
+    #[get("/unauthorized")]
+    fn unauthorized_status() -> Status {
+        Status::Unauthorized
+    }
+
+    #[get("/forbidden")]
+    fn forbidden_status() -> Status {
+        Status::Forbidden
+    }
+
+    #[get("/challenge")]
+    fn challenge() -> ApiError {
+        ApiError::Unauthorized(vec![
+            "Basic realm=\"malachite\"",
+            "Bearer realm=\"malachite\"",
+        ])
+    }
+
+    fn get(client: &Client, uri: &str) -> (Status, Vec<String>, Value) {
+        let resp = client.get(uri).dispatch();
+        let status = resp.status();
+        let challenges = resp
+            .headers()
+            .get("WWW-Authenticate")
+            .map(str::to_string)
+            .collect();
+        (
+            status,
+            challenges,
+            serde_json::from_str(&resp.into_string().unwrap()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_catchers() {
+        let tokens = write_entries("catchers", "t0ken:admin\n");
+        let authenticators: Vec<Box<dyn Authenticator>> =
+            vec![Box::new(BearerAuthenticator::load(&tokens).unwrap())];
+        fs::remove_file(&tokens).unwrap();
+        assert_eq!(
+            challenges(&authenticators),
+            vec!["Bearer realm=\"malachite\""]
+        );
+
+        let rocket = rocket::build()
+            .mount(
+                "/",
+                routes![unauthorized_status, forbidden_status, challenge],
+            )
+            .register("/", catchers());
+        let client = Client::untracked(rocket).unwrap();
+
+        // no authenticators are set in the tests, so the catcher has no challenge to send
+        let (status, challenges, body) = get(&client, "/unauthorized");
+        assert_eq!(status, Status::Unauthorized);
+        assert!(challenges.is_empty());
+        assert_eq!(
+            body,
+            serde_json::json!({"status": 1008, "message": "authentication required"})
+        );
+
+        let (status, challenges, body) = get(&client, "/challenge");
+        assert_eq!(status, Status::Unauthorized);
+        assert_eq!(
+            challenges,
+            vec!["Basic realm=\"malachite\"", "Bearer realm=\"malachite\""]
+        );
+        assert_eq!(body["status"], 1008);
+
+        let (status, _, body) = get(&client, "/forbidden");
+        assert_eq!(status, Status::Forbidden);
+        assert_eq!(
+            body,
+            serde_json::json!({"status": 1009, "message": "permission denied"})
+        );
+    }
+}
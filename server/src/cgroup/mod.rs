@@ -13,67 +13,471 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use crate::common::Resp;
+use crate::auth::{AdminAccess, ReadAccess};
+use crate::common::{ApiError, ApiResult, Resp};
+use crate::history::{self, RangeQuery, RespRange, Section};
+use crate::projection::Projection;
 use crate::system;
 use lib::cgroup;
-use lib::common::MOUNT_POINT;
-use lib::settings;
-use rocket::http::Status;
-use rocket::serde::json::Json;
-use std::path::PathBuf;
+use lib::common;
+use lib::settings::{self, DataSourceType};
+use lib::system::{Collector, DueSubSystems};
+use rocket::serde::json::{Json, Value};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::task::spawn_blocking;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::thread;
 use utoipa;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema, Debug)]
+pub(crate) struct RespCGroupItem {
+    /// user cgroup relative path
+    pub(crate) user_path: PathBuf,
+    /// last update time in seconds since epoch, 0 if not collected yet
+    pub(crate) update_time: u64,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub(crate) struct ReqCGroupBatch {
+    /// user cgroup relative paths
+    pub(crate) cgroup_user_paths: Vec<String>,
+    /// only return these subsystems, all if absent
+    pub(crate) subsystems: Option<Vec<cgroup::SubSystemType>>,
+}
+
+#[derive(Serialize, ToSchema, Debug)]
+pub(crate) struct RespCGroupBatchItem {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cgroup: Option<cgroup::CGroup>,
+    /// why the cgroup is missing, or what failed in its last update
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) message: Option<String>,
+}
+
+/// build and collect a cgroup that is not tracked yet, None if it does not exist
+fn new_cgroup(cgroup_user_path: &str, settings: &settings::Settings) -> Option<cgroup::CGroup> {
+    let ds_settings = settings.get_data_source(settings::DataSourceType::CgroupFS)?;
+    let mount_point = common::cgroup_mount_point();
+    let mut cgroup = cgroup::CGroup::new(
+        &mount_point.to_string_lossy(),
+        PathBuf::from(cgroup_user_path),
+    );
+
+    if cgroup.update(&*ds_settings, &DueSubSystems::all()).is_err() {
+        warn!(
+            "[server] new cgroup update error, path= {}",
+            cgroup.user_path().display()
+        )
+    }
+
+    if !cgroup.is_valid() {
+        return None;
+    }
+    Some(cgroup)
+}
+
+/// hand new cgroups to the monitor so that they are tracked from the next cycle
+fn register_cgroups(cgroups: Vec<cgroup::CGroup>) {
+    thread::spawn(move || {
+        let sender = system::CHANGE_CHANNEL.get_channel_sender().clone();
+        for cgroup in cgroups {
+            sender
+                .send(system::ChangeEvent::CGroupEvent(cgroup))
+                .unwrap();
+        }
+    })
+    .join()
+    .unwrap();
+}
+
+/// the tracked cgroup, or a freshly collected one which the monitor tracks from then on
+fn find_or_track_cgroup(
+    cgroup_user_path: String,
+    access: &ReadAccess,
+) -> Result<cgroup::CGroup, ApiError> {
+    let reader_lock = system::MONITOR.get_monitor_reader();
+    if let Some(cgroup_info) = reader_lock
+        .read()
+        .get_cgroups(PathBuf::from(&cgroup_user_path))
+    {
+        return Ok(cgroup_info.clone());
+    };
+
+    // tracking a new cgroup changes what the monitor collects
+    if !access.is_admin() {
+        return Err(ApiError::CGroupNotTracked(cgroup_user_path));
+    }
+    let settings = system::MONITOR.get_settings().read().clone();
+    match new_cgroup(&cgroup_user_path, &settings) {
+        Some(cgroup) => {
+            register_cgroups(vec![cgroup.clone()]);
+            Ok(cgroup)
+        }
+        None => Err(ApiError::CGroupNotFound(cgroup_user_path)),
+    }
+}
 
 /// per cgroup info
 #[utoipa::path(
     context_path = "/api/v1/cgroup",
+    params(
+        RangeQuery,
+        ("fields" = Option<String>, Query, description = "comma separated fields to keep, dotted for nested ones, e.g. `user_path,sub_system_groups.Memory`"),
+        ("subsystems" = Option<String>, Query, description = "comma separated sub systems to keep, e.g. `memory,cpuacct`"),
+        ("numa" = Option<String>, Query, description = "NUMA nodes of the memory stats to keep, e.g. `0-1`"),
+    ),
+    responses(
+        (status = 200, description = "per cgroup API, or the cgroup history when a range is given", body = RespRangeCGroup),
+        (status = 404, description = "cgroup not found, or not tracked and tracking it requires admin access", body = RespErrorBody),
+        (status = "4XX", description = "invalid query or data source disabled", body = RespErrorBody),
+        (status = "5XX", description = "collector failed or snapshot stale", body = RespErrorBody)
+    )
+)]
+#[get("/groups?<cgroup_user_path>&<fields>&<subsystems>&<numa>&<range..>")]
+async fn get_cgroup_info(
+    cgroup_user_path: String,
+    fields: Option<String>,
+    subsystems: Option<String>,
+    numa: Option<String>,
+    range: RangeQuery,
+    access: ReadAccess,
+) -> ApiResult<RespRange<Value>> {
+    let projection = Projection::new(fields, subsystems, None, numa)?;
+    if range.is_range() {
+        // series are labelled by the normalized path the cgroup is tracked under
+        let path = cgroup::normalize_user_path(Path::new(&cgroup_user_path))
+            .display()
+            .to_string();
+        let series = history::query(Section::CGroup, &[("cgroup", &path)], &range);
+        return Ok(Json(Resp::new(RespRange::Range(
+            projection.apply_series(series)?,
+        ))));
+    }
+
+    system::MONITOR.check_data_source(DataSourceType::CgroupFS)?;
+    system::MONITOR.check_snapshot(&[Collector::Cgroups])?;
+    let cgroup = find_or_track_cgroup(cgroup_user_path, &access)?;
+    Ok(Json(Resp::new(RespRange::Latest(
+        projection.apply(cgroup)?,
+    ))))
+}
+
+/// per cgroup metrics, the same fields whichever cgroup version the host runs
+#[utoipa::path(
+    context_path = "/api/v2/cgroup",
     responses(
-        (status = 200, description = "per cgroup API", body = [CGroup])
+        (status = 200, description = "normalized per cgroup API", body = [CGroupMetrics]),
+        (status = 404, description = "cgroup not found, or not tracked and tracking it requires admin access", body = RespErrorBody),
+        (status = "4XX", description = "data source disabled", body = RespErrorBody),
+        (status = "5XX", description = "collector failed or snapshot stale", body = RespErrorBody)
     )
 )]
 #[get("/groups?<cgroup_user_path>")]
-async fn get_cgroup_info(cgroup_user_path: String) -> Result<Json<Resp<cgroup::CGroup>>, Status> {
+async fn get_cgroup_metrics(
+    cgroup_user_path: String,
+    access: ReadAccess,
+) -> ApiResult<cgroup::CGroupMetrics> {
+    system::MONITOR.check_data_source(DataSourceType::CgroupFS)?;
+    system::MONITOR.check_snapshot(&[Collector::Cgroups])?;
+    let cgroup = find_or_track_cgroup(cgroup_user_path, &access)?;
+    Ok(Json(Resp::new(cgroup.normalize())))
+}
+
+/// a cgroup with its tracked descendants aggregated into it, and the usage none of
+/// them accounts for; only tracked cgroups show up, nothing gets tracked
+#[utoipa::path(
+    context_path = "/api/v2/cgroup",
+    responses(
+        (status = 200, description = "cgroup roll-up tree API", body = CGroupRollup),
+        (status = 404, description = "neither the cgroup nor any of its descendants is tracked", body = RespErrorBody),
+        (status = "4XX", description = "data source disabled", body = RespErrorBody),
+        (status = "5XX", description = "collector failed or snapshot stale", body = RespErrorBody)
+    )
+)]
+#[get("/tree?<cgroup_user_path>")]
+async fn get_cgroup_tree(
+    cgroup_user_path: String,
+    _access: ReadAccess,
+) -> ApiResult<cgroup::CGroupRollup> {
+    system::MONITOR.check_data_source(DataSourceType::CgroupFS)?;
+    system::MONITOR.check_snapshot(&[Collector::Cgroups])?;
+
     let reader_lock = system::MONITOR.get_monitor_reader();
-    if let Some(cgroup_info) = reader_lock
-        .read()
-        .get_cgroups(PathBuf::from(&cgroup_user_path))
-    {
-        return Ok(Json(Resp::new(cgroup_info.clone())));
-    };
+    let reader = reader_lock.read();
+    let tree = cgroup::rollup_cgroups(
+        Path::new(&cgroup_user_path),
+        reader
+            .get_cgroup_list()
+            .values()
+            .map(|cgroup| cgroup.normalize()),
+    );
+    if tree.usage.is_none() && tree.children.is_empty() {
+        return Err(ApiError::CGroupNotTracked(cgroup_user_path));
+    }
+    Ok(Json(Resp::new(tree)))
+}
 
-    let settings = system::MONITOR.get_settings().read().clone();
-    let mut cgroup = cgroup::CGroup::new(MOUNT_POINT, PathBuf::from(&cgroup_user_path));
-
-    if let Some(ds_settings) = settings.get_data_source(settings::DataSourceType::CgroupFS) {
-        if cgroup.update(&*ds_settings).is_err() {
-            warn!(
-                "[server] new cgroup update error, path= {}",
-                cgroup.user_path().display()
-            )
+/// most cgroups a batch request may ask for
+const MAX_BATCH_SIZE: usize = 256;
+
+/// answers a batch from the cgroups found tracked, `collect` builds the missing ones when
+/// the caller may track them; also returns the collected cgroups to track
+fn batch_items<F>(
+    req: &ReqCGroupBatch,
+    mut tracked: HashMap<String, cgroup::CGroup>,
+    collect: Option<F>,
+) -> (HashMap<String, RespCGroupBatchItem>, Vec<cgroup::CGroup>)
+where
+    F: Fn(&str) -> Option<cgroup::CGroup>,
+{
+    let select = |mut cgroup: cgroup::CGroup| {
+        if let Some(subsystems) = &req.subsystems {
+            cgroup
+                .sub_system_groups
+                .retain(|sub_system_type, _| subsystems.contains(sub_system_type));
         }
+        cgroup
+    };
 
-        if !cgroup.is_valid() {
-            return Err(Status::NotFound);
+    let mut result: HashMap<String, RespCGroupBatchItem> = HashMap::new();
+    let mut new_cgroups = vec![];
+    for cgroup_user_path in req.cgroup_user_paths.iter() {
+        if result.contains_key(cgroup_user_path) {
+            continue;
         }
+        let item = match (tracked.remove(cgroup_user_path), &collect) {
+            (Some(cgroup), _) => RespCGroupBatchItem {
+                message: cgroup.last_error().map(str::to_string),
+                cgroup: Some(select(cgroup)),
+            },
+            (None, None) => RespCGroupBatchItem {
+                cgroup: None,
+                message: Some(ApiError::CGroupNotTracked(cgroup_user_path.clone()).to_string()),
+            },
+            (None, Some(collect)) => match collect(cgroup_user_path) {
+                Some(cgroup) => {
+                    new_cgroups.push(cgroup.clone());
+                    RespCGroupBatchItem {
+                        message: cgroup.last_error().map(str::to_string),
+                        cgroup: Some(select(cgroup)),
+                    }
+                }
+                None => RespCGroupBatchItem {
+                    cgroup: None,
+                    message: Some(ApiError::CGroupNotFound(cgroup_user_path.clone()).to_string()),
+                },
+            },
+        };
+        result.insert(cgroup_user_path.clone(), item);
+    }
+    (result, new_cgroups)
+}
 
-        let cgroup_tmp = cgroup.clone();
-        thread::spawn(move || {
-            let sender = system::CHANGE_CHANNEL.get_channel_sender().clone();
-            sender
-                .send(system::ChangeEvent::CGroupEvent(cgroup_tmp))
-                .unwrap();
+/// batch cgroup info, unknown cgroups are registered as in the per cgroup API
+#[utoipa::path(
+    context_path = "/api/v1/cgroup",
+    request_body = ReqCGroupBatch,
+    responses(
+        (status = 200, description = "batch cgroup API", body = HashMap<String, RespCGroupBatchItem>),
+        (status = 400, description = "more than 256 cgroups are asked for", body = RespErrorBody)
+    )
+)]
+#[post("/groups/batch", data = "<req>")]
+async fn get_cgroup_batch(
+    req: Json<ReqCGroupBatch>,
+    access: ReadAccess,
+) -> ApiResult<HashMap<String, RespCGroupBatchItem>> {
+    system::MONITOR.check_data_source(DataSourceType::CgroupFS)?;
+    system::MONITOR.check_snapshot(&[Collector::Cgroups])?;
+    let req = req.into_inner();
+    if req.cgroup_user_paths.len() > MAX_BATCH_SIZE {
+        return Err(ApiError::InvalidQuery(format!(
+            "at most {} cgroups per batch, got {}",
+            MAX_BATCH_SIZE,
+            req.cgroup_user_paths.len()
+        )));
+    }
+
+    // collecting untracked cgroups reads their files, keep it off the async workers
+    let is_admin = access.is_admin();
+    let (result, new_cgroups) = spawn_blocking(move || {
+        let tracked: HashMap<String, cgroup::CGroup> = {
+            let reader_lock = system::MONITOR.get_monitor_reader();
+            let reader = reader_lock.read();
+            req.cgroup_user_paths
+                .iter()
+                .filter_map(|path| {
+                    reader
+                        .get_cgroups(PathBuf::from(path))
+                        .map(|cgroup| (path.clone(), cgroup.clone()))
+                })
+                .collect()
+        };
+        let settings = system::MONITOR.get_settings().read().clone();
+        batch_items(
+            &req,
+            tracked,
+            is_admin.then_some(|path: &str| new_cgroup(path, &settings)),
+        )
+    })
+    .await
+    .map_err(|e| ApiError::Internal(format!("batch cgroup error: {}", e)))?;
+    if !new_cgroups.is_empty() {
+        register_cgroups(new_cgroups);
+    }
+
+    Ok(Json(Resp::new(result)))
+}
+
+/// list tracked cgroups
+#[utoipa::path(
+    context_path = "/api/v1/cgroup",
+    responses(
+        (status = 200, description = "tracked cgroup list API", body = [RespCGroupItem])
+    )
+)]
+#[get("/groups", rank = 2)]
+async fn list_cgroups(_access: ReadAccess) -> ApiResult<Vec<RespCGroupItem>> {
+    let reader_lock = system::MONITOR.get_monitor_reader();
+    let mut cgroups = reader_lock
+        .read()
+        .get_cgroup_list()
+        .iter()
+        .map(|(user_path, cgroup)| RespCGroupItem {
+            user_path: user_path.clone(),
+            update_time: cgroup.update_time(),
         })
-        .join()
-        .unwrap();
+        .collect::<Vec<RespCGroupItem>>();
+    cgroups.sort_by(|a, b| a.user_path.cmp(&b.user_path));
+    Ok(Json(Resp::new(cgroups)))
+}
 
-        return Ok(Json(Resp::new(cgroup)));
+/// stop tracking a cgroup, cgroups matching `cgroup.discovery` can only be untracked
+/// by changing the patterns, as discovery would track them again
+#[utoipa::path(
+    context_path = "/api/v1/cgroup",
+    responses(
+        (status = 200, description = "untrack cgroup API", body = [String]),
+        (status = 404, description = "cgroup is not tracked", body = RespErrorBody),
+        (status = 409, description = "cgroup matches cgroup.discovery", body = RespErrorBody)
+    )
+)]
+#[delete("/groups?<cgroup_user_path>")]
+async fn delete_cgroup(cgroup_user_path: String, _access: AdminAccess) -> ApiResult<String> {
+    let reader_lock = system::MONITOR.get_monitor_reader();
+    let path = PathBuf::from(&cgroup_user_path);
+    if reader_lock.read().get_cgroups(path.clone()).is_none() {
+        return Err(ApiError::CGroupNotTracked(cgroup_user_path));
+    }
+    let discovered = cgroup::match_user_path(
+        system::MONITOR.get_settings().read().get_cgroup_discovery(),
+        &path,
+    );
+    if discovered {
+        return Err(ApiError::CGroupDiscovered(cgroup_user_path));
     }
 
-    Err(Status::NotFound)
+    thread::spawn(move || {
+        let sender = system::CHANGE_CHANNEL.get_channel_sender().clone();
+        sender
+            .send(system::ChangeEvent::CGroupRemoveEvent(path))
+            .unwrap();
+    })
+    .join()
+    .unwrap();
+
+    Ok(Json(Resp::new(cgroup_user_path)))
 }
 
 pub fn cgroup_v1_router() -> rocket::fairing::AdHoc {
     rocket::fairing::AdHoc::on_ignite("JSON", |rocket| async {
-        rocket.mount("/api/v1/cgroup", routes![get_cgroup_info])
+        rocket.mount(
+            "/api/v1/cgroup",
+            routes![
+                get_cgroup_info,
+                get_cgroup_batch,
+                list_cgroups,
+                delete_cgroup
+            ],
+        )
+    })
+}
+
+pub fn cgroup_v2_router() -> rocket::fairing::AdHoc {
+    rocket::fairing::AdHoc::on_ignite("JSON", |rocket| async {
+        rocket.mount(
+            "/api/v2/cgroup",
+            routes![get_cgroup_metrics, get_cgroup_tree],
+        )
     })
 }
+
+#[cfg(test)]
+mod tests_cgroup {
+    use super::*;
+
+    fn new_test_cgroup(user_path: &str) -> cgroup::CGroup {
+        cgroup::CGroup::new("/sys/fs/cgroup", PathBuf::from(user_path))
+    }
+
+    fn batch(paths: &[&str], subsystems: Option<Vec<cgroup::SubSystemType>>) -> ReqCGroupBatch {
+        ReqCGroupBatch {
+            cgroup_user_paths: paths.iter().map(|path| path.to_string()).collect(),
+            subsystems,
+        }
+    }
+
+    #[test]
+    fn test_batch_items_tracked() {
+        let req = batch(&["/a", "/b"], Some(vec![cgroup::SubSystemType::Memory]));
+        let tracked = HashMap::from([("/a".to_string(), new_test_cgroup("/a"))]);
+        let (result, new_cgroups) = batch_items(&req, tracked, None::<fn(&str) -> _>);
+        assert!(new_cgroups.is_empty());
+        assert_eq!(result.len(), 2);
+
+        let a = result["/a"].cgroup.as_ref().unwrap();
+        assert_eq!(a.user_path(), Path::new("/a"));
+        assert_eq!(
+            a.sub_system_groups.keys().collect::<Vec<_>>(),
+            vec![&cgroup::SubSystemType::Memory]
+        );
+        assert!(result["/a"].message.is_none());
+
+        // without admin access untracked cgroups are not collected
+        assert!(result["/b"].cgroup.is_none());
+        assert_eq!(
+            result["/b"].message,
+            Some(ApiError::CGroupNotTracked("/b".to_string()).to_string())
+        );
+    }
+
+    #[test]
+    fn test_batch_items_collect() {
+        let req = batch(&["/a", "/b", "/c", "/b"], None);
+        let tracked = HashMap::from([("/a".to_string(), new_test_cgroup("/a"))]);
+        let collect = |path: &str| (path == "/b").then(|| new_test_cgroup(path));
+        let (result, new_cgroups) = batch_items(&req, tracked, Some(collect));
+        assert_eq!(result.len(), 3);
+
+        // tracked cgroups are not collected again, and repeated paths only once
+        assert_eq!(new_cgroups.len(), 1);
+        assert_eq!(new_cgroups[0].user_path(), Path::new("/b"));
+        assert_eq!(
+            result["/b"]
+                .cgroup
+                .as_ref()
+                .unwrap()
+                .sub_system_groups
+                .len(),
+            new_cgroups[0].sub_system_groups.len()
+        );
+        assert!(result["/a"].cgroup.is_some());
+        assert!(result["/c"].cgroup.is_none());
+        assert_eq!(
+            result["/c"].message,
+            Some(ApiError::CGroupNotFound("/c".to_string()).to_string())
+        );
+    }
+}
@@ -0,0 +1,130 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use clap::Parser;
+use once_cell::sync::OnceCell;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+static CLI: OnceCell<Cli> = OnceCell::new();
+
+/// node and cgroup metrics daemon
+///
+/// settings are also overridden by environment variables such as
+/// `MALACHITE_EBPF__ENABLE=false`, `__` separating nested fields
+#[derive(Parser, Debug)]
+#[command(name = "malachite", version)]
+pub struct Cli {
+    /// settings file, `static/config/default.toml` next to the binary by default
+    #[arg(long, env = "MALACHITE_CONFIG_FILE")]
+    pub config_file: Option<PathBuf>,
+    /// settings changed through the API are persisted to this file,
+    /// `static/config/state.json` next to the binary by default
+    #[arg(long, env = "MALACHITE_STATE_FILE")]
+    pub state_file: Option<PathBuf>,
+    /// log4rs config file, `static/config/log4rs.toml` next to the binary by default
+    #[arg(long, env = "MALACHITE_LOG_CONFIG_FILE")]
+    pub log_config_file: Option<PathBuf>,
+    /// PMU config dir, `static/pmu_config` next to the binary by default
+    #[arg(long, env = "MALACHITE_PMU_CONFIG_DIR")]
+    pub pmu_config_dir: Option<PathBuf>,
+    /// listen address, rocket's default if unset
+    #[arg(long, env = "MALACHITE_ADDRESS")]
+    pub address: Option<IpAddr>,
+    /// listen port, rocket's default if unset
+    #[arg(long, env = "MALACHITE_PORT")]
+    pub port: Option<u16>,
+    /// root that /proc, /sys and the cgroup mount point are read under, e.g. /host
+    #[arg(long, env = "MALACHITE_HOST_ROOT")]
+    pub host_root: Option<PathBuf>,
+    /// cgroup fs mount point, /sys/fs/cgroup under the host root by default
+    #[arg(long, env = "MALACHITE_CGROUP_MOUNT_POINT")]
+    pub cgroup_mount_point: Option<PathBuf>,
+    /// also serve the API on this unix domain socket path
+    #[arg(long, env = "MALACHITE_UNIX_SOCKET")]
+    pub unix_socket: Option<PathBuf>,
+    /// file mode of the unix domain socket, in octal
+    #[arg(long, env = "MALACHITE_UNIX_SOCKET_MODE", default_value = "660", value_parser = parse_mode)]
+    pub unix_socket_mode: u32,
+    /// owner of the unix domain socket, `uid[:gid]`
+    #[arg(long, env = "MALACHITE_UNIX_SOCKET_OWNER", value_parser = parse_owner)]
+    pub unix_socket_owner: Option<SocketOwner>,
+    /// serve the API on the unix domain socket only
+    #[arg(long, env = "MALACHITE_NO_TCP", requires = "unix_socket")]
+    pub no_tcp: bool,
+    /// basic auth credentials, `username:password:role` per line, role being read or admin
+    #[arg(long, env = "MALACHITE_AUTH_CREDENTIALS_FILE")]
+    pub auth_credentials_file: Option<PathBuf>,
+    /// static bearer tokens, `token:role` per line
+    #[arg(long, env = "MALACHITE_AUTH_TOKENS_FILE")]
+    pub auth_tokens_file: Option<PathBuf>,
+    /// PEM certificate chain, serves HTTPS together with `--tls-key`
+    #[arg(long, env = "MALACHITE_TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key of the certificate
+    #[arg(long, env = "MALACHITE_TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+    /// PEM CA certificates which client certificates are verified against, enables mutual TLS
+    #[arg(long, env = "MALACHITE_TLS_CLIENT_CA", requires = "tls_cert")]
+    pub tls_client_ca: Option<PathBuf>,
+    /// accept clients without a certificate under mutual TLS
+    #[arg(
+        long,
+        env = "MALACHITE_TLS_CLIENT_AUTH_OPTIONAL",
+        requires = "tls_client_ca"
+    )]
+    pub tls_client_auth_optional: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SocketOwner {
+    pub uid: u32,
+    pub gid: Option<u32>,
+}
+
+fn parse_mode(s: &str) -> Result<u32, String> {
+    let mode = u32::from_str_radix(s.trim_start_matches("0o"), 8)
+        .map_err(|e| format!("invalid octal mode {}: {}", s, e))?;
+    if mode > 0o777 {
+        return Err(format!("invalid mode {}, at most 777", s));
+    }
+    Ok(mode)
+}
+
+fn parse_owner(s: &str) -> Result<SocketOwner, String> {
+    let (uid, gid) = match s.split_once(':') {
+        Some((uid, gid)) => (uid, Some(gid)),
+        None => (s, None),
+    };
+    let uid = uid
+        .parse()
+        .map_err(|e| format!("invalid uid {}: {}", uid, e))?;
+    let gid = gid
+        .map(|gid| {
+            gid.parse()
+                .map_err(|e| format!("invalid gid {}: {}", gid, e))
+        })
+        .transpose()?;
+    Ok(SocketOwner { uid, gid })
+}
+
+/// parse the command line, exits on invalid arguments
+pub fn parse() -> &'static Cli {
+    CLI.get_or_init(Cli::parse)
+}
+
+pub fn get() -> &'static Cli {
+    CLI.get().expect("command line is not parsed yet")
+}
@@ -13,12 +13,27 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use lib::settings::DataSourceType;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
 use rocket::serde::{Serialize, Serializer};
+use rocket::Request;
 use serde::ser::SerializeStruct;
+use std::fmt;
+use utoipa::ToSchema;
 
 pub(crate) struct Resp<T> {
     pub status: i32,
-    pub data: Result<T, &'static str>,
+    pub data: Result<T, RespError>,
+}
+
+#[derive(Debug)]
+pub(crate) struct RespError {
+    pub message: String,
+    /// path of the offending field of the request, e.g. `ebpf.net.enable`
+    pub field: Option<String>,
 }
 
 impl<T> Resp<T> {
@@ -28,6 +43,13 @@ impl<T> Resp<T> {
             data: Ok(t),
         }
     }
+
+    pub(crate) fn error(status: i32, e: RespError) -> Resp<T> {
+        Resp::<T> {
+            status,
+            data: Err(e),
+        }
+    }
 }
 
 impl<T> Serialize for Resp<T>
@@ -44,10 +66,251 @@ where
             Ok(d) => {
                 state.serialize_field("data", d)?;
             }
-            Err(i) => {
-                state.serialize_field("message", i)?;
+            Err(e) => {
+                state.serialize_field("message", &e.message)?;
+                if let Some(field) = &e.field {
+                    state.serialize_field("field", field)?;
+                }
             }
         }
         state.end()
     }
 }
+
+/// body of an error response, `status` being one of the [`ApiError`] codes
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub(crate) struct RespErrorBody {
+    /// 1001 cgroup not found, 1002 cgroup not tracked, 1003 data source disabled,
+    /// 1004 collector failed, 1005 settings invalid, 1006 snapshot stale, 1007 invalid query,
+    /// 1008 unauthorized, 1009 forbidden, 1010 cgroup discovered, 1500 internal error
+    status: i32,
+    message: String,
+    /// path of the offending field of the request, for settings invalid
+    field: Option<String>,
+}
+
+#[derive(Debug)]
+pub(crate) enum ApiError {
+    /// the cgroup does not exist on the host
+    CGroupNotFound(String),
+    /// the cgroup is not tracked, and the client may not track it
+    CGroupNotTracked(String),
+    DataSourceDisabled(DataSourceType),
+    /// a collector behind the requested section failed its last run, or the
+    /// last monitor update failed as a whole
+    CollectorFailed(String),
+    SettingsInvalid(RespError),
+    /// seconds since the last successful monitor update
+    SnapshotStale(u64),
+    /// a query parameter fails to parse
+    InvalidQuery(String),
+    /// no valid credentials, the challenges of the configured authenticators
+    /// are sent as `WWW-Authenticate`
+    Unauthorized(Vec<&'static str>),
+    /// the credentials lack the role required by the route
+    Forbidden,
+    /// the cgroup matches a `cgroup.discovery` pattern, untracking it would only
+    /// last until the next discovery
+    CGroupDiscovered(String),
+    Internal(String),
+}
+
+pub(crate) type ApiResult<T> = Result<Json<Resp<T>>, ApiError>;
+
+impl ApiError {
+    pub(crate) fn code(&self) -> i32 {
+        match self {
+            ApiError::CGroupNotFound(_) => 1001,
+            ApiError::CGroupNotTracked(_) => 1002,
+            ApiError::DataSourceDisabled(_) => 1003,
+            ApiError::CollectorFailed(_) => 1004,
+            ApiError::SettingsInvalid(_) => 1005,
+            ApiError::SnapshotStale(_) => 1006,
+            ApiError::InvalidQuery(_) => 1007,
+            ApiError::Unauthorized(_) => 1008,
+            ApiError::Forbidden => 1009,
+            ApiError::CGroupDiscovered(_) => 1010,
+            ApiError::Internal(_) => 1500,
+        }
+    }
+
+    pub(crate) fn status(&self) -> Status {
+        match self {
+            ApiError::CGroupNotFound(_) | ApiError::CGroupNotTracked(_) => Status::NotFound,
+            ApiError::DataSourceDisabled(_) | ApiError::CGroupDiscovered(_) => Status::Conflict,
+            ApiError::CollectorFailed(_) | ApiError::Internal(_) => Status::InternalServerError,
+            ApiError::SettingsInvalid(_) => Status::UnprocessableEntity,
+            ApiError::SnapshotStale(_) => Status::ServiceUnavailable,
+            ApiError::InvalidQuery(_) => Status::BadRequest,
+            ApiError::Unauthorized(_) => Status::Unauthorized,
+            ApiError::Forbidden => Status::Forbidden,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::CGroupNotFound(path) => write!(f, "cgroup {} not found", path),
+            ApiError::CGroupNotTracked(path) => write!(f, "cgroup {} is not tracked", path),
+            ApiError::DataSourceDisabled(t) => write!(f, "data source {:?} is disabled", t),
+            ApiError::CollectorFailed(e) => write!(f, "collector failed: {}", e),
+            ApiError::SettingsInvalid(e) => write!(f, "{}", e.message),
+            ApiError::SnapshotStale(age) => write!(f, "snapshot is {}s old", age),
+            ApiError::InvalidQuery(e) => write!(f, "invalid query: {}", e),
+            ApiError::Unauthorized(_) => write!(f, "authentication required"),
+            ApiError::Forbidden => write!(f, "permission denied"),
+            ApiError::CGroupDiscovered(path) => write!(
+                f,
+                "cgroup {} matches cgroup.discovery and would be tracked again",
+                path
+            ),
+            ApiError::Internal(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let status = self.status();
+        let code = self.code();
+        let message = self.to_string();
+        let (field, challenges) = match self {
+            ApiError::SettingsInvalid(e) => (e.field, vec![]),
+            ApiError::Unauthorized(challenges) => (None, challenges),
+            _ => (None, vec![]),
+        };
+        let mut response = Custom(
+            status,
+            Json(Resp::<()>::error(code, RespError { message, field })),
+        )
+        .respond_to(req)?;
+        for challenge in challenges {
+            response.adjoin_raw_header("WWW-Authenticate", challenge);
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests_common {
+    use super::*;
+    use rocket::local::blocking::Client;
+    use rocket::serde::json::{serde_json, Value};
+
+    #[test]
+    fn test_api_error_code() {
+        let cases = vec![
+            (
+                ApiError::CGroupNotFound("/a".into()),
+                1001,
+                Status::NotFound,
+            ),
+            (
+                ApiError::CGroupNotTracked("/a".into()),
+                1002,
+                Status::NotFound,
+            ),
+            (
+                ApiError::DataSourceDisabled(DataSourceType::Ebpf),
+                1003,
+                Status::Conflict,
+            ),
+            (
+                ApiError::CollectorFailed("Psi: no such file".into()),
+                1004,
+                Status::InternalServerError,
+            ),
+            (
+                ApiError::SettingsInvalid(RespError {
+                    message: "unknown field".into(),
+                    field: None,
+                }),
+                1005,
+                Status::UnprocessableEntity,
+            ),
+            (
+                ApiError::SnapshotStale(30),
+                1006,
+                Status::ServiceUnavailable,
+            ),
+            (
+                ApiError::InvalidQuery("cpus".into()),
+                1007,
+                Status::BadRequest,
+            ),
+            (ApiError::Unauthorized(vec![]), 1008, Status::Unauthorized),
+            (ApiError::Forbidden, 1009, Status::Forbidden),
+            (
+                ApiError::CGroupDiscovered("/a".into()),
+                1010,
+                Status::Conflict,
+            ),
+            (
+                ApiError::Internal("error".into()),
+                1500,
+                Status::InternalServerError,
+            ),
+        ];
+        for (e, code, status) in cases {
+            assert_eq!(e.code(), code, "{:?}", e);
+            assert_eq!(e.status(), status, "{:?}", e);
+        }
+    }
+
+    #[rocket::get("/stale")]
+    fn stale() -> ApiResult<()> {
+        Err(ApiError::SnapshotStale(30))
+    }
+
+    #[rocket::get("/invalid")]
+    fn invalid() -> ApiResult<()> {
+        Err(ApiError::SettingsInvalid(RespError {
+            message: "unknown field `enabled`".into(),
+            field: Some("ebpf.net.enabled".into()),
+        }))
+    }
+
+    #[rocket::get("/ok")]
+    fn ok() -> ApiResult<u64> {
+        Ok(Json(Resp::new(1)))
+    }
+
+    fn get(client: &Client, uri: &str) -> (Status, Value) {
+        let resp = client.get(uri).dispatch();
+        let status = resp.status();
+        (
+            status,
+            serde_json::from_str(&resp.into_string().unwrap()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_api_error_responder() {
+        let rocket = rocket::build().mount("/", rocket::routes![stale, invalid, ok]);
+        let client = Client::untracked(rocket).unwrap();
+
+        let (status, body) = get(&client, "/stale");
+        assert_eq!(status, Status::ServiceUnavailable);
+        assert_eq!(
+            body,
+            serde_json::json!({"status": 1006, "message": "snapshot is 30s old"})
+        );
+
+        let (status, body) = get(&client, "/invalid");
+        assert_eq!(status, Status::UnprocessableEntity);
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "status": 1005,
+                "message": "unknown field `enabled`",
+                "field": "ebpf.net.enabled"
+            })
+        );
+
+        let (status, body) = get(&client, "/ok");
+        assert_eq!(status, Status::Ok);
+        assert_eq!(body, serde_json::json!({"status": 0, "data": 1}));
+    }
+}
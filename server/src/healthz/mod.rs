@@ -13,67 +13,238 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::auth::ReadAccess;
 use crate::common::Resp;
+use crate::setting::{ReloadStatus, RELOAD_STATUS};
 use crate::system;
+use lib::common::CGroupType;
 use lib::ffi::{ModuleMask, ModuleMaskIDType};
 use lib::settings::Settings;
-use lib::common::CGroupType;
+use lib::system::Collector;
 use rocket::http::Status;
+use rocket::response::status::Custom;
 use rocket::serde::json::Json;
 use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
 use utoipa::ToSchema;
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
-struct ModuleDetails {
+/// `unhealthy` is answered with 503 so that probes fail, `degraded` still with 200
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    /// every enabled collector succeeded on its last run
+    Healthy,
+    /// some enabled collector or profiled byteperf module is failing, the rest is served
+    Degraded,
+    /// the monitor update failed or is stale, queries are rejected
+    Unhealthy,
+}
+
+impl HealthStatus {
+    fn http_status(self) -> Status {
+        match self {
+            HealthStatus::Unhealthy => Status::ServiceUnavailable,
+            _ => Status::Ok,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash, ToSchema)]
+pub struct ModuleDetails {
+    /// set up, and for byteperf every profiled module is healthy
     status: bool,
     mask: u64,
     enable_list: Vec<String>,
 }
 
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash, ToSchema)]
+pub struct CollectorHealth {
+    name: Collector,
+    /// its data source and sub system are enabled in the settings
+    enable: bool,
+    /// seconds since epoch of the last run which did not fail, none before the first one
+    last_success: Option<u64>,
+    /// seconds since the last successful run
+    age: Option<u64>,
+    last_error: Option<String>,
+    consecutive_failures: u64,
+    /// duration of the last run in milliseconds
+    duration_ms: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash, ToSchema)]
+pub struct HealthDetails {
+    status: HealthStatus,
+    /// why the status is not healthy
+    reasons: Vec<String>,
+    /// seconds since the last successful monitor update
+    update_age: u64,
+    update_error: Option<String>,
+    collectors: Vec<CollectorHealth>,
+    ebpf: ModuleDetails,
+    perf: ModuleDetails,
+    /// mask of the byteperf modules asked to profile, `perf.mask` being the healthy ones
+    perf_profile_module: u64,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash, ToSchema)]
 pub struct Healths {
-    status: String,
+    status: HealthStatus,
     ebpf_mask: Vec<String>,
     perf_mask: Vec<String>,
+    /// byteperf modules asked to profile, unhealthy ones are missing from `perf_mask`
+    perf_profile_mask: Vec<String>,
     cgroup_type: CGroupType,
+    /// only given to clients with read access
     settings: Option<Settings>,
+    /// hot reload of the settings file
+    settings_reload: ReloadStatus,
+}
+
+fn module_names(mask: u64, config: &lib::ffi::ModuleMaskConfig) -> Vec<String> {
+    let mask_ids: Vec<ModuleMaskIDType> = Vec::from(ModuleMask::new(mask));
+    config.get_names_vec(mask_ids)
+}
+
+fn details() -> HealthDetails {
+    let settings = system::MONITOR.get_settings().read().clone();
+    let mut reasons = vec![];
+
+    let (update_age, update_error) = system::MONITOR.get_update_state();
+    let mut status = match system::MONITOR.check_update() {
+        Ok(()) => HealthStatus::Healthy,
+        Err(e) => {
+            reasons.push(e.to_string());
+            HealthStatus::Unhealthy
+        }
+    };
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let collectors: Vec<CollectorHealth> = {
+        let reader = system::MONITOR.get_monitor_reader().read();
+        let stats = reader.get_collector_stats();
+        Collector::all()
+            .map(|name| {
+                let stat = stats.get(name).cloned().unwrap_or_default();
+                CollectorHealth {
+                    name,
+                    enable: name.is_enable(&settings),
+                    last_success: stat.last_success,
+                    age: stat.last_success.map(|t| now.saturating_sub(t)),
+                    last_error: stat.last_error,
+                    consecutive_failures: stat.consecutive_failures,
+                    duration_ms: stat.duration_ms,
+                }
+            })
+            .collect()
+    };
+    for collector in collectors.iter() {
+        if !collector.enable || collector.consecutive_failures == 0 {
+            continue;
+        }
+        reasons.push(format!(
+            "collector {:?} failed {} times: {}",
+            collector.name,
+            collector.consecutive_failures,
+            collector.last_error.as_deref().unwrap_or_default()
+        ));
+        if status == HealthStatus::Healthy {
+            status = HealthStatus::Degraded;
+        }
+    }
+
+    let bpf_mask = lib::ffi::wrapper_get_bpf_mask() as u64;
+    let ebpf = ModuleDetails {
+        status: lib::ffi::is_bpf_initialized(),
+        mask: bpf_mask,
+        enable_list: module_names(bpf_mask, &lib::ffi::BPF_MODULE_MASK_CONFIG),
+    };
+
+    let (profile_module, module_health) = lib::ffi::wrapper_byteperf_check_module_health();
+    let (profile_module, module_health) = (profile_module as u64, module_health as u64);
+    let unhealthy_modules = profile_module & !module_health;
+    if Collector::Pmu.is_enable(&settings) && unhealthy_modules != 0 {
+        reasons.push(format!(
+            "byteperf modules {:?} are unhealthy",
+            module_names(unhealthy_modules, &lib::ffi::PERF_MODULE_MASK_CONFIG)
+        ));
+        if status == HealthStatus::Healthy {
+            status = HealthStatus::Degraded;
+        }
+    }
+    let perf = ModuleDetails {
+        status: lib::ffi::is_byteperf_initialized() && unhealthy_modules == 0,
+        mask: module_health,
+        enable_list: module_names(module_health, &lib::ffi::PERF_MODULE_MASK_CONFIG),
+    };
+
+    HealthDetails {
+        status,
+        reasons,
+        update_age: update_age.as_secs(),
+        update_error,
+        collectors,
+        ebpf,
+        perf,
+        perf_profile_module: profile_module,
+    }
 }
 
-/// malachite health
+/// malachite health, answered with 503 when unhealthy; open for probes, the settings
+/// are left out unless the client has read access
 #[utoipa::path(
     context_path = "/v1/health",
     responses(
-        (status = 200, description = "health api", body = [Healths])
+        (status = 200, description = "healthy or degraded", body = [Healths]),
+        (status = 503, description = "unhealthy", body = [Healths])
     )
 )]
 #[get("/")]
-async fn health() -> Result<Json<Resp<Healths>>, Status> {
-    let mask = lib::ffi::wrapper_get_bpf_mask();
-    let mask_ids: Vec<ModuleMaskIDType> = Vec::from(ModuleMask::new(mask));
-    let ebpf_mask_list: Vec<String> = lib::ffi::BPF_MODULE_MASK_CONFIG.get_names_vec(mask_ids);
-
-    let (_, cur_mask) = lib::ffi::wrapper_byteperf_check_module_health();
-    let mask = cur_mask as u64;
-    let perf_mask_ids: Vec<ModuleMaskIDType> = Vec::from(ModuleMask::new(mask));
-    let perf_mask_list: Vec<String> =
-        lib::ffi::PERF_MODULE_MASK_CONFIG.get_names_vec(perf_mask_ids);
-
+async fn health(access: Option<ReadAccess>) -> Custom<Json<Resp<Healths>>> {
+    let details = details();
     let mut settings = None;
-    if let Some(s) = system::MONITOR.get_settings().try_read() {
-        settings = Some(s.clone());
+    if access.is_some() {
+        if let Some(s) = system::MONITOR.get_settings().try_read() {
+            settings = Some(s.clone());
+        }
     }
-    Ok(Json(Resp::new(Healths {
-        status: "Ok".to_string(),
-        ebpf_mask: ebpf_mask_list,
-        perf_mask: perf_mask_list,
-        cgroup_type: lib::common::MODULE_LIST.cgroup_type.actual_status(),
-        settings,
-    })))
+    Custom(
+        details.status.http_status(),
+        Json(Resp::new(Healths {
+            status: details.status,
+            ebpf_mask: details.ebpf.enable_list,
+            perf_mask: details.perf.enable_list,
+            perf_profile_mask: module_names(
+                details.perf_profile_module,
+                &lib::ffi::PERF_MODULE_MASK_CONFIG,
+            ),
+            cgroup_type: lib::common::MODULE_LIST.cgroup_type.actual_status(),
+            settings,
+            settings_reload: RELOAD_STATUS.read().clone(),
+        })),
+    )
+}
+
+/// freshness and errors of every collector and the state of the ebpf and byteperf modules,
+/// answered with 503 when unhealthy
+#[utoipa::path(
+    context_path = "/v1/health",
+    responses(
+        (status = 200, description = "healthy or degraded", body = [HealthDetails]),
+        (status = 503, description = "unhealthy", body = [HealthDetails])
+    )
+)]
+#[get("/details")]
+async fn health_details() -> Custom<Json<Resp<HealthDetails>>> {
+    let details = details();
+    Custom(details.status.http_status(), Json(Resp::new(details)))
 }
 
 pub fn healthz_v1_router() -> rocket::fairing::AdHoc {
     rocket::fairing::AdHoc::on_ignite("JSON", |rocket| async {
-        rocket.mount("/v1/health", routes![health])
-        //rocket.mount("/v1/health/details", routes![health_details])
+        rocket.mount("/v1/health", routes![health, health_details])
     })
 }
@@ -0,0 +1,461 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::metrics::{self, Labels};
+use crate::system::{RespCompute, RespIo, RespMemory, RespNetwork};
+use lib::cgroup::CGroup;
+use lib::settings::HistorySettings;
+use lib::system::System;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use rocket::serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::SystemTime;
+use utoipa::{IntoParams, ToSchema};
+
+/// bytes of one (timestamp, value) point
+const POINT_SIZE: u64 = 16;
+/// rough bytes of a series besides its points
+const SERIES_OVERHEAD: u64 = 128;
+
+pub(crate) static HISTORY: Lazy<RwLock<History>> = Lazy::new(|| RwLock::new(History::default()));
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub(crate) enum Section {
+    Compute,
+    Memory,
+    Io,
+    Network,
+    CGroup,
+}
+
+impl Section {
+    fn of(name: &str, labels: &Labels) -> Option<Section> {
+        if name.starts_with("malachite_pressure") {
+            return match labels.iter().find(|(k, _)| k == "resource") {
+                Some((_, r)) if r == "cpu" => Some(Section::Compute),
+                Some((_, r)) if r == "memory" => Some(Section::Memory),
+                Some((_, r)) if r == "io" => Some(Section::Io),
+                _ => None,
+            };
+        }
+        [
+            ("malachite_cpu_", Section::Compute),
+            ("malachite_load_", Section::Compute),
+            ("malachite_process_", Section::Compute),
+            ("malachite_memory_", Section::Memory),
+            ("malachite_numa_", Section::Memory),
+            ("malachite_disk_", Section::Io),
+            ("malachite_network", Section::Network),
+            ("malachite_cgroup_cpuacct_", Section::CGroup),
+            ("malachite_cgroup_memory_", Section::CGroup),
+            ("malachite_cgroup_blkio_", Section::CGroup),
+        ]
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+        .map(|(_, section)| *section)
+    }
+}
+
+#[derive(FromForm, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct RangeQuery {
+    /// range start, seconds since epoch
+    pub(crate) start: Option<u64>,
+    /// range end, seconds since epoch
+    pub(crate) end: Option<u64>,
+    /// keep at most one point per step seconds
+    pub(crate) step: Option<u64>,
+}
+
+impl RangeQuery {
+    pub(crate) fn is_range(&self) -> bool {
+        self.start.is_some() || self.end.is_some() || self.step.is_some()
+    }
+}
+
+#[derive(Serialize, ToSchema, Debug)]
+pub(crate) struct RespSeries {
+    /// metric name, as exposed on /metrics
+    pub(crate) name: String,
+    pub(crate) labels: BTreeMap<String, String>,
+    /// (seconds since epoch, value)
+    pub(crate) points: Vec<(u64, f64)>,
+}
+
+/// latest snapshot, or the history of its series when a range is queried
+#[derive(Serialize, ToSchema, Debug)]
+#[aliases(
+    RespRangeCompute = RespRange<RespCompute>,
+    RespRangeMemory = RespRange<RespMemory>,
+    RespRangeIo = RespRange<RespIo>,
+    RespRangeNetwork = RespRange<RespNetwork>,
+    RespRangeCGroup = RespRange<CGroup>
+)]
+#[serde(untagged)]
+pub(crate) enum RespRange<T> {
+    Latest(T),
+    Range(Vec<RespSeries>),
+}
+
+#[derive(Hash, Eq, PartialEq, Debug)]
+struct SeriesKey {
+    name: String,
+    labels: Labels,
+}
+
+impl SeriesKey {
+    /// bytes accounted to the series for its name and labels
+    fn size(&self) -> u64 {
+        SERIES_OVERHEAD
+            + self.name.len() as u64
+            + self
+                .labels
+                .iter()
+                .map(|(k, v)| (k.len() + v.len()) as u64)
+                .sum::<u64>()
+    }
+}
+
+struct Series {
+    section: Section,
+    points: VecDeque<(u64, f64)>,
+}
+
+/// bounded in-memory history of the system and cgroup series
+#[derive(Default)]
+pub(crate) struct History {
+    series: HashMap<SeriesKey, Series>,
+    overhead: u64,
+}
+
+impl History {
+    /// append one sample of every series of the flattened metric families
+    pub(crate) fn record(
+        &mut self,
+        timestamp: u64,
+        families: &BTreeMap<String, Vec<(Labels, f64)>>,
+        settings: &HistorySettings,
+    ) {
+        for (name, samples) in families {
+            for (labels, value) in samples {
+                let section = match Section::of(name, labels) {
+                    Some(section) => section,
+                    None => continue,
+                };
+                let key = SeriesKey {
+                    name: name.clone(),
+                    labels: labels.clone(),
+                };
+                let overhead = &mut self.overhead;
+                self.series
+                    .entry(key)
+                    .or_insert_with_key(|key| {
+                        *overhead += key.size();
+                        Series {
+                            section,
+                            points: VecDeque::new(),
+                        }
+                    })
+                    .points
+                    .push_back((timestamp, *value));
+            }
+        }
+
+        self.evict(timestamp, settings);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.series.clear();
+        self.overhead = 0;
+    }
+
+    /// drop points past retention, then shrink every series evenly to fit the budget
+    fn evict(&mut self, now: u64, settings: &HistorySettings) {
+        let oldest = now.saturating_sub(settings.retention());
+        for series in self.series.values_mut() {
+            while matches!(series.points.front(), Some((ts, _)) if *ts < oldest) {
+                series.points.pop_front();
+            }
+        }
+
+        let overhead = &mut self.overhead;
+        self.series.retain(|key, series| {
+            if series.points.is_empty() {
+                *overhead = overhead.saturating_sub(key.size());
+                return false;
+            }
+            true
+        });
+
+        if self.series.is_empty() {
+            return;
+        }
+        let budget = settings.memory_budget().saturating_sub(self.overhead);
+        let max_points = (budget / (self.series.len() as u64 * POINT_SIZE)).max(1) as usize;
+        for series in self.series.values_mut() {
+            while series.points.len() > max_points {
+                series.points.pop_front();
+            }
+        }
+    }
+
+    /// series of a section whose labels contain every given label
+    pub(crate) fn query(
+        &self,
+        section: Section,
+        labels: &[(&str, &str)],
+        range: &RangeQuery,
+    ) -> Vec<RespSeries> {
+        let start = range.start.unwrap_or(0);
+        let end = range.end.unwrap_or(u64::MAX);
+        let step = range.step.unwrap_or(0);
+
+        let mut result = self
+            .series
+            .iter()
+            .filter(|(key, series)| {
+                series.section == section
+                    && labels
+                        .iter()
+                        .all(|(k, v)| key.labels.iter().any(|(lk, lv)| lk == k && lv == v))
+            })
+            .map(|(key, series)| {
+                let mut points: Vec<(u64, f64)> = vec![];
+                for (ts, value) in series
+                    .points
+                    .iter()
+                    .filter(|(ts, _)| *ts >= start && *ts <= end)
+                {
+                    match points.last_mut() {
+                        // same step bucket, keep the latest point
+                        Some(last)
+                            if step > 0 && (last.0 - start) / step == (ts - start) / step =>
+                        {
+                            *last = (*ts, *value);
+                        }
+                        _ => points.push((*ts, *value)),
+                    }
+                }
+                RespSeries {
+                    name: key.name.clone(),
+                    labels: key.labels.iter().cloned().collect(),
+                    points,
+                }
+            })
+            .filter(|series| !series.points.is_empty())
+            .collect::<Vec<RespSeries>>();
+        result.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.labels.cmp(&b.labels)));
+        result
+    }
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// the system is flattened before taking the history lock, range queries
+/// only wait for the insertion
+pub(crate) fn record(system: &System, settings: &HistorySettings) {
+    if !settings.is_enable() {
+        if !HISTORY.read().series.is_empty() {
+            HISTORY.write().clear();
+        }
+        return;
+    }
+    let encoder = metrics::collect_system(system);
+    HISTORY.write().record(now(), encoder.families(), settings);
+}
+
+pub(crate) fn query(
+    section: Section,
+    labels: &[(&str, &str)],
+    range: &RangeQuery,
+) -> Vec<RespSeries> {
+    HISTORY.read().query(section, labels, range)
+}
+
+#[cfg(test)]
+mod tests_history {
+    use super::*;
+    use rocket::serde::json::serde_json;
+
+    fn settings(retention: u64, memory_budget: u64) -> HistorySettings {
+        serde_json::from_value(serde_json::json!({
+            "enable": true,
+            "retention": retention,
+            "memory_budget": memory_budget,
+        }))
+        .unwrap()
+    }
+
+    fn families(value: f64) -> BTreeMap<String, Vec<(Labels, f64)>> {
+        let labels = |pairs: &[(&str, &str)]| -> Labels {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let mut families = BTreeMap::new();
+        families.insert(
+            "malachite_cpu_usage_ratio".to_string(),
+            vec![
+                (labels(&[("cpu", "0")]), value),
+                (labels(&[("cpu", "1")]), value * 2.0),
+            ],
+        );
+        families.insert(
+            "malachite_memory_free_bytes".to_string(),
+            vec![(Labels::new(), value)],
+        );
+        families.insert(
+            "malachite_pressure_some_avg10".to_string(),
+            vec![(labels(&[("resource", "io")]), value)],
+        );
+        // not part of any section
+        families.insert("malachite_up".to_string(), vec![(Labels::new(), value)]);
+        families
+    }
+
+    fn timestamps(series: &RespSeries) -> Vec<u64> {
+        series.points.iter().map(|(ts, _)| *ts).collect()
+    }
+
+    #[test]
+    fn test_record_query() {
+        let settings = settings(600, 1 << 20);
+        let mut history = History::default();
+        for ts in 100..103 {
+            history.record(ts, &families(ts as f64), &settings);
+        }
+        assert_eq!(history.series.len(), 4);
+
+        let compute = history.query(Section::Compute, &[], &RangeQuery::default());
+        assert_eq!(compute.len(), 2);
+        assert_eq!(compute[0].name, "malachite_cpu_usage_ratio");
+        assert_eq!(compute[0].labels.get("cpu").unwrap(), "0");
+        assert_eq!(
+            compute[1].points,
+            vec![(100, 200.0), (101, 202.0), (102, 204.0)]
+        );
+
+        let cpu1 = history.query(Section::Compute, &[("cpu", "1")], &RangeQuery::default());
+        assert_eq!(cpu1.len(), 1);
+        assert_eq!(cpu1[0].labels.get("cpu").unwrap(), "1");
+        assert!(history
+            .query(Section::Compute, &[("cpu", "2")], &RangeQuery::default())
+            .is_empty());
+
+        let io = history.query(Section::Io, &[], &RangeQuery::default());
+        assert_eq!(io.len(), 1);
+        assert_eq!(io[0].name, "malachite_pressure_some_avg10");
+        assert!(history
+            .query(Section::Network, &[], &RangeQuery::default())
+            .is_empty());
+
+        let range = RangeQuery {
+            start: Some(101),
+            end: Some(101),
+            step: None,
+        };
+        let memory = history.query(Section::Memory, &[], &range);
+        assert_eq!(memory.len(), 1);
+        assert_eq!(memory[0].points, vec![(101, 101.0)]);
+    }
+
+    #[test]
+    fn test_query_step() {
+        let settings = settings(600, 1 << 20);
+        let mut history = History::default();
+        for ts in 100..110 {
+            history.record(ts, &families(ts as f64), &settings);
+        }
+
+        // the latest point of every step bucket, buckets start at the range start
+        let range = RangeQuery {
+            start: Some(100),
+            end: None,
+            step: Some(4),
+        };
+        let memory = history.query(Section::Memory, &[], &range);
+        assert_eq!(timestamps(&memory[0]), vec![103, 107, 109]);
+        assert_eq!(memory[0].points[0], (103, 103.0));
+
+        let range = RangeQuery {
+            start: Some(102),
+            end: Some(108),
+            step: Some(3),
+        };
+        let memory = history.query(Section::Memory, &[], &range);
+        assert_eq!(timestamps(&memory[0]), vec![104, 107, 108]);
+    }
+
+    #[test]
+    fn test_evict_retention() {
+        let settings = settings(5, 1 << 20);
+        let mut history = History::default();
+        for ts in 100..110 {
+            history.record(ts, &families(ts as f64), &settings);
+        }
+        let memory = history.query(Section::Memory, &[], &RangeQuery::default());
+        assert_eq!(timestamps(&memory[0]), vec![104, 105, 106, 107, 108, 109]);
+
+        // series not sampled anymore are dropped with their last point
+        let mut only_memory = families(200.0);
+        only_memory.retain(|name, _| name == "malachite_memory_free_bytes");
+        history.record(200, &only_memory, &settings);
+        assert_eq!(history.series.len(), 1);
+        assert_eq!(
+            history.overhead,
+            history.series.keys().map(|key| key.size()).sum::<u64>()
+        );
+        let memory = history.query(Section::Memory, &[], &RangeQuery::default());
+        assert_eq!(memory[0].points, vec![(200, 200.0)]);
+    }
+
+    #[test]
+    fn test_evict_memory_budget() {
+        let mut history = History::default();
+        history.record(0, &families(0.0), &settings(600, 1 << 20));
+        let overhead = history.overhead;
+        assert_eq!(
+            overhead,
+            history.series.keys().map(|key| key.size()).sum::<u64>()
+        );
+
+        // room for 3 points in each of the 4 series
+        let budget = settings(600, overhead + 4 * 3 * POINT_SIZE);
+        for ts in 1..10 {
+            history.record(ts, &families(ts as f64), &budget);
+        }
+        for series in history.series.values() {
+            assert_eq!(series.points.len(), 3);
+        }
+        let memory = history.query(Section::Memory, &[], &RangeQuery::default());
+        assert_eq!(timestamps(&memory[0]), vec![7, 8, 9]);
+
+        // a budget below the overhead still keeps the latest point
+        history.record(10, &families(10.0), &settings(600, 0));
+        let memory = history.query(Section::Memory, &[], &RangeQuery::default());
+        assert_eq!(memory[0].points, vec![(10, 10.0)]);
+
+        history.clear();
+        assert!(history.series.is_empty());
+        assert_eq!(history.overhead, 0);
+    }
+}
@@ -13,23 +13,40 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+mod auth;
 mod cgroup;
+mod cli;
 mod common;
 mod healthz;
+mod history;
+mod metrics;
+mod projection;
 mod setting;
+mod stream;
 mod system;
+mod tls;
+mod uds;
+mod watcher;
 
 #[macro_use]
 extern crate rocket;
 
 mod monitor {
+    use crate::history;
     use crate::system::{ChangeEvent, CHANGE_CHANNEL, MONITOR};
+    use crossbeam::channel::{bounded, Receiver, RecvTimeoutError, Sender};
     use lib::settings;
     use log::info;
+    use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
     use std::ops::DerefMut;
     use std::process;
-    use std::thread::{self, sleep};
-    use std::time::Duration;
+    use std::thread::{self, JoinHandle};
+    use std::time::{Duration, Instant};
+
+    /// ends the update loop once the running update is done
+    static STOP: Lazy<(Sender<()>, Receiver<()>)> = Lazy::new(|| bounded(1));
+    static DAEMON: Lazy<Mutex<Option<JoinHandle<()>>>> = Lazy::new(|| Mutex::new(None));
 
     fn update() {
         let start_settings = MONITOR.get_settings().read().clone();
@@ -42,27 +59,65 @@ mod monitor {
         .join()
         .unwrap();
 
+        // change events are drained here and handed to every attempt, so that an
+        // update which panics does not lose them, they are applied again on the next
+        let mut pending: Vec<ChangeEvent> = vec![];
         loop {
-            thread::spawn(|| {
-                update_once();
-            });
-            sleep(Duration::from_millis(10000));
-            MONITOR.change_pointer();
+            let start = Instant::now();
+            pending.extend(CHANGE_CHANNEL.get_channel_receiver().try_iter());
+            let events = pending.clone();
+            // swap only once the writer is fully updated, a tick may be shorter than an update
+            let updated = match thread::spawn(move || update_once(events)).join() {
+                Ok(Ok(())) => {
+                    MONITOR.update_succeeded();
+                    true
+                }
+                // the snapshot is complete, the failures are served from the collector stats
+                Ok(Err(e)) => {
+                    warn!("[monitor] update instance with failed collectors: {}", e);
+                    MONITOR.update_succeeded();
+                    true
+                }
+                Err(e) => {
+                    let cause = e
+                        .downcast_ref::<String>()
+                        .cloned()
+                        .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+                        .unwrap_or_else(|| String::from("<cause unknown>"));
+                    warn!("[monitor] update instance failed: {}", cause);
+                    MONITOR.update_failed(cause);
+                    false
+                }
+            };
+            // a failed update leaves the previous reader in place
+            if updated {
+                pending.clear();
+                MONITOR.change_pointer();
+                // from the new reader, so neither the writer nor the API wait for it
+                let history_settings = MONITOR.get_settings().read().get_history().clone();
+                history::record(&MONITOR.get_monitor_reader().read(), &history_settings);
+            }
+            let tick = Duration::from_secs(MONITOR.get_settings().read().get_refresh_tick());
+            if let Err(RecvTimeoutError::Timeout) =
+                STOP.1.recv_timeout(tick.saturating_sub(start.elapsed()))
+            {
+                continue;
+            }
+            info!("[monitor] stopped");
+            return;
         }
     }
 
-    fn update_once() {
-        let mut writer_instance = MONITOR.get_monitor_writer().write();
+    /// fails with the collectors which failed, the update itself completed
+    fn update_once(events: Vec<ChangeEvent>) -> Result<(), String> {
+        let writer_lock = MONITOR.get_monitor_writer();
+        let mut writer_instance = writer_lock.write();
         info!("[moniter] updating instance {}", writer_instance.nr);
         writer_instance.update(MONITOR.get_monitor_reader().read().clone());
 
         let mut new_settings = None;
-        loop {
-            let receiver = CHANGE_CHANNEL.get_channel_receiver();
-            if receiver.is_empty() {
-                break;
-            }
-            let msg = receiver.try_recv().unwrap();
+        let mut persist = None;
+        for msg in events {
             info!(
                 "[monitor] instance {}, receive {:?}",
                 writer_instance.nr, msg
@@ -71,9 +126,22 @@ mod monitor {
                 ChangeEvent::CGroupEvent(cgroup) => {
                     writer_instance.insert_cgroups_item(cgroup).unwrap();
                 }
+                ChangeEvent::CGroupRemoveEvent(path) => {
+                    writer_instance.remove_cgroups_item(&path).unwrap();
+                    MONITOR
+                        .get_monitor_peer(writer_lock)
+                        .write()
+                        .remove_cgroups_item(&path)
+                        .unwrap();
+                }
+                // overrides persisted by an earlier event of the batch are still saved
                 ChangeEvent::SettingsEvent(s) => {
                     new_settings = Some(s);
                 }
+                ChangeEvent::SettingsPersistEvent(s, overrides) => {
+                    new_settings = Some(s);
+                    persist = Some(overrides);
+                }
             }
         }
 
@@ -85,12 +153,21 @@ mod monitor {
                 .deref_mut()
                 .update(*s.clone());
             writer_instance.switch(&s);
-            //settings.Save();
+            if let Some(overrides) = persist {
+                let path = settings::state_path();
+                match settings::Settings::save_state(&overrides, &path) {
+                    Ok(_) => info!("[monitor] settings saved to {}", path.display()),
+                    Err(e) => warn!("[monitor] save settings to {} error: {}", path.display(), e),
+                }
+            }
         }
 
         let settings = MONITOR.get_settings().read().clone();
-        writer_instance.refresh(&settings);
+        let result = writer_instance
+            .refresh(&settings)
+            .map_err(|e| e.to_string());
         info!("[monitor] update instance {} done", writer_instance.nr);
+        result
     }
 
     fn monitor() {
@@ -118,53 +195,148 @@ mod monitor {
 
     pub fn daemon() {
         enable_modules();
-        let _handler = thread::Builder::new()
+        let handler = thread::Builder::new()
             .name("monitor-daemon".into())
-            .spawn(monitor);
+            .spawn(monitor)
+            .unwrap();
+        *DAEMON.lock() = Some(handler);
     }
 
-    pub fn clean() {
-        let mut ebpf_disable = false;
-        let mut perf_disable = false;
-        let settings = MONITOR.get_settings().read().clone();
-        if !settings.is_enable() {
-            ebpf_disable = true;
-            perf_disable = true;
-        }
-
-        if let Some(ds_settings) = settings.get_data_source(settings::DataSourceType::Ebpf) {
-            if !ds_settings.is_enable() {
-                ebpf_disable = true;
-            }
-        }
-
-        if let Some(ds_settings) = settings.get_data_source(settings::DataSourceType::BytePerf) {
-            if !ds_settings.is_enable() {
-                perf_disable = true;
-            }
-        }
-
-        if ebpf_disable {
-            lib::ffi::wrapper_free_bpf();
-            info!("[clean] release bpf resource");
+    /// stop updating, waits for the running update so that nothing uses
+    /// bpf or byteperf afterwards
+    pub fn stop() {
+        let _ = STOP.0.try_send(());
+        if let Some(handler) = DAEMON.lock().take() {
+            let _ = handler.join();
         }
+    }
 
-        if perf_disable {
-            lib::ffi::wrapper_byteperf_destroy_malachite();
-            info!("[clean] release byteperf resource");
-        }
+    /// release whatever bpf programs and PMU counters are set up
+    pub fn clean() {
+        lib::ffi::release();
+        info!("[clean] resources released");
     }
 }
 
 mod web_server {
-    use crate::{cgroup, healthz, setting, system};
+    use crate::{
+        auth, cgroup, cli, common, healthz, history, metrics, setting, stream, system, tls, uds,
+    };
     use lib;
     use lib::cgroup as lib_cgroup;
+    use rocket::figment::Figment;
+    use rocket::tokio::signal::ctrl_c;
+    use rocket::tokio::signal::unix::{signal, SignalKind};
     use rocket::{Build, Rocket};
     use utoipa::OpenApi;
     use utoipa_swagger_ui::SwaggerUi;
-    #[launch]
-    pub fn start() -> Rocket<Build> {
+
+    pub fn main() {
+        let cli = cli::get();
+        let socket = cli.unix_socket.clone().map(|path| uds::UnixSocket {
+            path,
+            mode: cli.unix_socket_mode,
+            uid: cli.unix_socket_owner.map(|owner| owner.uid),
+            gid: cli.unix_socket_owner.and_then(|owner| owner.gid),
+        });
+
+        rocket::execute(async move {
+            let unix = socket.map(|socket| {
+                rocket::tokio::spawn(async move {
+                    let path = socket.path.clone();
+                    if let Err(e) = uds::serve(build(rocket::Config::figment()), socket).await {
+                        error!("[web_server] serve on {} error: {}", path.display(), e);
+                    }
+                })
+            });
+            if cli.no_tcp {
+                // rocket stops on the signals by itself, serving on a unix socket only does not
+                if let Some(unix) = unix {
+                    rocket::tokio::select! {
+                        _ = unix => {}
+                        _ = stop_signal() => info!("[web_server] stop on signal"),
+                    }
+                }
+                return;
+            }
+
+            let mut figment = rocket::Config::figment();
+            if let Some(address) = cli.address {
+                figment = figment.merge(("address", address));
+            }
+            if let Some(port) = cli.port {
+                figment = figment.merge(("port", port));
+            }
+            match tls::TlsFiles::from_cli(cli) {
+                Some(tls_files) => launch_tls(figment, tls_files).await,
+                None => {
+                    if let Err(e) = build(figment).launch().await {
+                        error!("[web_server] launch error: {}", e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// SIGTERM or SIGINT
+    async fn stop_signal() {
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                rocket::tokio::select! {
+                    _ = term.recv() => {}
+                    _ = ctrl_c() => {}
+                }
+            }
+            Err(e) => {
+                warn!("[web_server] listen to SIGTERM error: {}", e);
+                let _ = ctrl_c().await;
+            }
+        }
+    }
+
+    /// rocket 0.5 builds the TLS acceptor itself and takes no certificate
+    /// resolver, so the server is relaunched whenever the files change: the
+    /// listener closes, running requests get the shutdown grace period and new
+    /// connections are refused until the relaunched server binds again
+    async fn launch_tls(figment: Figment, tls_files: tls::TlsFiles) {
+        let mut reloaded = false;
+        loop {
+            let snapshot = tls_files.snapshot();
+            let result = match build(tls_files.merge(figment.clone())).ignite().await {
+                Ok(rocket) => {
+                    let shutdown = rocket.shutdown();
+                    let watcher = tls_files.clone();
+                    let changed = rocket::tokio::spawn(async move {
+                        watcher.changed(&snapshot).await;
+                        shutdown.notify();
+                    });
+                    let result = rocket.launch().await.map(|_| ());
+                    changed.abort();
+                    if changed.await.is_ok() {
+                        info!("[web_server] relaunch with the reloaded certificate");
+                        reloaded = true;
+                        continue;
+                    }
+                    result
+                }
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => break,
+                Err(e) if reloaded => {
+                    // keep waiting for a fixed certificate instead of exiting
+                    error!("[web_server] relaunch error: {}", e);
+                    tls_files.changed(&tls_files.snapshot()).await;
+                }
+                Err(e) => {
+                    error!("[web_server] launch error: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn build(figment: Figment) -> Rocket<Build> {
         #[derive(OpenApi)]
         #[openapi(
             paths(
@@ -175,19 +347,34 @@ mod web_server {
                 system::memory,
                 system::system_event,
                 cgroup::get_cgroup_info,
+                cgroup::get_cgroup_batch,
+                cgroup::list_cgroups,
+                cgroup::delete_cgroup,
+                cgroup::get_cgroup_metrics,
+                cgroup::get_cgroup_tree,
                 healthz::health,
+                healthz::health_details,
+                setting::get_settings,
+                setting::patch_settings,
+                setting::reset_settings,
+                metrics::metrics,
+                stream::stream,
             ),
             components(
                 schemas(lib_cgroup::CGroup, lib::common::CGroupType, lib_cgroup::SubSystem, lib_cgroup::SubSystemType,
-                    lib_cgroup::MemoryCGroup,lib_cgroup::MemoryCGroupV1, lib_cgroup::MemoryCGroupV2, lib_cgroup::MemEventLocalV2,
+                    lib_cgroup::MemoryCGroup,lib_cgroup::MemoryCGroupV1, lib_cgroup::MemoryCGroupV2, lib_cgroup::MemEventsV2, lib_cgroup::MemSwapEventsV2,
                     lib_cgroup::MemNumaStatsV2, lib_cgroup::MemStatsV2, lib_cgroup::MemoryCGroupNumaStat,
-                    lib_cgroup::CpuCGroup,lib_cgroup::CpuCGroupV1,lib_cgroup::CpuCGroupV2, lib_cgroup::CpuCGroupBasicInfo, lib_cgroup::CpuStatsV2,
+                    lib_cgroup::CpuCGroup,lib_cgroup::CpuCGroupV1,lib_cgroup::CpuCGroupV2, lib_cgroup::CpuCGroupBasicInfo, lib_cgroup::CpuStatsV2, lib_cgroup::CpuThrottling,
                     lib_cgroup::CpuSetCGroup, lib_cgroup::CpuSetCGroupV1, lib_cgroup::CpuSetCGroupV2,
                     lib_cgroup::BlkIOCGroup,lib_cgroup::BlkIOCGroupV1,lib_cgroup::BlkIOCGroupV2,lib_cgroup::BlkIOMaxV2, lib_cgroup::BlkIOStatV2,
                     lib::ffi::WrapperIoLatpcts, lib::ffi::IoPercentLatency, lib::ffi::WrapperFSData, lib::ffi::WrapperBpfProgStat, lib::ffi::WrapperSystemEvent,
                     lib::ffi::WrapperSystemEventFS,lib::ffi::WrapperSystemEventGen,lib::ffi::WrapperSystemEventIO,lib::ffi::WrapperSystemEventMem,lib::ffi::WrapperSystemEventNet,lib::ffi::WrapperSystemEventSched,
                     lib_cgroup::NetCGroup, lib::ffi::WrapperNetData,
                     lib_cgroup::PerfEventCGroup,
+                    lib_cgroup::PidsCGroup, lib_cgroup::HugeTLBCGroup, lib_cgroup::HugeTLBStat,
+                    lib_cgroup::RdmaCGroup, lib_cgroup::RdmaDeviceStat, lib_cgroup::MiscCGroup, lib_cgroup::MiscResourceStat,
+                    lib_cgroup::CGroupMetrics, lib_cgroup::CpuMetrics, lib_cgroup::MemoryMetrics, lib_cgroup::IoMetrics, lib_cgroup::IoThroughput,
+                    lib_cgroup::CGroupRollup, lib_cgroup::RollupUsage,
                     lib::psi::PressureStallInfo, lib::psi::PSIItem,
                     lib::cpu::NodeVec, lib::cpu::ProcessorCPIData, lib::process::SystemProcessStats,
                     lib::system::LoadAvg, lib::system::LoadAvgOperator, lib::system::BPFProgStats,
@@ -196,9 +383,15 @@ mod web_server {
                     lib::system::SystemEventData,
                     lib::net::NetworkCardTraffic, lib::net::NetInfo,
                     lib::settings::Settings, lib::settings::DataSourceProcFS, lib::settings::DataSourceSysFS,lib::settings::DataSourceCgroupFS,
-                    lib::settings::DataSourceBytePerf,lib::settings::DataSourceEBPF, lib::settings::DataSourceSubSys,
+                    lib::settings::DataSourceBytePerf,lib::settings::DataSourceEBPF, lib::settings::DataSourceSubSys, lib::settings::HistorySettings,
                     system::RespCompute, system::RespComputeCpu, system::RespIo, system::RespMemory, system::RespNetwork, system::RespSystemEvent,
-                    healthz::Healths, 
+                    cgroup::RespCGroupItem, cgroup::ReqCGroupBatch, cgroup::RespCGroupBatchItem,
+                    history::RespSeries, history::RespRangeCompute, history::RespRangeMemory, history::RespRangeIo, history::RespRangeNetwork, history::RespRangeCGroup,
+                    stream::RespSnapshot,
+                    common::RespErrorBody,
+                    healthz::Healths, healthz::HealthDetails, healthz::HealthStatus, healthz::ModuleDetails, healthz::CollectorHealth,
+                    lib::system::Collector,
+                    setting::ReloadStatus,
                 )
             ),
             tags(
@@ -207,7 +400,7 @@ mod web_server {
         )]
         struct ApiDoc;
 
-        rocket::build()
+        rocket::custom(figment)
             .mount(
                 "/",
                 SwaggerUi::new("/swagger-ui/<_..>")
@@ -215,9 +408,13 @@ mod web_server {
             )
             //.mount("/", routes![hello])
             .attach(cgroup::cgroup_v1_router())
+            .attach(cgroup::cgroup_v2_router())
             .attach(system::system_v1_router())
             .attach(healthz::healthz_v1_router())
             .attach(setting::settings_v1_router())
+            .attach(metrics::metrics_router())
+            .attach(stream::stream_v1_router())
+            .register("/", auth::catchers())
     }
 
     /*
@@ -244,20 +441,84 @@ mod web_server {
 }
 
 mod init {
+    use crate::cli::{self, Cli};
     use std::default::Default;
     use std::env;
     use std::ops::Deref;
     use std::panic;
+    use std::process;
 
     pub fn init() {
-        logs();
+        let cli = cli::parse();
+        logs(cli);
         panic_hook();
+        host_root(cli);
+        paths(cli);
+        auth(cli);
+    }
+
+    fn auth(cli: &Cli) {
+        if let Err(e) = crate::auth::init(cli) {
+            error!("[init] auth init error: {}", e);
+            process::exit(1);
+        }
+    }
+
+    fn host_root(cli: &Cli) {
+        if let Some(root) = &cli.host_root {
+            info!("[init] host root: {}", root.display());
+            if lib::common::set_host_root(root).is_err() {
+                warn!("[init] host root already set, ignore {}", root.display());
+            }
+        }
+    }
+
+    fn paths(cli: &Cli) {
+        if let Some(path) = &cli.cgroup_mount_point {
+            info!("[init] cgroup mount point: {}", path.display());
+            if lib::common::set_cgroup_mount_point(path).is_err() {
+                warn!(
+                    "[init] cgroup mount point already set, ignore {}",
+                    path.display()
+                );
+            }
+        }
+        if let Some(path) = &cli.config_file {
+            info!("[init] settings file: {}", path.display());
+            if lib::settings::set_default_path(path).is_err() {
+                warn!(
+                    "[init] settings file already set, ignore {}",
+                    path.display()
+                );
+            }
+        }
+        if let Some(path) = &cli.state_file {
+            info!("[init] settings state file: {}", path.display());
+            if lib::settings::set_state_path(path).is_err() {
+                warn!(
+                    "[init] settings state file already set, ignore {}",
+                    path.display()
+                );
+            }
+        }
+        if let Some(path) = &cli.pmu_config_dir {
+            info!("[init] pmu config dir: {}", path.display());
+            if lib::ffi::set_pmu_config_dir(path).is_err() {
+                warn!(
+                    "[init] pmu config dir already set, ignore {}",
+                    path.display()
+                );
+            }
+        }
     }
 
-    fn logs() {
-        let mut p = env::current_exe().unwrap();
-        p.pop();
-        p.push("static/config/log4rs.toml");
+    fn logs(cli: &Cli) {
+        let p = cli.log_config_file.clone().unwrap_or_else(|| {
+            let mut p = env::current_exe().unwrap();
+            p.pop();
+            p.push("static/config/log4rs.toml");
+            p
+        });
         log4rs::init_file(p, Default::default()).unwrap();
     }
 
@@ -285,6 +546,9 @@ mod init {
 fn main() {
     init::init();
     monitor::daemon();
+    watcher::daemon();
+    setting::daemon();
     web_server::main();
+    monitor::stop();
     monitor::clean();
 }
@@ -0,0 +1,225 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::auth::ReadAccess;
+use crate::metrics::{Labels, MetricsEncoder};
+use crate::system::MONITOR;
+use lib::system::System;
+use rocket::http::{ContentType, Status};
+use rocket::serde::json::serde_json;
+use rocket::serde::Serialize;
+use std::ops::Deref;
+
+fn labels(pairs: &[(&str, &str)]) -> Labels {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+fn add_serialized<T: Serialize>(encoder: &mut MetricsEncoder, name: &str, labels: &Labels, t: &T) {
+    match serde_json::to_value(t) {
+        Ok(value) => encoder.add_value(name, labels, &value),
+        Err(e) => warn!("[metrics] serialize {} error: {}", name, e),
+    }
+}
+
+fn encode_processors(encoder: &mut MetricsEncoder, system: &System) {
+    let processors = system.get_processors();
+    let global_processor = processors.get_global_processor();
+    for (cpu, processor) in processors
+        .iter()
+        .map(|(k, v)| (k.as_str(), v))
+        .chain(std::iter::once(("all", global_processor)))
+    {
+        let cpu_labels = labels(&[("cpu", cpu)]);
+        encoder.add_sample(
+            "malachite_cpu_usage_ratio",
+            &cpu_labels,
+            processor.get_cpu_usage() as f64,
+        );
+        encoder.add_sample(
+            "malachite_cpu_sys_usage_ratio",
+            &cpu_labels,
+            processor.get_cpu_sys_usage() as f64,
+        );
+        encoder.add_sample(
+            "malachite_cpu_iowait_ratio",
+            &cpu_labels,
+            processor.get_iowait_ratio() as f64,
+        );
+        encoder.add_sample(
+            "malachite_cpu_sched_wait",
+            &cpu_labels,
+            processor.get_sched_wait() as f64,
+        );
+        if let Some(cpi_data) = processor.get_cpi_data() {
+            add_serialized(encoder, "malachite_cpu_cpi", &cpu_labels, cpi_data);
+        }
+    }
+}
+
+fn encode_numa_nodes(encoder: &mut MetricsEncoder, system: &System) {
+    for node in system.get_system_device_nodes().get_nodes() {
+        let numa_labels = labels(&[("numa", &node.get_id().to_string())]);
+        match serde_json::to_value(node) {
+            Ok(mut value) => {
+                if let Some(map) = value.as_object_mut() {
+                    map.remove("id");
+                }
+                encoder.add_value("malachite_numa", &numa_labels, &value);
+            }
+            Err(e) => warn!("[metrics] serialize numa node error: {}", e),
+        }
+    }
+}
+
+fn encode_pressure(encoder: &mut MetricsEncoder, system: &System) {
+    if let Some(pressure) = system.get_system_pressure() {
+        for (resource, psi) in [
+            ("cpu", pressure.cpu()),
+            ("memory", pressure.memory()),
+            ("io", pressure.io()),
+        ] {
+            add_serialized(
+                encoder,
+                "malachite_pressure",
+                &labels(&[("resource", resource)]),
+                psi,
+            );
+        }
+    }
+}
+
+/// every tracked cgroup, labelled by its user path; the subsystem and
+/// cgroup version enum tags become part of the name and a label
+fn encode_cgroups(encoder: &mut MetricsEncoder, system: &System) {
+    for (user_path, cgroup) in system.get_cgroup_list() {
+        let path = user_path.display().to_string();
+        for (sub_system_type, sub_system) in cgroup.sub_system_groups.iter() {
+            let name = format!("malachite_cgroup_{}", sub_system_type);
+            let value = match serde_json::to_value(sub_system) {
+                Ok(value) => value,
+                Err(e) => {
+                    warn!("[metrics] serialize cgroup {} error: {}", path, e);
+                    continue;
+                }
+            };
+            // externally tagged: {"Memory": {"V2": {...}}} or {"Net": {...}}
+            let inner = match value.as_object().and_then(|m| m.values().next()) {
+                Some(inner) => inner,
+                None => continue,
+            };
+            let mut cgroup_labels = labels(&[("cgroup", &path)]);
+            match inner.as_object() {
+                Some(m) if m.len() == 1 && (m.contains_key("V1") || m.contains_key("V2")) => {
+                    let (version, data) = m.iter().next().unwrap();
+                    cgroup_labels.push(("version".to_string(), version.to_lowercase()));
+                    encoder.add_value(&name, &cgroup_labels, data);
+                }
+                _ => encoder.add_value(&name, &cgroup_labels, inner),
+            }
+        }
+    }
+}
+
+/// samples of everything the system instance holds
+pub(crate) fn collect_system(system: &System) -> MetricsEncoder {
+    let mut encoder = MetricsEncoder::new();
+    let no_labels = Labels::new();
+
+    encode_processors(&mut encoder, system);
+    add_serialized(
+        &mut encoder,
+        "malachite_process",
+        &no_labels,
+        system.get_process_stats(),
+    );
+    add_serialized(
+        &mut encoder,
+        "malachite_memory",
+        &no_labels,
+        system.get_memory_info(),
+    );
+    add_serialized(
+        &mut encoder,
+        "malachite_load",
+        &no_labels,
+        system.get_load(),
+    );
+    add_serialized(
+        &mut encoder,
+        "malachite_network_card",
+        &no_labels,
+        system.get_net_traffic().deref(),
+    );
+    add_serialized(
+        &mut encoder,
+        "malachite_network",
+        &no_labels,
+        system.get_net_info(),
+    );
+    add_serialized(
+        &mut encoder,
+        "malachite_disk_stat",
+        &no_labels,
+        system.get_disk_io().get_stats(),
+    );
+    add_serialized(
+        &mut encoder,
+        "malachite_disk_usage",
+        &no_labels,
+        system.get_disk_io().get_usage(),
+    );
+    encode_pressure(&mut encoder, system);
+    encode_numa_nodes(&mut encoder, system);
+    add_serialized(
+        &mut encoder,
+        "malachite_system_event",
+        &no_labels,
+        system.get_system_event(),
+    );
+    add_serialized(
+        &mut encoder,
+        "malachite_bpf_prog",
+        &no_labels,
+        system.get_bpf_prog_stats(),
+    );
+    encode_cgroups(&mut encoder, system);
+
+    encoder
+}
+
+/// prometheus text exposition of system and cgroup info
+#[utoipa::path(
+    responses(
+        (status = 200, description = "prometheus metrics api", body = String, content_type = "text/plain")
+    )
+)]
+#[get("/metrics")]
+async fn metrics(_access: ReadAccess) -> Result<(ContentType, String), Status> {
+    let reader_lock = MONITOR.get_monitor_reader();
+    let body = collect_system(&reader_lock.read()).encode();
+    Ok((
+        ContentType::new("text", "plain").with_params(("version", "0.0.4")),
+        body,
+    ))
+}
+
+pub fn metrics_router() -> rocket::fairing::AdHoc {
+    rocket::fairing::AdHoc::on_ignite("JSON", |rocket| async {
+        rocket.mount("/", routes![metrics])
+    })
+}
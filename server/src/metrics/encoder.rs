@@ -0,0 +1,434 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use rocket::serde::json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// map-like fields whose keys are label values rather than metric names
+const KEYED_FIELDS: &[(&str, &str)] = &[
+    ("io_stat", "device"),
+    ("io_max", "device"),
+    ("io_latency", "device"),
+    ("io_weight", "device"),
+    ("iops_details", "device"),
+    ("bps_details", "device"),
+    ("mem_numa_stats", "numa"),
+];
+
+/// fields that identify an element of an array, and the label they become
+const ELEMENT_ID_FIELDS: &[(&str, &str)] = &[
+    ("device_name", "device"),
+    ("name", "device"),
+    ("mount_point", "mount_point"),
+    ("channel_name", "channel"),
+    ("numa_name", "numa"),
+    ("id", "id"),
+];
+
+/// arrays of plain numbers whose index is meaningful
+const INDEXED_FIELDS: &[(&str, &str)] = &[("percpu_usage", "cpu")];
+
+/// fields holding cumulative kernel counters, typed `counter` when their values
+/// are integers; everything else is a `gauge`
+const COUNTER_FIELDS: &[&str] = &[
+    // cpu.stat, cpuacct
+    "usage_usec",
+    "user_usec",
+    "system_usec",
+    "nr_periods",
+    "nr_throttled",
+    "throttled_usec",
+    "nr_bursts",
+    "burst_usec",
+    "percpu_usage",
+    // memory.stat, memory.events
+    "pgfault",
+    "pgmajfault",
+    "pgrefill",
+    "pgscan",
+    "pgsteal",
+    "pgactivate",
+    "pgdeactivate",
+    "pglazyfree",
+    "pglazyfreed",
+    "thp_fault_alloc",
+    "thp_collapse_alloc",
+    "workingset_refault",
+    "workingset_activate",
+    "workingset_nodereclaim",
+    "workingset_restore_anon",
+    "workingset_restore_file",
+    "zswpin",
+    "zswpout",
+    "allocstall",
+    "kswapd_steal",
+    "total_pgfault",
+    "total_pgmajfault",
+    "total_allocstall",
+    "total_kswapd_steal",
+    "oom_cnt",
+    "max_events",
+    // io.stat, blkio, diskstats
+    "rbytes",
+    "wbytes",
+    "rios",
+    "wios",
+    "dbytes",
+    "dios",
+    "Read",
+    "Write",
+    "Sync",
+    "Async",
+    "Total",
+    "io_read",
+    "io_write",
+    "io_busy",
+    // net/dev, net/netstat, net/snmp
+    "receive_bytes",
+    "receive_packets",
+    "receive_errs",
+    "receive_drop",
+    "receive_fifo",
+    "receive_frame",
+    "receive_compressed",
+    "receive_multicast",
+    "transmit_bytes",
+    "transmit_packets",
+    "transmit_errs",
+    "transmit_drop",
+    "transmit_fifo",
+    "transmit_colls",
+    "transmit_carrier",
+    "transmit_compressed",
+    "tcp_delay_acks",
+    "tcp_listen_overflows",
+    "tcp_listen_drops",
+    "tcp_abort_on_memory",
+    "tcp_req_q_full_drop",
+    "tcp_retrans_segs",
+    "tcp_out_segs",
+];
+
+/// fields that carry no metric value
+const SKIPPED_FIELDS: &[&str] = &["full_path", "user_path", "path", "cpu_list", "meta"];
+
+pub(crate) type Labels = Vec<(String, String)>;
+
+/// Collects samples and renders them in the Prometheus text exposition format.
+#[derive(Default)]
+pub(crate) struct MetricsEncoder {
+    families: BTreeMap<String, Vec<(Labels, f64)>>,
+    counters: BTreeSet<String>,
+}
+
+impl MetricsEncoder {
+    pub(crate) fn new() -> MetricsEncoder {
+        MetricsEncoder::default()
+    }
+
+    pub(crate) fn add_sample(&mut self, name: &str, labels: &Labels, value: f64) {
+        self.families
+            .entry(sanitize_name(name))
+            .or_default()
+            .push((labels.clone(), value));
+    }
+
+    /// flatten a serialized structure into samples named `<name>_<field>...`,
+    /// strings and nulls are skipped
+    pub(crate) fn add_value(&mut self, name: &str, labels: &Labels, value: &Value) {
+        match value {
+            Value::Number(n) => {
+                if let Some(v) = n.as_f64() {
+                    self.add_sample(name, labels, v);
+                }
+            }
+            Value::Bool(b) => self.add_sample(name, labels, if *b { 1.0 } else { 0.0 }),
+            Value::Object(map) => {
+                for (key, item) in map {
+                    self.add_field(name, key, labels, item);
+                }
+            }
+            Value::Array(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    self.add_element(name, "index", index, labels, item);
+                }
+            }
+            Value::String(_) | Value::Null => {}
+        }
+    }
+
+    fn add_field(&mut self, name: &str, key: &str, labels: &Labels, value: &Value) {
+        if SKIPPED_FIELDS.contains(&key) {
+            return;
+        }
+        let field_name = format!("{}_{}", name, key);
+        if COUNTER_FIELDS.contains(&key) && is_integer(value) {
+            self.counters.insert(sanitize_name(&field_name));
+        }
+
+        if let Some((_, label)) = KEYED_FIELDS.iter().find(|(k, _)| *k == key) {
+            if let Value::Object(map) = value {
+                for (label_value, item) in map {
+                    let mut item_labels = labels.clone();
+                    item_labels.push((label.to_string(), label_value.clone()));
+                    self.add_value(&field_name, &item_labels, item);
+                }
+                return;
+            }
+        }
+
+        if let Value::Array(items) = value {
+            let label = INDEXED_FIELDS
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, l)| *l)
+                .unwrap_or("index");
+            for (index, item) in items.iter().enumerate() {
+                self.add_element(&field_name, label, index, labels, item);
+            }
+            return;
+        }
+
+        self.add_value(&field_name, labels, value)
+    }
+
+    fn add_element(
+        &mut self,
+        name: &str,
+        index_label: &str,
+        index: usize,
+        labels: &Labels,
+        value: &Value,
+    ) {
+        let mut item_labels = labels.clone();
+        if let Value::Object(map) = value {
+            let id = ELEMENT_ID_FIELDS
+                .iter()
+                .find_map(|(field, label)| match map.get(*field) {
+                    Some(Value::String(s)) => Some((*field, *label, s.clone())),
+                    Some(Value::Number(n)) => Some((*field, *label, n.to_string())),
+                    _ => None,
+                });
+            if let Some((id_field, label, label_value)) = id {
+                item_labels.push((label.to_string(), label_value));
+                for (key, item) in map.iter().filter(|(k, _)| k.as_str() != id_field) {
+                    self.add_field(name, key, &item_labels, item);
+                }
+                return;
+            }
+        }
+        item_labels.push((index_label.to_string(), index.to_string()));
+        self.add_value(name, &item_labels, value);
+    }
+
+    pub(crate) fn families(&self) -> &BTreeMap<String, Vec<(Labels, f64)>> {
+        &self.families
+    }
+
+    pub(crate) fn encode(&self) -> String {
+        let mut out = String::new();
+        for (name, samples) in self.families.iter() {
+            let metric_type = if self.counters.contains(name) {
+                "counter"
+            } else {
+                "gauge"
+            };
+            let _ = writeln!(out, "# TYPE {} {}", name, metric_type);
+            for (labels, value) in samples {
+                out.push_str(name);
+                if !labels.is_empty() {
+                    let labels = labels
+                        .iter()
+                        .map(|(k, v)| format!("{}=\"{}\"", sanitize_name(k), escape_label(v)))
+                        .collect::<Vec<String>>()
+                        .join(",");
+                    let _ = write!(out, "{{{}}}", labels);
+                }
+                let _ = writeln!(out, " {}", format_value(*value));
+            }
+        }
+        out
+    }
+}
+
+/// an integer, or an array of them
+fn is_integer(value: &Value) -> bool {
+    match value {
+        Value::Array(items) => items.iter().all(Value::is_u64),
+        _ => value.is_u64(),
+    }
+}
+
+fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests_encoder {
+    use super::*;
+    use rocket::serde::json::serde_json::json;
+
+    fn labels(pairs: &[(&str, &str)]) -> Labels {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn samples(encoder: &MetricsEncoder, name: &str) -> Vec<(Labels, f64)> {
+        encoder.families().get(name).cloned().unwrap_or_default()
+    }
+
+    #[test]
+    fn test_sanitize_name() {
+        assert_eq!(sanitize_name("malachite_cpu_usage"), "malachite_cpu_usage");
+        assert_eq!(
+            sanitize_name("malachite_io_stat_Read"),
+            "malachite_io_stat_read"
+        );
+        assert_eq!(
+            sanitize_name("malachite_disk.usage-ratio"),
+            "malachite_disk_usage_ratio"
+        );
+        assert_eq!(sanitize_name("a:b c"), "a:b_c");
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("/kubepods/pod1"), "/kubepods/pod1");
+        assert_eq!(escape_label(r#"a"b"#), r#"a\"b"#);
+        assert_eq!(escape_label(r"a\b"), r"a\\b");
+        assert_eq!(escape_label("a\nb"), r"a\nb");
+    }
+
+    #[test]
+    fn test_keyed_fields() {
+        let mut encoder = MetricsEncoder::new();
+        let value = json!({
+            "io_stat": {
+                "8:0": {"rbytes": 10, "wbytes": 20},
+                "8:16": {"rbytes": 30, "wbytes": 40}
+            },
+            "full_path": "/sys/fs/cgroup/kubepods",
+            "user_path": "/kubepods"
+        });
+        encoder.add_value(
+            "malachite_cgroup_blkio",
+            &labels(&[("cgroup", "/a")]),
+            &value,
+        );
+
+        assert_eq!(
+            samples(&encoder, "malachite_cgroup_blkio_io_stat_rbytes"),
+            vec![
+                (labels(&[("cgroup", "/a"), ("device", "8:0")]), 10.0),
+                (labels(&[("cgroup", "/a"), ("device", "8:16")]), 30.0),
+            ]
+        );
+        // paths and other strings are no samples
+        assert_eq!(encoder.families().len(), 2);
+    }
+
+    #[test]
+    fn test_array_flattening() {
+        let mut encoder = MetricsEncoder::new();
+        let value = json!({
+            "stats": [
+                {"device_name": "sda", "io_read": 1},
+                {"device_name": "sdb", "io_read": 2}
+            ],
+            "percpu_usage": [100, 200],
+            "watermarks": [7, 8],
+            "enabled": true,
+            "ratio": null
+        });
+        encoder.add_value("malachite_disk", &Labels::new(), &value);
+
+        // elements named by an id field are labelled by it
+        assert_eq!(
+            samples(&encoder, "malachite_disk_stats_io_read"),
+            vec![
+                (labels(&[("device", "sda")]), 1.0),
+                (labels(&[("device", "sdb")]), 2.0),
+            ]
+        );
+        // known indexed fields get their own label, others the index
+        assert_eq!(
+            samples(&encoder, "malachite_disk_percpu_usage"),
+            vec![
+                (labels(&[("cpu", "0")]), 100.0),
+                (labels(&[("cpu", "1")]), 200.0),
+            ]
+        );
+        assert_eq!(
+            samples(&encoder, "malachite_disk_watermarks"),
+            vec![
+                (labels(&[("index", "0")]), 7.0),
+                (labels(&[("index", "1")]), 8.0),
+            ]
+        );
+        assert_eq!(
+            samples(&encoder, "malachite_disk_enabled"),
+            vec![(vec![], 1.0)]
+        );
+        assert!(samples(&encoder, "malachite_disk_ratio").is_empty());
+    }
+
+    #[test]
+    fn test_encode() {
+        let mut encoder = MetricsEncoder::new();
+        let value = json!({"pgfault": 12, "anon": 4096, "usage_ratio": 0.5});
+        encoder.add_value("malachite_memory", &labels(&[("path", "a\"b")]), &value);
+        encoder.add_sample("malachite_cpu_usage_ratio", &Labels::new(), f64::NAN);
+
+        assert_eq!(
+            encoder.encode(),
+            "# TYPE malachite_cpu_usage_ratio gauge\n\
+             malachite_cpu_usage_ratio NaN\n\
+             # TYPE malachite_memory_anon gauge\n\
+             malachite_memory_anon{path=\"a\\\"b\"} 4096\n\
+             # TYPE malachite_memory_pgfault counter\n\
+             malachite_memory_pgfault{path=\"a\\\"b\"} 12\n\
+             # TYPE malachite_memory_usage_ratio gauge\n\
+             malachite_memory_usage_ratio{path=\"a\\\"b\"} 0.5\n"
+        );
+    }
+}
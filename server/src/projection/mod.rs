@@ -0,0 +1,320 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::common::ApiError;
+use crate::history::RespSeries;
+use crate::system::{RespCompute, RespIo, RespMemory, RespNetwork};
+use lib::cgroup::{CGroup, SubSystemType};
+use lib::cpu::NodeVec;
+use lib::system::SystemEventData;
+use rocket::serde::json::serde_json::{self, Map};
+use rocket::serde::json::Value;
+use rocket::serde::Serialize;
+
+/// selection of a response, from the `fields`, `subsystems`, `cpus` and `numa` query parameters;
+/// it only shrinks responses, what is collected is up to the data source settings
+#[derive(Default)]
+pub(crate) struct Projection {
+    /// dotted paths, arrays are selected element by element
+    fields: Option<Vec<Vec<String>>>,
+    subsystems: Option<Vec<SubSystemType>>,
+    cpus: Option<NodeVec>,
+    numa: Option<NodeVec>,
+}
+
+/// responses which have parts selectable other than by `fields`
+pub(crate) trait Project: Serialize + Sized {
+    fn project(self, _projection: &Projection) -> Self {
+        self
+    }
+}
+
+fn split(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+fn parse_nodes(name: &str, list: Option<String>) -> Result<Option<NodeVec>, ApiError> {
+    list.map(|list| {
+        list.parse()
+            .map_err(|e| ApiError::InvalidQuery(format!("{} {}: {}", name, list, e)))
+    })
+    .transpose()
+}
+
+impl Projection {
+    pub(crate) fn new(
+        fields: Option<String>,
+        subsystems: Option<String>,
+        cpus: Option<String>,
+        numa: Option<String>,
+    ) -> Result<Projection, ApiError> {
+        let fields = fields.map(|fields| {
+            split(&fields)
+                .map(|field| field.split('.').map(String::from).collect())
+                .collect()
+        });
+        let subsystems = subsystems
+            .map(|subsystems| {
+                split(&subsystems)
+                    .map(|subsystem| subsystem.parse().map_err(ApiError::InvalidQuery))
+                    .collect::<Result<Vec<SubSystemType>, ApiError>>()
+            })
+            .transpose()?;
+        Ok(Projection {
+            fields,
+            subsystems,
+            cpus: parse_nodes("cpus", cpus)?,
+            numa: parse_nodes("numa", numa)?,
+        })
+    }
+
+    /// the selected parts of the data, unknown fields are left out
+    pub(crate) fn apply<T: Project>(&self, data: T) -> Result<Value, ApiError> {
+        let value = serde_json::to_value(data.project(self))
+            .map_err(|e| ApiError::Internal(format!("serialize response error: {}", e)))?;
+        Ok(match &self.fields {
+            Some(fields) => {
+                let paths: Vec<&[String]> = fields.iter().map(Vec::as_slice).collect();
+                select(&value, &paths)
+            }
+            None => value,
+        })
+    }
+
+    /// the selected series of a range query; `fields` address the snapshot layout,
+    /// which series do not have, so they are rejected rather than ignored
+    pub(crate) fn apply_series(
+        &self,
+        series: Vec<RespSeries>,
+    ) -> Result<Vec<RespSeries>, ApiError> {
+        if self.fields.is_some() {
+            return Err(ApiError::InvalidQuery(String::from(
+                "fields cannot be combined with a range, series are selected by cpus, numa and subsystems",
+            )));
+        }
+        Ok(series
+            .into_iter()
+            .filter(|series| self.keeps(series))
+            .collect())
+    }
+
+    fn keeps(&self, series: &RespSeries) -> bool {
+        // a series without the label, e.g. of the global processor, is kept
+        let in_nodes = |nodes: &Option<NodeVec>, label: &str, prefix: &str| match nodes {
+            Some(nodes) => series
+                .labels
+                .get(label)
+                .and_then(|value| value.strip_prefix(prefix).unwrap_or(value).parse().ok())
+                .is_none_or(|id: usize| nodes.contains(&id)),
+            None => true,
+        };
+        let in_subsystems = match &self.subsystems {
+            Some(subsystems) => match series.name.strip_prefix("malachite_cgroup_") {
+                Some(name) => subsystems
+                    .iter()
+                    .any(|subsystem| name.starts_with(&format!("{}_", subsystem))),
+                None => true,
+            },
+            None => true,
+        };
+        // processors are labelled cpu0, cgroup numa stats N0 and system nodes 0
+        in_nodes(&self.cpus, "cpu", "cpu") && in_nodes(&self.numa, "numa", "N") && in_subsystems
+    }
+}
+
+fn select(value: &Value, paths: &[&[String]]) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(items.iter().map(|item| select(item, paths)).collect()),
+        Value::Object(map) => {
+            let mut selected = Map::new();
+            for (key, item) in map {
+                let rest: Vec<&[String]> = paths
+                    .iter()
+                    .filter(|path| path.first() == Some(key))
+                    .map(|path| &path[1..])
+                    .collect();
+                if rest.is_empty() {
+                    continue;
+                }
+                if rest.iter().any(|path| path.is_empty()) {
+                    selected.insert(key.clone(), item.clone());
+                } else {
+                    selected.insert(key.clone(), select(item, &rest));
+                }
+            }
+            Value::Object(selected)
+        }
+        // a path going below a plain value keeps it
+        _ => value.clone(),
+    }
+}
+
+impl Project for RespCompute {
+    fn project(mut self, projection: &Projection) -> Self {
+        if let Some(cpus) = &projection.cpus {
+            // processors are named after their index, e.g. cpu0
+            self.cpu.retain(|cpu| {
+                cpu.name
+                    .strip_prefix("cpu")
+                    .and_then(|id| id.parse::<usize>().ok())
+                    .is_some_and(|id| cpus.contains(&id))
+            });
+        }
+        self
+    }
+}
+
+impl Project for RespMemory {
+    fn project(mut self, projection: &Projection) -> Self {
+        if let Some(numa) = &projection.numa {
+            self.numa.retain(|node| numa.contains(&node.get_id()));
+        }
+        self
+    }
+}
+
+impl Project for CGroup {
+    fn project(mut self, projection: &Projection) -> Self {
+        if let Some(subsystems) = &projection.subsystems {
+            self.sub_system_groups
+                .retain(|sub_system_type, _| subsystems.contains(sub_system_type));
+        }
+        if let Some(numa) = &projection.numa {
+            self.retain_numa_nodes(numa);
+        }
+        self
+    }
+}
+
+impl Project for RespIo {}
+
+impl Project for RespNetwork {}
+
+impl Project for SystemEventData {}
+
+#[cfg(test)]
+mod tests_projection {
+    use super::*;
+    use rocket::serde::json::serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn series(name: &str, labels: &[(&str, &str)]) -> RespSeries {
+        RespSeries {
+            name: name.to_string(),
+            labels: labels
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<BTreeMap<String, String>>(),
+            points: vec![(1, 1.0)],
+        }
+    }
+
+    fn names(series: &[RespSeries]) -> Vec<String> {
+        series
+            .iter()
+            .map(|s| format!("{}{:?}", s.name, s.labels.values().collect::<Vec<_>>()))
+            .collect()
+    }
+
+    #[test]
+    fn test_select() {
+        let value = json!({
+            "load": {"one": 1.0, "five": 2.0},
+            "cpu": [{"name": "cpu0", "cpu_usage": 3.0}, {"name": "cpu1", "cpu_usage": 4.0}],
+        });
+        let paths = [
+            vec![String::from("load"), String::from("one")],
+            vec![String::from("cpu"), String::from("name")],
+        ];
+        let paths: Vec<&[String]> = paths.iter().map(Vec::as_slice).collect();
+        assert_eq!(
+            select(&value, &paths),
+            json!({"load": {"one": 1.0}, "cpu": [{"name": "cpu0"}, {"name": "cpu1"}]})
+        );
+    }
+
+    #[test]
+    fn test_apply_series_cpus() {
+        let projection = Projection::new(None, None, Some(String::from("0-1")), None).unwrap();
+        let selected = projection
+            .apply_series(vec![
+                series("malachite_cpu_usage_ratio", &[("cpu", "cpu0")]),
+                series("malachite_cpu_usage_ratio", &[("cpu", "cpu3")]),
+                series("malachite_cpu_usage_ratio", &[("cpu", "all")]),
+                series("malachite_load_one", &[]),
+            ])
+            .unwrap();
+        assert_eq!(
+            names(&selected),
+            vec![
+                "malachite_cpu_usage_ratio[\"cpu0\"]",
+                "malachite_cpu_usage_ratio[\"all\"]",
+                "malachite_load_one[]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_series_cgroup() {
+        let projection = Projection::new(
+            None,
+            Some(String::from("memory")),
+            None,
+            Some(String::from("1")),
+        )
+        .unwrap();
+        let selected = projection
+            .apply_series(vec![
+                series("malachite_cgroup_memory_rss", &[("cgroup", "/a")]),
+                series(
+                    "malachite_cgroup_memory_mem_numa_stats_total",
+                    &[("cgroup", "/a"), ("numa", "N0")],
+                ),
+                series(
+                    "malachite_cgroup_memory_mem_numa_stats_total",
+                    &[("cgroup", "/a"), ("numa", "N1")],
+                ),
+                series("malachite_cgroup_cpuacct_cpu_usage", &[("cgroup", "/a")]),
+            ])
+            .unwrap();
+        assert_eq!(
+            names(&selected),
+            vec![
+                "malachite_cgroup_memory_rss[\"/a\"]",
+                "malachite_cgroup_memory_mem_numa_stats_total[\"/a\", \"N1\"]",
+            ]
+        );
+
+        // system nodes are labelled by their bare id
+        let selected = projection
+            .apply_series(vec![
+                series("malachite_numa_mem_free", &[("numa", "0")]),
+                series("malachite_numa_mem_free", &[("numa", "1")]),
+            ])
+            .unwrap();
+        assert_eq!(names(&selected), vec!["malachite_numa_mem_free[\"1\"]"]);
+    }
+
+    #[test]
+    fn test_apply_series_fields() {
+        let projection = Projection::new(Some(String::from("load")), None, None, None).unwrap();
+        assert!(matches!(
+            projection.apply_series(vec![series("malachite_load_one", &[])]),
+            Err(ApiError::InvalidQuery(_))
+        ));
+    }
+}
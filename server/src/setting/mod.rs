@@ -13,33 +13,311 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use crate::common::Resp;
+mod reload;
+
+pub use reload::*;
+
+use crate::auth::{AdminAccess, ReadAccess};
+use crate::common::{ApiError, ApiResult, Resp, RespError};
 use crate::system;
 use lib::settings;
-use rocket::http::Status;
-use rocket::serde::json::Json;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rocket::serde::json::{serde_json, Json, Value};
 use std::thread;
 
-#[post("/", data = "<setting>")]
-async fn update_settings(
-    setting: Json<settings::Settings>,
-) -> Result<Json<Resp<String>>, Status> {
+/// merge patches applied through the API since the last reset, persisted as the state file
+static OVERRIDES: Lazy<Mutex<Value>> = Lazy::new(|| {
+    let path = settings::state_path();
+    Mutex::new(settings::Settings::load_state(&path).unwrap_or_else(|e| {
+        error!(
+            "[settings] read {} error, start over from the default file: {}",
+            path.display(),
+            e
+        );
+        Value::Object(Default::default())
+    }))
+});
+
+fn send_settings(event: system::ChangeEvent) {
     thread::spawn(move || {
         let sender = system::CHANGE_CHANNEL.get_channel_sender().clone();
-        sender
-            .send(system::ChangeEvent::SettingsEvent(Box::new(
-                setting.into_inner(),
-            )))
-            .unwrap();
+        sender.send(event).unwrap();
     })
     .join()
     .unwrap();
+}
+
+/// RFC 7386 JSON merge patch
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let patch_map = match patch.as_object() {
+        Some(patch_map) => patch_map,
+        None => {
+            *target = patch.clone();
+            return;
+        }
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let target_map = target.as_object_mut().unwrap();
+    for (key, value) in patch_map {
+        if value.is_null() {
+            target_map.remove(key);
+        } else {
+            merge_patch(target_map.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// first field of `value` which is not part of `known`, settings ignore unknown fields on their own
+fn find_unknown_field(value: &Value, known: &Value, path: &str) -> Option<String> {
+    let (value_map, known_map) = match (value.as_object(), known.as_object()) {
+        (Some(value_map), Some(known_map)) => (value_map, known_map),
+        _ => return None,
+    };
+    for (key, value) in value_map {
+        let field = if path.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", path, key)
+        };
+        match known_map.get(key) {
+            Some(known) => {
+                if let Some(unknown) = find_unknown_field(value, known, &field) {
+                    return Some(unknown);
+                }
+            }
+            None => return Some(field),
+        }
+    }
+    None
+}
+
+fn apply_patch(
+    current: &settings::Settings,
+    patch: &Value,
+) -> Result<settings::Settings, RespError> {
+    let mut merged = serde_json::to_value(current).map_err(|e| RespError {
+        message: e.to_string(),
+        field: None,
+    })?;
+    merge_patch(&mut merged, patch);
+
+    let patched: settings::Settings =
+        serde_json::from_value(merged.clone()).map_err(|e| RespError {
+            message: format!("invalid settings: {}", e),
+            field: None,
+        })?;
+    let known = serde_json::to_value(&patched).map_err(|e| RespError {
+        message: e.to_string(),
+        field: None,
+    })?;
+    if let Some(field) = find_unknown_field(&merged, &known, "") {
+        return Err(RespError {
+            message: format!("unknown field {}", field),
+            field: Some(field),
+        });
+    }
+    Ok(patched)
+}
+
+/// current settings
+#[utoipa::path(
+    context_path = "/v1/settings",
+    responses(
+        (status = 200, description = "settings api", body = [Settings])
+    )
+)]
+#[get("/")]
+async fn get_settings(_access: ReadAccess) -> ApiResult<settings::Settings> {
+    let settings = system::MONITOR.get_settings().read().clone();
+    Ok(Json(Resp::new(settings)))
+}
+
+/// patch settings with a JSON merge patch, e.g. `{"ebpf":{"net":{"enable":false}}}`
+///
+/// the patched settings are returned at once, data sources are switched on the next monitor update
+#[utoipa::path(
+    context_path = "/v1/settings",
+    request_body(content = Object, description = "JSON merge patch of the settings"),
+    responses(
+        (status = 200, description = "patched settings", body = [Settings]),
+        (status = 422, description = "the patched settings are invalid", body = RespErrorBody)
+    )
+)]
+#[patch("/", data = "<patch>")]
+async fn patch_settings(patch: Json<Value>, _access: AdminAccess) -> ApiResult<settings::Settings> {
+    // hold the lock so that concurrent patches apply on top of each other
+    let mut current = system::MONITOR.get_settings().write();
+    match apply_patch(&current, &patch) {
+        Ok(patched) => {
+            current.update(patched.clone());
+            let mut overrides = OVERRIDES.lock();
+            merge_patch(&mut overrides, &patch);
+            send_settings(system::ChangeEvent::SettingsPersistEvent(
+                Box::new(patched.clone()),
+                Box::new(overrides.clone()),
+            ));
+            Ok(Json(Resp::new(patched)))
+        }
+        Err(e) => Err(ApiError::SettingsInvalid(e)),
+    }
+}
+
+#[post("/", data = "<setting>")]
+async fn update_settings(
+    setting: Json<settings::Settings>,
+    _access: AdminAccess,
+) -> ApiResult<String> {
+    // every key is set, so the whole settings override the default file
+    let setting = setting.into_inner();
+    let overrides = serde_json::to_value(&setting)
+        .map_err(|e| ApiError::Internal(format!("serialize settings error: {}", e)))?;
+    // hold the lock as a patch does, so that a patch right after applies on top
+    let mut current = system::MONITOR.get_settings().write();
+    current.update(setting.clone());
+    *OVERRIDES.lock() = overrides.clone();
+    send_settings(system::ChangeEvent::SettingsPersistEvent(
+        Box::new(setting),
+        Box::new(overrides),
+    ));
 
     Ok(Json(Resp::new(String::from("success"))))
 }
 
+/// discard the settings changed through the API and go back to the default file
+#[utoipa::path(
+    context_path = "/v1/settings",
+    responses(
+        (status = 200, description = "default settings", body = [Settings]),
+        (status = 500, description = "the persisted settings or the default file can not be handled", body = RespErrorBody)
+    )
+)]
+#[post("/reset")]
+async fn reset_settings(_access: AdminAccess) -> ApiResult<settings::Settings> {
+    let mut current = system::MONITOR.get_settings().write();
+    let path = settings::state_path();
+    if let Err(e) = settings::Settings::remove_state(&path) {
+        return Err(ApiError::Internal(format!(
+            "remove {} error: {}",
+            path.display(),
+            e
+        )));
+    }
+    *OVERRIDES.lock() = Value::Object(Default::default());
+    let default = settings::Settings::load_default()
+        .map_err(|e| ApiError::Internal(format!("load default settings error: {}", e)))?;
+    current.update(default.clone());
+    drop(current);
+    // the empty overrides replace any patch still waiting to be saved
+    send_settings(system::ChangeEvent::SettingsPersistEvent(
+        Box::new(default.clone()),
+        Box::new(Value::Object(Default::default())),
+    ));
+    Ok(Json(Resp::new(default)))
+}
+
 pub fn settings_v1_router() -> rocket::fairing::AdHoc {
     rocket::fairing::AdHoc::on_ignite("JSON", |rocket| async {
-        rocket.mount("/v1/settings", routes![update_settings])
+        rocket.mount(
+            "/v1/settings",
+            routes![
+                update_settings,
+                get_settings,
+                patch_settings,
+                reset_settings
+            ],
+        )
     })
 }
+
+#[cfg(test)]
+mod tests_setting {
+    use super::*;
+    use rocket::serde::json::json;
+
+    #[test]
+    fn test_merge_patch() {
+        let mut target = json!({
+            "a": "b",
+            "c": {"d": "e", "f": "g"},
+            "h": [1, 2]
+        });
+        merge_patch(
+            &mut target,
+            &json!({
+                "a": "z",
+                "c": {"f": null, "x": {"y": 1}},
+                "h": [3],
+                "i": null
+            }),
+        );
+        assert_eq!(
+            target,
+            json!({
+                "a": "z",
+                "c": {"d": "e", "x": {"y": 1}},
+                "h": [3]
+            })
+        );
+
+        // a non object patch replaces the target, a patch object replaces a non object target
+        let mut target = json!({"a": "b"});
+        merge_patch(&mut target, &json!(["c"]));
+        assert_eq!(target, json!(["c"]));
+        let mut target = json!({"a": "b"});
+        merge_patch(&mut target, &json!({"a": {"b": "c"}}));
+        assert_eq!(target, json!({"a": {"b": "c"}}));
+    }
+
+    #[test]
+    fn test_find_unknown_field() {
+        let known = json!({"enable": true, "proc": {"cpu": {"interval": 1}}});
+        assert_eq!(
+            find_unknown_field(&json!({"enable": false}), &known, ""),
+            None
+        );
+        assert_eq!(
+            find_unknown_field(&json!({"enabled": false}), &known, ""),
+            Some("enabled".to_string())
+        );
+        assert_eq!(
+            find_unknown_field(
+                &json!({"proc": {"cpu": {"interval": 1, "intervall": 2}}}),
+                &known,
+                ""
+            ),
+            Some("proc.cpu.intervall".to_string())
+        );
+    }
+
+    #[test]
+    fn test_apply_patch() {
+        let current = settings::Settings::default();
+        let value = |s: &settings::Settings| serde_json::to_value(s).unwrap();
+
+        let patched = apply_patch(
+            &current,
+            &json!({"proc": {"cpu": {"interval": 7}}, "history": {"retention": 60}}),
+        )
+        .unwrap();
+        assert_eq!(value(&patched)["proc"]["cpu"]["interval"], json!(7));
+        assert_eq!(value(&patched)["history"]["retention"], json!(60));
+        assert_eq!(value(&patched)["sys"], value(&current)["sys"]);
+
+        // null deletes the member, defaulted sections fall back to their defaults
+        let reset = apply_patch(&patched, &json!({"history": null})).unwrap();
+        assert_eq!(value(&reset)["history"], value(&current)["history"]);
+
+        let e = apply_patch(&current, &json!({"proc": {"cpu": {"intervall": 7}}})).unwrap_err();
+        assert_eq!(e.field, Some("proc.cpu.intervall".to_string()));
+        assert_eq!(e.message, "unknown field proc.cpu.intervall");
+
+        let e = apply_patch(&current, &json!({"proc": {"cpu": {"interval": "7"}}})).unwrap_err();
+        assert_eq!(e.field, None);
+        assert!(e.message.starts_with("invalid settings"));
+        let e = apply_patch(&current, &json!({"proc": {"cpu": null}})).unwrap_err();
+        assert!(e.message.starts_with("invalid settings"));
+    }
+}
@@ -0,0 +1,177 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use super::OVERRIDES;
+use crate::system::{ChangeEvent, CHANGE_CHANNEL, MONITOR};
+use crate::watcher::FileWatcher;
+use lib::settings::{self, Settings};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::thread::{self, sleep};
+use std::time::{Duration, SystemTime};
+use utoipa::ToSchema;
+
+const WAIT_TIMEOUT_MS: i32 = 1000;
+
+pub(crate) static RELOAD_STATUS: Lazy<RwLock<ReloadStatus>> =
+    Lazy::new(|| RwLock::new(ReloadStatus::default()));
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq, Eq, Hash, ToSchema)]
+pub struct ReloadStatus {
+    /// last time the settings file was reloaded, in seconds since epoch, 0 if never
+    last_reload: u64,
+    /// error of the last reload, the previous settings are kept meanwhile
+    error: Option<String>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// the settings to switch to, if they changed, and the status of the reload; on a
+/// parse error the current settings are kept
+fn check_reload(
+    parsed: Result<Settings, String>,
+    current: &Settings,
+) -> (Option<Settings>, ReloadStatus) {
+    match parsed {
+        Ok(new_settings) => {
+            let changed = *current != new_settings;
+            (
+                changed.then_some(new_settings),
+                ReloadStatus {
+                    last_reload: now(),
+                    error: None,
+                },
+            )
+        }
+        Err(e) => {
+            warn!(
+                "[reload] parse settings file error, keep the previous settings: {}",
+                e
+            );
+            (
+                None,
+                ReloadStatus {
+                    last_reload: now(),
+                    error: Some(e),
+                },
+            )
+        }
+    }
+}
+
+/// rebuilt from the default file and the overrides in memory rather than the state
+/// file, which lags behind a patch until the next monitor update
+fn reload() {
+    // locked in the order a patch locks them, so that no patch slips in between
+    let mut current = MONITOR.get_settings().write();
+    let overrides = OVERRIDES.lock();
+    let parsed = Settings::with_overrides(&overrides).map_err(|e| e.to_string());
+    let (new_settings, status) = check_reload(parsed, &current);
+    if let Some(new_settings) = new_settings {
+        info!("[reload] settings file changed, switch settings");
+        current.update(new_settings.clone());
+        if let Err(e) = CHANGE_CHANNEL
+            .get_channel_sender()
+            .send(ChangeEvent::SettingsEvent(Box::new(new_settings)))
+        {
+            warn!("[reload] send change event error: {}", e);
+        }
+    }
+    *RELOAD_STATUS.write() = status;
+}
+
+/// content is compared on every event of the directory rather than mtime: a
+/// ConfigMap update swaps a symlink next to the file
+fn watch() {
+    let path = settings::default_path();
+    let mut last_content = fs::read(&path).ok();
+    let mut watcher: Option<FileWatcher> = None;
+    loop {
+        match watcher.as_mut() {
+            // compared right after the watch is in place, the file may have
+            // changed before
+            None => match FileWatcher::new(&[&path]) {
+                Ok(w) => watcher = Some(w),
+                Err(e) => {
+                    warn!("[reload] watch {} error: {}", path.display(), e);
+                    sleep(Duration::from_millis(WAIT_TIMEOUT_MS as u64));
+                    continue;
+                }
+            },
+            Some(w) => match w.wait_changed(WAIT_TIMEOUT_MS) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    warn!("[reload] read {} events error: {}", path.display(), e);
+                    watcher = None;
+                    continue;
+                }
+            },
+        }
+        let content = match fs::read(&path) {
+            Ok(content) => Some(content),
+            Err(e) => {
+                warn!("[reload] read {} error: {}", path.display(), e);
+                continue;
+            }
+        };
+        if content != last_content {
+            last_content = content;
+            reload();
+        }
+    }
+}
+
+pub fn daemon() {
+    let _handler = thread::Builder::new()
+        .name("settings-reload".into())
+        .spawn(watch);
+}
+
+#[cfg(test)]
+mod tests_reload {
+    use super::*;
+    use rocket::serde::json::{json, serde_json};
+
+    #[test]
+    fn test_check_reload() {
+        let current = Settings::default();
+
+        // a parse error keeps the previous settings and is reported
+        let (new_settings, status) =
+            check_reload(Err("invalid type: string \"x\"".to_string()), &current);
+        assert!(new_settings.is_none());
+        assert_eq!(status.error, Some("invalid type: string \"x\"".to_string()));
+        assert!(status.last_reload > 0);
+
+        let (new_settings, status) = check_reload(Ok(current.clone()), &current);
+        assert!(new_settings.is_none());
+        assert!(status.error.is_none());
+
+        let mut changed = serde_json::to_value(&current).unwrap();
+        changed["history"]["retention"] = json!(60);
+        let changed: Settings = serde_json::from_value(changed).unwrap();
+        let (new_settings, status) = check_reload(Ok(changed.clone()), &current);
+        assert_eq!(new_settings, Some(changed));
+        assert!(status.error.is_none());
+    }
+}
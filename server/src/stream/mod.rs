@@ -0,0 +1,260 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::auth::ReadAccess;
+use crate::system::{self, RespCompute, RespIo, RespMemory, RespNetwork, MONITOR};
+use lib::cgroup::CGroup;
+use lib::system::{System, SystemEventData};
+use rocket::response::stream::{Event, EventStream};
+use rocket::serde::Serialize;
+use rocket::tokio::task::spawn_blocking;
+use rocket::Shutdown;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+#[derive(FromFormField, Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum StreamSection {
+    Compute,
+    Memory,
+    Io,
+    Network,
+    #[field(value = "system_event")]
+    SystemEvent,
+    /// every tracked cgroup
+    Cgroup,
+}
+
+#[derive(Serialize, ToSchema, Debug, Default)]
+pub(crate) struct RespSnapshot {
+    /// generation of the monitor instance the payload is read from
+    pub(crate) generation: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) compute: Option<RespCompute>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) memory: Option<RespMemory>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) io: Option<RespIo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) network: Option<RespNetwork>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) system_event: Option<SystemEventData>,
+    /// cgroups by user path, requested ones which are not tracked are left out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cgroups: Option<HashMap<String, CGroup>>,
+}
+
+struct StreamFilter {
+    sections: Vec<StreamSection>,
+    cgroup_user_paths: Vec<String>,
+}
+
+impl StreamFilter {
+    fn new(sections: Vec<StreamSection>, cgroup_user_paths: Vec<String>) -> StreamFilter {
+        let sections = if sections.is_empty() && cgroup_user_paths.is_empty() {
+            vec![
+                StreamSection::Compute,
+                StreamSection::Memory,
+                StreamSection::Io,
+                StreamSection::Network,
+                StreamSection::SystemEvent,
+                StreamSection::Cgroup,
+            ]
+        } else {
+            sections
+        };
+        StreamFilter {
+            sections,
+            cgroup_user_paths,
+        }
+    }
+
+    fn contains(&self, section: StreamSection) -> bool {
+        self.sections.contains(&section)
+    }
+
+    fn snapshot(&self, generation: u64, system: &System) -> RespSnapshot {
+        let mut snapshot = RespSnapshot {
+            generation,
+            ..Default::default()
+        };
+        if self.contains(StreamSection::Compute) {
+            snapshot.compute = Some(system::compute_info(system));
+        }
+        if self.contains(StreamSection::Memory) {
+            snapshot.memory = Some(system::memory_info(system));
+        }
+        if self.contains(StreamSection::Io) {
+            snapshot.io = Some(system::io_info(system));
+        }
+        if self.contains(StreamSection::Network) {
+            snapshot.network = Some(system::network_info(system));
+        }
+        if self.contains(StreamSection::SystemEvent) {
+            snapshot.system_event = Some(system.get_system_event().clone());
+        }
+        if self.contains(StreamSection::Cgroup) {
+            snapshot.cgroups = Some(
+                system
+                    .get_cgroup_list()
+                    .iter()
+                    .map(|(path, cgroup)| (path.display().to_string(), cgroup.clone()))
+                    .collect(),
+            );
+        } else if !self.cgroup_user_paths.is_empty() {
+            snapshot.cgroups = Some(
+                self.cgroup_user_paths
+                    .iter()
+                    .filter_map(|path| {
+                        system
+                            .get_cgroups(PathBuf::from(path))
+                            .map(|cgroup| (path.clone(), cgroup.clone()))
+                    })
+                    .collect(),
+            );
+        }
+        snapshot
+    }
+}
+
+/// the reader lock blocks while the pointers are changed, so the snapshot is read
+/// and serialized off the async workers; None once the runtime shuts down
+async fn read_snapshot(filter: Arc<StreamFilter>) -> Option<Event> {
+    spawn_blocking(move || {
+        let reader_lock = MONITOR.get_monitor_reader();
+        let generation = MONITOR.get_generation();
+        let snapshot = filter.snapshot(generation, &reader_lock.read());
+        Event::json(&snapshot)
+            .event("snapshot")
+            .id(generation.to_string())
+    })
+    .await
+    .ok()
+}
+
+/// server-sent events of the selected sections, one `snapshot` event per monitor update
+///
+/// without any filter every section and every tracked cgroup is sent
+#[utoipa::path(
+    context_path = "/api/v1/stream",
+    params(
+        ("section" = Option<Vec<String>>, Query, description = "compute, memory, io, network, system_event or cgroup, repeatable"),
+        ("cgroup_user_path" = Option<Vec<String>>, Query, description = "cgroup user path, repeatable"),
+    ),
+    responses(
+        (status = 200, description = "event stream of snapshots", body = RespSnapshot, content_type = "text/event-stream")
+    )
+)]
+#[get("/?<section>&<cgroup_user_path>")]
+fn stream(
+    section: Vec<StreamSection>,
+    cgroup_user_path: Vec<String>,
+    mut shutdown: Shutdown,
+    _access: ReadAccess,
+) -> EventStream![] {
+    let filter = Arc::new(StreamFilter::new(section, cgroup_user_path));
+    let mut generation = MONITOR.subscribe();
+    generation.borrow_and_update();
+    EventStream! {
+        match read_snapshot(filter.clone()).await {
+            Some(event) => yield event,
+            None => return,
+        }
+        loop {
+            rocket::tokio::select! {
+                changed = generation.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                }
+                _ = &mut shutdown => break,
+            }
+            generation.borrow_and_update();
+            match read_snapshot(filter.clone()).await {
+                Some(event) => yield event,
+                None => break,
+            }
+        }
+    }
+}
+
+pub fn stream_v1_router() -> rocket::fairing::AdHoc {
+    rocket::fairing::AdHoc::on_ignite("JSON", |rocket| async {
+        rocket.mount("/api/v1/stream", routes![stream])
+    })
+}
+
+#[cfg(test)]
+mod tests_stream {
+    use super::*;
+
+    fn system() -> System {
+        let mut system = System::default();
+        for path in ["/a", "b/c"] {
+            system
+                .insert_cgroups_item(CGroup::new("/sys/fs/cgroup", PathBuf::from(path)))
+                .unwrap();
+        }
+        system
+    }
+
+    #[test]
+    fn test_snapshot_default() {
+        let snapshot = StreamFilter::new(vec![], vec![]).snapshot(3, &system());
+        assert_eq!(snapshot.generation, 3);
+        assert!(snapshot.compute.is_some());
+        assert!(snapshot.memory.is_some());
+        assert!(snapshot.io.is_some());
+        assert!(snapshot.network.is_some());
+        assert!(snapshot.system_event.is_some());
+        let mut cgroups: Vec<String> = snapshot.cgroups.unwrap().into_keys().collect();
+        cgroups.sort();
+        assert_eq!(cgroups, vec!["/a", "/b/c"]);
+    }
+
+    #[test]
+    fn test_snapshot_sections() {
+        let filter = StreamFilter::new(vec![StreamSection::Memory, StreamSection::Io], vec![]);
+        let snapshot = filter.snapshot(1, &system());
+        assert!(snapshot.compute.is_none());
+        assert!(snapshot.memory.is_some());
+        assert!(snapshot.io.is_some());
+        assert!(snapshot.network.is_none());
+        assert!(snapshot.system_event.is_none());
+        assert!(snapshot.cgroups.is_none());
+    }
+
+    #[test]
+    fn test_snapshot_cgroups() {
+        // only the given cgroups, keyed as requested, untracked ones left out
+        let filter = StreamFilter::new(vec![], vec!["b/c".to_string(), "/d".to_string()]);
+        let snapshot = filter.snapshot(1, &system());
+        assert!(snapshot.compute.is_none());
+        assert!(snapshot.system_event.is_none());
+        let cgroups = snapshot.cgroups.unwrap();
+        assert_eq!(cgroups.len(), 1);
+        assert_eq!(cgroups["b/c"].user_path(), PathBuf::from("b/c"));
+
+        // the cgroup section wins over the given paths
+        let filter = StreamFilter::new(
+            vec![StreamSection::Compute, StreamSection::Cgroup],
+            vec!["/a".to_string()],
+        );
+        let snapshot = filter.snapshot(1, &system());
+        assert!(snapshot.compute.is_some());
+        assert_eq!(snapshot.cgroups.unwrap().len(), 2);
+    }
+}
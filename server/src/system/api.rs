@@ -13,28 +13,20 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use crate::common::Resp;
+use crate::auth::ReadAccess;
+use crate::common::{ApiResult, Resp};
+use crate::history::{self, RangeQuery, RespRange, Section};
+use crate::projection::Projection;
 use crate::system::response_mod::{RespCompute, RespComputeCpu, RespIo, RespMemory, RespNetwork};
 use crate::system::MONITOR;
-use lib::system::SystemEventData;
-use rocket::http::Status;
-use rocket::serde::json::Json;
+use lib::settings::DataSourceType;
+use lib::system::{Collector, System};
+use rocket::serde::json::{Json, Value};
 use std::ops::Deref;
 
-/// system cpu info
-#[utoipa::path(
-    context_path = "/api/v1/system",
-    responses(
-        (status = 200, description = "system compute api", body = [RespCompute])
-    )
-)]
-#[get("/compute")]
-async fn compute() -> Result<Json<Resp<RespCompute>>, Status> {
-    let reader_lock = MONITOR.get_monitor_reader();
-    let load = reader_lock.read().get_load().clone();
-    let process_stats = reader_lock.read().get_process_stats().clone();
-    let cpu_info = reader_lock
-        .read()
+/// compute section of a system instance
+pub(crate) fn compute_info(system: &System) -> RespCompute {
+    let cpu_info = system
         .get_processors()
         .iter()
         .map({
@@ -48,11 +40,7 @@ async fn compute() -> Result<Json<Resp<RespCompute>>, Status> {
             }
         })
         .collect::<Vec<RespComputeCpu>>();
-    let global_cpu_info = reader_lock
-        .read()
-        .get_processors()
-        .get_global_processor()
-        .clone();
+    let global_cpu_info = system.get_processors().get_global_processor();
     let global_cpu = RespComputeCpu {
         name: global_cpu_info.name().to_string(),
         cpu_usage: global_cpu_info.get_cpu_usage(),
@@ -61,98 +49,210 @@ async fn compute() -> Result<Json<Resp<RespCompute>>, Status> {
         cpu_sched_wait: global_cpu_info.get_sched_wait(),
         cpi_data: global_cpu_info.get_cpi_data().as_ref().cloned(),
     };
-    let pressure = reader_lock.read().get_system_pressure().clone();
-    let cpu_pressure = pressure.map(|x| *x.cpu());
+    let cpu_pressure = system.get_system_pressure().as_ref().map(|x| *x.cpu());
 
-    let stats = reader_lock.read().get_bpf_prog_stats().clone();
-
-    Ok(Json(Resp::new(RespCompute {
-        load,
+    RespCompute {
+        load: system.get_load().clone(),
         cpu: cpu_info,
         global_cpu,
         pressure: cpu_pressure,
-        process_stats,
-        bpf_prog_stats: Some(stats),
-    })))
+        process_stats: system.get_process_stats().clone(),
+        bpf_prog_stats: Some(system.get_bpf_prog_stats().clone()),
+    }
 }
 
-/// system network info
+/// network section of a system instance
+pub(crate) fn network_info(system: &System) -> RespNetwork {
+    RespNetwork {
+        networkcard: system.get_net_traffic().deref().clone(),
+        tcp: system.get_net_info().clone(),
+    }
+}
+
+/// io section of a system instance
+pub(crate) fn io_info(system: &System) -> RespIo {
+    RespIo {
+        disk_io: system.get_disk_io().get_stats().clone(),
+        disk_usage: system.get_disk_io().get_usage().clone(),
+        pressure: system.get_system_pressure().as_ref().map(|x| *x.io()),
+    }
+}
+
+/// memory section of a system instance
+pub(crate) fn memory_info(system: &System) -> RespMemory {
+    RespMemory {
+        system: system.get_memory_info().clone(),
+        pressure: system.get_system_pressure().as_ref().map(|x| *x.memory()),
+        numa: system.get_system_device_nodes().get_nodes().clone(),
+    }
+}
+
+/// system cpu info
 #[utoipa::path(
     context_path = "/api/v1/system",
+    params(
+        RangeQuery,
+        ("fields" = Option<String>, Query, description = "comma separated fields to keep, dotted for nested ones, e.g. `load,cpu.cpu_usage`"),
+        ("cpus" = Option<String>, Query, description = "processors to keep, e.g. `0-15,32`"),
+    ),
     responses(
-        (status = 200, description = "system network api", body = [RespNetwork])
+        (status = 200, description = "system compute api, or its history when a range is given", body = RespRangeCompute),
+        (status = "4XX", description = "invalid query or data source disabled", body = RespErrorBody),
+        (status = "5XX", description = "collector failed or snapshot stale", body = RespErrorBody)
     )
 )]
-#[get("/network")]
-async fn network() -> Result<Json<Resp<RespNetwork>>, Status> {
+#[get("/compute?<fields>&<cpus>&<range..>")]
+async fn compute(
+    fields: Option<String>,
+    cpus: Option<String>,
+    range: RangeQuery,
+    _access: ReadAccess,
+) -> ApiResult<RespRange<Value>> {
+    let projection = Projection::new(fields, None, cpus, None)?;
+    if range.is_range() {
+        let series = history::query(Section::Compute, &[], &range);
+        return Ok(Json(Resp::new(RespRange::Range(
+            projection.apply_series(series)?,
+        ))));
+    }
+
+    MONITOR.check_data_source(DataSourceType::ProcFS)?;
+    MONITOR.check_snapshot(&[Collector::ProcCpu, Collector::Psi, Collector::Pmu])?;
     let reader_lock = MONITOR.get_monitor_reader();
-    let tcp_info = reader_lock.read().get_net_info().clone();
-    let network_card_info = reader_lock.read().get_net_traffic().deref().clone();
+    let compute = compute_info(&reader_lock.read());
+    Ok(Json(Resp::new(RespRange::Latest(
+        projection.apply(compute)?,
+    ))))
+}
+
+/// system network info
+#[utoipa::path(
+    context_path = "/api/v1/system",
+    params(
+        RangeQuery,
+        ("fields" = Option<String>, Query, description = "comma separated fields to keep, dotted for nested ones, e.g. `tcp`"),
+    ),
+    responses(
+        (status = 200, description = "system network api, or its history when a range is given", body = RespRangeNetwork),
+        (status = "4XX", description = "invalid query or data source disabled", body = RespErrorBody),
+        (status = "5XX", description = "collector failed or snapshot stale", body = RespErrorBody)
+    )
+)]
+#[get("/network?<fields>&<range..>")]
+async fn network(
+    fields: Option<String>,
+    range: RangeQuery,
+    _access: ReadAccess,
+) -> ApiResult<RespRange<Value>> {
+    let projection = Projection::new(fields, None, None, None)?;
+    if range.is_range() {
+        let series = history::query(Section::Network, &[], &range);
+        return Ok(Json(Resp::new(RespRange::Range(
+            projection.apply_series(series)?,
+        ))));
+    }
 
-    Ok(Json(Resp::new(RespNetwork {
-        networkcard: network_card_info,
-        tcp: tcp_info,
-    })))
+    MONITOR.check_data_source(DataSourceType::ProcFS)?;
+    MONITOR.check_snapshot(&[Collector::ProcNet])?;
+    let reader_lock = MONITOR.get_monitor_reader();
+    let network = network_info(&reader_lock.read());
+    Ok(Json(Resp::new(RespRange::Latest(
+        projection.apply(network)?,
+    ))))
 }
 
 /// system io info
 #[utoipa::path(
     context_path = "/api/v1/system",
+    params(
+        RangeQuery,
+        ("fields" = Option<String>, Query, description = "comma separated fields to keep, dotted for nested ones, e.g. `pressure`"),
+    ),
     responses(
-        (status = 200, description = "system io api", body = [RespIo])
+        (status = 200, description = "system io api, or its history when a range is given", body = RespRangeIo),
+        (status = "4XX", description = "invalid query or data source disabled", body = RespErrorBody),
+        (status = "5XX", description = "collector failed or snapshot stale", body = RespErrorBody)
     )
 )]
-#[get("/io")]
-async fn io() -> Result<Json<Resp<RespIo>>, Status> {
+#[get("/io?<fields>&<range..>")]
+async fn io(
+    fields: Option<String>,
+    range: RangeQuery,
+    _access: ReadAccess,
+) -> ApiResult<RespRange<Value>> {
+    let projection = Projection::new(fields, None, None, None)?;
+    if range.is_range() {
+        let series = history::query(Section::Io, &[], &range);
+        return Ok(Json(Resp::new(RespRange::Range(
+            projection.apply_series(series)?,
+        ))));
+    }
+
+    MONITOR.check_data_source(DataSourceType::ProcFS)?;
+    MONITOR.check_snapshot(&[Collector::ProcDisk, Collector::Psi])?;
     let reader_lock = MONITOR.get_monitor_reader();
-    let disk_io = reader_lock.read().get_disk_io().get_stats().clone();
-    let disk_usage = reader_lock.read().get_disk_io().get_usage().clone();
-    let pressure = reader_lock.read().get_system_pressure().clone();
-    let io_pressure = pressure.map(|x| *x.io());
-    Ok(Json(Resp::new(RespIo {
-        disk_io,
-        disk_usage,
-        pressure: io_pressure,
-    })))
+    let io = io_info(&reader_lock.read());
+    Ok(Json(Resp::new(RespRange::Latest(projection.apply(io)?))))
 }
 
 /// system memory info
 #[utoipa::path(
     context_path = "api/v1/system",
+    params(
+        RangeQuery,
+        ("fields" = Option<String>, Query, description = "comma separated fields to keep, dotted for nested ones, e.g. `system,numa.mem_free`"),
+        ("numa" = Option<String>, Query, description = "NUMA nodes to keep, e.g. `0-1`"),
+    ),
     responses(
-        (status = 200, description = "system io api", body = [RespMemory])
+        (status = 200, description = "system memory api, or its history when a range is given", body = RespRangeMemory),
+        (status = "4XX", description = "invalid query or data source disabled", body = RespErrorBody),
+        (status = "5XX", description = "collector failed or snapshot stale", body = RespErrorBody)
     )
 )]
-#[get("/memory")]
-async fn memory() -> Result<Json<Resp<RespMemory>>, Status> {
+#[get("/memory?<fields>&<numa>&<range..>")]
+async fn memory(
+    fields: Option<String>,
+    numa: Option<String>,
+    range: RangeQuery,
+    _access: ReadAccess,
+) -> ApiResult<RespRange<Value>> {
+    let projection = Projection::new(fields, None, None, numa)?;
+    if range.is_range() {
+        let series = history::query(Section::Memory, &[], &range);
+        return Ok(Json(Resp::new(RespRange::Range(
+            projection.apply_series(series)?,
+        ))));
+    }
+
+    MONITOR.check_data_source(DataSourceType::ProcFS)?;
+    MONITOR.check_snapshot(&[Collector::ProcMem, Collector::Psi, Collector::Sys])?;
     let reader_lock = MONITOR.get_monitor_reader();
-    let memory_info = reader_lock.read().get_memory_info().clone();
-    let numa_info = reader_lock
-        .read()
-        .get_system_device_nodes()
-        .get_nodes()
-        .clone();
-    let pressure = reader_lock.read().get_system_pressure().clone();
-    let mem_pressure = pressure.map(|x| *x.memory());
-    Ok(Json(Resp::new(RespMemory {
-        system: memory_info,
-        pressure: mem_pressure,
-        numa: numa_info,
-    })))
+    let memory = memory_info(&reader_lock.read());
+    Ok(Json(Resp::new(RespRange::Latest(
+        projection.apply(memory)?,
+    ))))
 }
 
 /// system event info
 #[utoipa::path(
     context_path = "api/v1/system",
+    params(
+        ("fields" = Option<String>, Query, description = "comma separated fields to keep, dotted for nested ones"),
+    ),
     responses(
-        (status = 200, description = "system event api", body = [SystemEventData])
+        (status = 200, description = "system event api", body = [SystemEventData]),
+        (status = "4XX", description = "invalid query or data source disabled", body = RespErrorBody),
+        (status = "5XX", description = "collector failed or snapshot stale", body = RespErrorBody)
     )
 )]
-#[get("/system_event")]
-async fn system_event() -> Result<Json<Resp<SystemEventData>>, Status> {
+#[get("/system_event?<fields>")]
+async fn system_event(fields: Option<String>, _access: ReadAccess) -> ApiResult<Value> {
+    let projection = Projection::new(fields, None, None, None)?;
+    MONITOR.check_data_source(DataSourceType::Ebpf)?;
+    MONITOR.check_snapshot(&[Collector::Ebpf])?;
     let reader_lock = MONITOR.get_monitor_reader();
     let system_event = reader_lock.read().get_system_event().clone();
-    Ok(Json(Resp::new(system_event)))
+    Ok(Json(Resp::new(projection.apply(system_event)?)))
 }
 
 // #[get("/storage/", format = "json")]
@@ -17,11 +17,17 @@ use crossbeam::channel::{unbounded, Receiver, Sender};
 use lib::cgroup::CGroup;
 use lib::settings;
 use once_cell::sync::Lazy;
+use rocket::serde::json::Value;
+use std::path::PathBuf;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum ChangeEvent {
     CGroupEvent(CGroup),
+    CGroupRemoveEvent(PathBuf),
     SettingsEvent(Box<settings::Settings>),
+    /// settings changed through the API, along with the accumulated overrides which are
+    /// persisted to outlive a restart
+    SettingsPersistEvent(Box<settings::Settings>, Box<Value>),
 }
 
 pub static CHANGE_CHANNEL: Lazy<ChangeChannel> = Lazy::new(|| {
@@ -13,8 +13,9 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use lib::settings;
-use lib::system::System;
+use crate::common::ApiError;
+use lib::settings::{self, DataSourceType};
+use lib::system::{Collector, System};
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
 use std::ops::{Deref, DerefMut};
@@ -22,25 +23,55 @@ use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
 
-pub static MONITOR: Lazy<SystemMonitor> = Lazy::new(|| SystemMonitor {
-    instance_one: RwLock::new(SystemInstance {
-        inner: System::new(),
-        nr: 1,
-    }),
-    instance_two: RwLock::new(SystemInstance {
-        inner: System::new(),
-        nr: 2,
-    }),
-    pointer: Arc::new(AtomicBool::new(true)),
-    settings: RwLock::new(settings::Settings::new().unwrap()),
+/// the reader is stale when no update succeeded for this many refresh ticks
+const STALE_TICKS: u64 = 3;
+
+pub static MONITOR: Lazy<SystemMonitor> = Lazy::new(|| {
+    let (generation_sender, generation_receiver) = watch::channel(0);
+    SystemMonitor {
+        instance_one: RwLock::new(SystemInstance {
+            inner: System::new(),
+            nr: 1,
+        }),
+        instance_two: RwLock::new(SystemInstance {
+            inner: System::new(),
+            nr: 2,
+        }),
+        pointer: Arc::new(AtomicBool::new(true)),
+        settings: RwLock::new(settings::Settings::new().unwrap_or_else(|e| {
+            error!(
+                "[monitor] load settings error, fall back to the default file: {}",
+                e
+            );
+            settings::Settings::load_default().unwrap()
+        })),
+        generation_sender,
+        generation_receiver,
+        update_state: RwLock::new(UpdateState {
+            last_success: Instant::now(),
+            last_error: None,
+        }),
+    }
 });
 
+struct UpdateState {
+    /// monitor start until the first update succeeds
+    last_success: Instant,
+    last_error: Option<String>,
+}
+
 pub struct SystemMonitor {
     instance_one: RwLock<SystemInstance>,
     instance_two: RwLock<SystemInstance>,
     pointer: Arc<AtomicBool>,
     settings: RwLock<settings::Settings>,
+    /// bumped each time the reader switches to a freshly updated instance
+    generation_sender: watch::Sender<u64>,
+    generation_receiver: watch::Receiver<u64>,
+    update_state: RwLock<UpdateState>,
 }
 
 impl SystemMonitor {
@@ -58,14 +89,104 @@ impl SystemMonitor {
         }
         &self.instance_two
     }
+    /// the other instance of the double buffer
+    pub fn get_monitor_peer(&self, instance: &RwLock<SystemInstance>) -> &RwLock<SystemInstance> {
+        if std::ptr::eq(instance, &self.instance_one) {
+            return &self.instance_two;
+        }
+        &self.instance_one
+    }
     pub fn change_pointer(&self) {
         let flag = self.pointer.load(Ordering::SeqCst);
         self.pointer.store(!flag, Ordering::SeqCst);
+        let generation = *self.generation_receiver.borrow() + 1;
+        // never fails, the monitor holds a receiver itself
+        let _ = self.generation_sender.send(generation);
+    }
+
+    /// current generation of the reader instance
+    pub fn get_generation(&self) -> u64 {
+        *self.generation_receiver.borrow()
+    }
+
+    /// notified on every reader switch; a slow subscriber only sees the latest generation
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.generation_receiver.clone()
     }
 
     pub fn get_settings(&self) -> &RwLock<settings::Settings> {
         &self.settings
     }
+
+    pub fn update_succeeded(&self) {
+        let mut state = self.update_state.write();
+        state.last_success = Instant::now();
+        state.last_error = None;
+    }
+
+    /// the update did not complete, e.g. it panicked outside of any collector;
+    /// failed collectors are recorded in the collector stats instead
+    pub fn update_failed(&self, error: String) {
+        self.update_state.write().last_error = Some(error);
+    }
+
+    /// time since the last successful update, and the error of the last update if it failed
+    pub fn get_update_state(&self) -> (Duration, Option<String>) {
+        let state = self.update_state.read();
+        (state.last_success.elapsed(), state.last_error.clone())
+    }
+
+    /// whether the monitor keeps the reader up to date: the last update completed
+    /// and it did so within a few refresh ticks
+    pub fn check_update(&self) -> Result<(), ApiError> {
+        let state = self.update_state.read();
+        if let Some(e) = &state.last_error {
+            return Err(ApiError::CollectorFailed(e.clone()));
+        }
+        let age = state.last_success.elapsed().as_secs();
+        if age > STALE_TICKS * self.settings.read().get_refresh_tick() {
+            return Err(ApiError::SnapshotStale(age));
+        }
+        Ok(())
+    }
+
+    /// whether the reader instance can be served for a section: the update is
+    /// current and none of the enabled `collectors` behind the section failed its
+    /// last run, failures elsewhere don't matter
+    pub fn check_snapshot(&self, collectors: &[Collector]) -> Result<(), ApiError> {
+        self.check_update()?;
+        // the settings are locked only after the reader is released, the update
+        // locks them while holding the writer, which becomes the next reader
+        let failed: Vec<(Collector, String)> = {
+            let reader = self.get_monitor_reader().read();
+            let stats = reader.get_collector_stats();
+            collectors
+                .iter()
+                .filter_map(|c| {
+                    let e = stats.get(*c)?.last_error.clone()?;
+                    Some((*c, e))
+                })
+                .collect()
+        };
+        let settings = self.settings.read();
+        match failed.into_iter().find(|(c, _)| c.is_enable(&settings)) {
+            Some((c, e)) => Err(ApiError::CollectorFailed(format!("{:?}: {}", c, e))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn check_data_source(&self, t: DataSourceType) -> Result<(), ApiError> {
+        let settings = self.settings.read();
+        let enable = settings.is_enable()
+            && settings
+                .get_data_source(t)
+                .is_some_and(|data_source| data_source.is_enable());
+        if enable {
+            Ok(())
+        } else {
+            Err(ApiError::DataSourceDisabled(t))
+        }
+    }
 }
 
 pub struct SystemInstance {
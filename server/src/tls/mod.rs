@@ -0,0 +1,129 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::cli::Cli;
+use crate::watcher::FileWatcher;
+use rocket::figment::Figment;
+use rocket::tokio::sync::oneshot;
+use rocket::tokio::task::spawn_blocking;
+use std::fs;
+use std::future;
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::Duration;
+
+const WAIT_TIMEOUT_MS: i32 = 1000;
+
+/// certificate files of the TCP listener, mutual TLS when a client CA is given
+#[derive(Clone, Debug)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub client_ca: Option<PathBuf>,
+    /// reject clients without a certificate signed by the client CA
+    pub mandatory: bool,
+}
+
+/// content of the certificate files, None for a file which fails to read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot(Vec<Option<Vec<u8>>>);
+
+impl TlsFiles {
+    pub fn from_cli(cli: &Cli) -> Option<TlsFiles> {
+        match (&cli.tls_cert, &cli.tls_key) {
+            (Some(cert), Some(key)) => Some(TlsFiles {
+                cert: cert.clone(),
+                key: key.clone(),
+                client_ca: cli.tls_client_ca.clone(),
+                mandatory: !cli.tls_client_auth_optional,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn merge(&self, figment: Figment) -> Figment {
+        let figment = figment
+            .merge(("tls.certs", &self.cert))
+            .merge(("tls.key", &self.key));
+        match &self.client_ca {
+            Some(ca) => figment
+                .merge(("tls.mutual.ca_certs", ca))
+                .merge(("tls.mutual.mandatory", self.mandatory)),
+            None => figment,
+        }
+    }
+
+    fn paths(&self) -> Vec<&PathBuf> {
+        [Some(&self.cert), Some(&self.key), self.client_ca.as_ref()]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot(
+            self.paths()
+                .into_iter()
+                .map(|path| fs::read(path).ok())
+                .collect(),
+        )
+    }
+
+    /// wait until any of the files differs from the snapshot, e.g. on a
+    /// certificate rotation by a secret update
+    pub async fn changed(&self, snapshot: &Snapshot) {
+        let (sender, receiver) = oneshot::channel();
+        let files = self.clone();
+        let snapshot = snapshot.clone();
+        spawn_blocking(move || files.watch(&snapshot, sender));
+        if receiver.await.is_err() {
+            // the watch stopped without a change
+            future::pending::<()>().await;
+        }
+    }
+
+    /// notify `sender` once the files change, gives up when nobody waits any more
+    fn watch(&self, snapshot: &Snapshot, sender: oneshot::Sender<()>) {
+        let mut watcher: Option<FileWatcher> = None;
+        while !sender.is_closed() {
+            match watcher.as_mut() {
+                // compared right after the watch is in place, the files may
+                // have changed before
+                None => match FileWatcher::new(&self.paths()) {
+                    Ok(w) => watcher = Some(w),
+                    Err(e) => {
+                        warn!("[tls] watch certificate files error: {}", e);
+                        sleep(Duration::from_millis(WAIT_TIMEOUT_MS as u64));
+                        continue;
+                    }
+                },
+                Some(w) => match w.wait_changed(WAIT_TIMEOUT_MS) {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(e) => {
+                        warn!("[tls] read certificate file events error: {}", e);
+                        watcher = None;
+                        continue;
+                    }
+                },
+            }
+            if self.snapshot() != *snapshot {
+                info!("[tls] certificate files changed");
+                let _ = sender.send(());
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,377 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Rocket 0.5 binds its own TCP/TLS listener and keeps custom listeners
+//! crate private, so hyper accepts the connections on the socket and hands
+//! every request to rocket's local dispatcher, the one public entry to the
+//! router. Requests go through the same fairings, guards and data limits as
+//! on TCP.
+
+use nix::unistd::{chown, Gid, Uid};
+use rocket::data::Limits;
+use rocket::http::hyper::{
+    self,
+    body::{Bytes, HttpBody},
+    server::conn::Http,
+    service::service_fn,
+    Body,
+};
+use rocket::http::uri::Origin;
+use rocket::http::{Header, Method};
+use rocket::local::asynchronous::Client;
+use rocket::tokio::io::AsyncReadExt;
+use rocket::tokio::net::UnixListener;
+use rocket::{Build, Rocket};
+use std::convert::Infallible;
+use std::fs::{self, DirBuilder, Permissions};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+
+const READ_BUFFER_SIZE: usize = 8192;
+
+/// unix domain socket listener, serving the same routes as the TCP one
+pub struct UnixSocket {
+    pub path: PathBuf,
+    /// file mode of the socket, e.g. 0o660
+    pub mode: u32,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+fn error_response(status: u16, message: String) -> hyper::Response<Body> {
+    hyper::Response::builder()
+        .status(status)
+        .body(Body::from(message))
+        .unwrap_or_default()
+}
+
+fn bad_request(message: String) -> hyper::Response<Body> {
+    error_response(400, message)
+}
+
+/// largest request body accepted, the API only takes JSON bodies
+fn body_limit(client: &Client) -> u64 {
+    client
+        .rocket()
+        .config()
+        .limits
+        .get("json")
+        .unwrap_or(Limits::JSON)
+        .as_u64()
+}
+
+/// read the request body, refusing it as soon as it grows over `limit`
+async fn read_body(mut body: Body, limit: u64) -> Result<Vec<u8>, hyper::Response<Body>> {
+    let too_large = || error_response(413, format!("request body exceeds {} bytes", limit));
+    if body.size_hint().lower() > limit {
+        return Err(too_large());
+    }
+    let mut buf = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| bad_request(format!("read body error: {}", e)))?;
+        if (buf.len() + chunk.len()) as u64 > limit {
+            return Err(too_large());
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(buf)
+}
+
+/// dispatch a request to the local client, the response body is streamed
+/// so that event streams work as on TCP
+async fn dispatch(
+    client: &'static Client,
+    req: hyper::Request<Body>,
+) -> Result<hyper::Response<Body>, Infallible> {
+    let method = match Method::from_str(req.method().as_str()) {
+        Ok(method) => method,
+        Err(_) => return Ok(bad_request(format!("unsupported method {}", req.method()))),
+    };
+    let uri = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "/".to_string());
+    let uri = match Origin::parse_owned(uri) {
+        Ok(uri) => uri,
+        Err(e) => return Ok(bad_request(format!("invalid uri: {}", e))),
+    };
+
+    let mut local_req = client.req(method, uri);
+    for (name, value) in req.headers() {
+        if let Ok(value) = value.to_str() {
+            local_req.add_header(Header::new(name.as_str().to_string(), value.to_string()));
+        }
+    }
+    match read_body(req.into_body(), body_limit(client)).await {
+        Ok(body) => local_req.set_body(body),
+        Err(resp) => return Ok(resp),
+    }
+
+    let mut local_resp = local_req.dispatch().await;
+    let mut builder = hyper::Response::builder().status(local_resp.status().code);
+    for header in local_resp.headers().iter() {
+        builder = builder.header(header.name().as_str(), header.value());
+    }
+
+    let (mut sender, body) = Body::channel();
+    rocket::tokio::spawn(async move {
+        let mut buf = vec![0u8; READ_BUFFER_SIZE];
+        loop {
+            match local_resp.read(&mut buf).await {
+                Ok(0) => break,
+                Ok(n) => {
+                    // the peer is gone, e.g. an event stream is closed
+                    if sender
+                        .send_data(Bytes::copy_from_slice(&buf[..n]))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    warn!("[uds] read response error: {}", e);
+                    sender.abort();
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(builder
+        .body(body)
+        .unwrap_or_else(|e| bad_request(format!("invalid response: {}", e))))
+}
+
+/// bind the socket in a directory only we can enter, then move it to its path
+/// once its mode and owner are set, so that nobody can connect in between
+fn bind(socket: &UnixSocket) -> io::Result<UnixListener> {
+    if let Ok(meta) = fs::symlink_metadata(&socket.path) {
+        // a socket left by a previous run is replaced by the rename
+        if !meta.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", socket.path.display()),
+            ));
+        }
+    }
+    let file_name = socket.path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a file path", socket.path.display()),
+        )
+    })?;
+    let parent = match socket.path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let private = parent.join(format!(
+        ".{}.{}",
+        file_name.to_string_lossy(),
+        process::id()
+    ));
+    if fs::symlink_metadata(&private).is_ok() {
+        fs::remove_dir_all(&private)?;
+    }
+    DirBuilder::new().mode(0o700).create(&private)?;
+    let result = bind_staged(socket, &private.join(file_name));
+    if let Err(e) = fs::remove_dir_all(&private) {
+        warn!("[uds] remove {} error: {}", private.display(), e);
+    }
+    result
+}
+
+fn bind_staged(socket: &UnixSocket, staged: &Path) -> io::Result<UnixListener> {
+    let listener = UnixListener::bind(staged)?;
+    fs::set_permissions(staged, Permissions::from_mode(socket.mode))?;
+    if socket.uid.is_some() || socket.gid.is_some() {
+        chown(
+            staged,
+            socket.uid.map(Uid::from_raw),
+            socket.gid.map(Gid::from_raw),
+        )
+        .map_err(|e| io::Error::from_raw_os_error(e as i32))?;
+    }
+    fs::rename(staged, &socket.path)?;
+    Ok(listener)
+}
+
+/// serve the rocket routes on the unix domain socket until an accept error
+pub async fn serve(rocket: Rocket<Build>, socket: UnixSocket) -> io::Result<()> {
+    let client = Client::untracked(rocket)
+        .await
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    // responses are streamed by spawned tasks, which borrow the client; it is
+    // created once and lives as long as the server
+    let client: &'static Client = Box::leak(Box::new(client));
+
+    let listener = bind(&socket)?;
+    info!("[uds] listening on {}", socket.path.display());
+    loop {
+        let (stream, _) = listener.accept().await?;
+        rocket::tokio::spawn(async move {
+            if let Err(e) = Http::new()
+                .http1_only(true)
+                .serve_connection(stream, service_fn(move |req| dispatch(client, req)))
+                .await
+            {
+                warn!("[uds] serve connection error: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests_uds {
+    use super::*;
+    use rocket::tokio::io::AsyncWriteExt;
+    use rocket::tokio::net::UnixStream;
+    use rocket::tokio::time::{sleep, Duration};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("malachite-uds-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn unix_socket(path: PathBuf) -> UnixSocket {
+        UnixSocket {
+            path,
+            mode: 0o600,
+            uid: None,
+            gid: None,
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_bind() {
+        let dir = temp_dir("bind");
+        let socket = unix_socket(dir.join("malachite.sock"));
+
+        let listener = bind(&socket).unwrap();
+        let meta = fs::symlink_metadata(&socket.path).unwrap();
+        assert!(meta.file_type().is_socket());
+        assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+        // only the socket is left in the directory
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // a stale socket is replaced
+        drop(listener);
+        let _listener = bind(&socket).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // anything else is kept
+        let file = unix_socket(dir.join("file"));
+        fs::write(&file.path, "data").unwrap();
+        assert!(bind(&file).is_err());
+        assert_eq!(fs::read_to_string(&file.path).unwrap(), "data");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[rocket::async_test]
+    async fn test_read_body() {
+        let body = read_body(Body::from("0123456789"), 16).await.unwrap();
+        assert_eq!(body, b"0123456789");
+
+        // refused up front by its length
+        let resp = read_body(Body::from("0123456789"), 4).await.unwrap_err();
+        assert_eq!(resp.status(), 413);
+
+        // or while streaming, when the length is unknown
+        let (mut sender, body) = Body::channel();
+        rocket::tokio::spawn(async move {
+            for _ in 0..4 {
+                if sender.send_data(Bytes::from("0123")).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let resp = read_body(body, 10).await.unwrap_err();
+        assert_eq!(resp.status(), 413);
+    }
+
+    #[rocket::get("/echo?<value>")]
+    fn echo(value: &str) -> String {
+        value.to_string()
+    }
+
+    #[rocket::post("/echo", data = "<body>")]
+    fn echo_body(body: rocket::serde::json::Json<String>) -> String {
+        body.into_inner()
+    }
+
+    async fn request(path: &Path, raw: &str) -> String {
+        let mut stream = UnixStream::connect(path).await.unwrap();
+        stream.write_all(raw.as_bytes()).await.unwrap();
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).await.unwrap();
+        resp
+    }
+
+    #[rocket::async_test]
+    async fn test_serve() {
+        let dir = temp_dir("serve");
+        let path = dir.join("malachite.sock");
+        let figment = rocket::Config::figment().merge(("limits.json", 64));
+        let rocket = rocket::custom(figment).mount("/", rocket::routes![echo, echo_body]);
+        rocket::tokio::spawn(serve(rocket, unix_socket(path.clone())));
+        // the socket appears once it is ready
+        for _ in 0..100 {
+            if path.exists() {
+                break;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        let resp = request(
+            &path,
+            "GET /echo?value=hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        assert!(resp.starts_with("HTTP/1.1 200 OK"), "{}", resp);
+        assert!(resp.contains("\r\nhello\r\n"), "{}", resp);
+
+        let resp = request(
+            &path,
+            "POST /echo HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+             Content-Length: 7\r\nConnection: close\r\n\r\n\"hello\"",
+        )
+        .await;
+        assert!(resp.starts_with("HTTP/1.1 200 OK"), "{}", resp);
+        assert!(resp.contains("\r\nhello\r\n"), "{}", resp);
+
+        let body = format!("\"{}\"", "x".repeat(100));
+        let resp = request(
+            &path,
+            &format!(
+                "POST /echo HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            ),
+        )
+        .await;
+        assert!(resp.starts_with("HTTP/1.1 413"), "{}", resp);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use std::collections::BTreeSet;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// Watches the directories of a few files with inotify. Directories rather than
+/// files are watched, a ConfigMap or Secret volume replaces its files by swapping
+/// a symlink next to them; callers compare contents to tell what changed.
+pub struct FileWatcher {
+    inotify: Inotify,
+}
+
+impl FileWatcher {
+    pub fn new<P: AsRef<Path>>(files: &[P]) -> Result<FileWatcher, String> {
+        let dirs: BTreeSet<PathBuf> = files
+            .iter()
+            .map(|file| match file.as_ref().parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ => PathBuf::from("."),
+            })
+            .collect();
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC | InitFlags::IN_NONBLOCK)
+            .map_err(|e| format!("inotify init error: {}", e))?;
+        let flags = AddWatchFlags::IN_CLOSE_WRITE
+            | AddWatchFlags::IN_CREATE
+            | AddWatchFlags::IN_DELETE
+            | AddWatchFlags::IN_MOVED_FROM
+            | AddWatchFlags::IN_MOVED_TO
+            | AddWatchFlags::IN_ATTRIB
+            | AddWatchFlags::IN_ONLYDIR;
+        for dir in dirs.iter() {
+            inotify
+                .add_watch(dir, flags)
+                .map_err(|e| format!("add watch {} error: {}", dir.display(), e))?;
+        }
+        Ok(FileWatcher { inotify })
+    }
+
+    /// wait up to timeout_ms for anything to change in the directories, false
+    /// means timeout; fails once a directory is gone, the watcher is to be
+    /// created again
+    pub fn wait_changed(&mut self, timeout_ms: i32) -> Result<bool, String> {
+        let mut fds = [PollFd::new(self.inotify.as_raw_fd(), PollFlags::POLLIN)];
+        match poll(&mut fds, timeout_ms) {
+            Ok(0) => return Ok(false),
+            Ok(_) => {}
+            Err(Errno::EINTR) => return Ok(false),
+            Err(e) => return Err(e.to_string()),
+        }
+
+        let events = match self.inotify.read_events() {
+            Ok(events) => events,
+            Err(Errno::EAGAIN) => return Ok(false),
+            Err(e) => return Err(e.to_string()),
+        };
+        if events
+            .iter()
+            .any(|event| event.mask.contains(AddWatchFlags::IN_IGNORED))
+        {
+            return Err("watched directory removed".to_string());
+        }
+        Ok(!events.is_empty())
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        let _ = nix::unistd::close(self.inotify.as_raw_fd());
+    }
+}
+
+#[cfg(test)]
+mod tests_files {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_file_watcher() {
+        let dir = env::temp_dir().join(format!("malachite_files_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("..v1")).unwrap();
+        fs::write(dir.join("..v1/tls.crt"), "v1").unwrap();
+        symlink("..v1", dir.join("..data")).unwrap();
+        symlink("..data/tls.crt", dir.join("tls.crt")).unwrap();
+
+        let mut watcher = FileWatcher::new(&[dir.join("tls.crt")]).unwrap();
+        assert!(!watcher.wait_changed(0).unwrap());
+
+        // a Secret update swaps the `..data` symlink
+        fs::create_dir_all(dir.join("..v2")).unwrap();
+        fs::write(dir.join("..v2/tls.crt"), "v2").unwrap();
+        symlink("..v2", dir.join("..data_tmp")).unwrap();
+        fs::rename(dir.join("..data_tmp"), dir.join("..data")).unwrap();
+        assert!(watcher.wait_changed(1000).unwrap());
+        assert_eq!(fs::read_to_string(dir.join("tls.crt")).unwrap(), "v2");
+
+        // the deletions may come before the directory is gone
+        fs::remove_dir_all(&dir).unwrap();
+        let gone = (0..10).any(|_| watcher.wait_changed(1000).is_err());
+        assert!(gone);
+    }
+}
@@ -0,0 +1,109 @@
+/*
+Copyright 2023 The Malachite Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+mod files;
+
+pub use files::*;
+
+use crate::system::{ChangeEvent, CHANGE_CHANNEL, MONITOR};
+use lib::cgroup::{self, CGroupWatchEvent, CGroupWatcher};
+use lib::common;
+use std::path::Path;
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+const WAIT_TIMEOUT_MS: i32 = 1000;
+
+fn handle_event(event: CGroupWatchEvent, mount_point: &Path) {
+    let settings = MONITOR.get_settings().read().clone();
+    let msg = match event {
+        CGroupWatchEvent::Created(user_path) => {
+            if !cgroup::match_user_path(settings.get_cgroup_discovery(), &user_path) {
+                return;
+            }
+            info!("[watcher] cgroup created, path= {}", user_path.display());
+            ChangeEvent::CGroupEvent(cgroup::CGroup::new(
+                &mount_point.to_string_lossy(),
+                user_path,
+            ))
+        }
+        CGroupWatchEvent::Removed(user_path) => {
+            let tracked = MONITOR
+                .get_monitor_reader()
+                .read()
+                .get_cgroups(user_path.clone())
+                .is_some();
+            if !tracked && !cgroup::match_user_path(settings.get_cgroup_discovery(), &user_path) {
+                return;
+            }
+            info!("[watcher] cgroup removed, path= {}", user_path.display());
+            ChangeEvent::CGroupRemoveEvent(user_path)
+        }
+    };
+
+    if let Err(e) = CHANGE_CHANNEL.get_channel_sender().send(msg) {
+        warn!("[watcher] send change event error: {}", e);
+    }
+}
+
+fn watch() {
+    let mount_point = common::cgroup_mount_point();
+    let mut watcher: Option<CGroupWatcher> = None;
+    loop {
+        if !MONITOR.get_settings().read().is_cgroup_watch_enable() {
+            if watcher.take().is_some() {
+                info!("[watcher] cgroup watcher stopped");
+            }
+            sleep(Duration::from_millis(WAIT_TIMEOUT_MS as u64));
+            continue;
+        }
+
+        if watcher.is_none() {
+            let cgroup_type = common::MODULE_LIST.cgroup_type.actual_status();
+            match CGroupWatcher::new(&mount_point, cgroup_type) {
+                Ok(w) => {
+                    info!(
+                        "[watcher] cgroup watcher started, {} directories watched",
+                        w.nr_watches()
+                    );
+                    watcher = Some(w);
+                }
+                Err(e) => {
+                    warn!("[watcher] start cgroup watcher error: {}", e);
+                    sleep(Duration::from_millis(WAIT_TIMEOUT_MS as u64));
+                    continue;
+                }
+            }
+        }
+
+        if let Some(w) = watcher.as_mut() {
+            match w.wait_events(WAIT_TIMEOUT_MS) {
+                Ok(events) => events
+                    .into_iter()
+                    .for_each(|event| handle_event(event, &mount_point)),
+                Err(e) => {
+                    warn!("[watcher] read cgroup events error: {}", e);
+                    watcher = None;
+                }
+            }
+        }
+    }
+}
+
+pub fn daemon() {
+    let _handler = thread::Builder::new()
+        .name("cgroup-watcher".into())
+        .spawn(watch);
+}